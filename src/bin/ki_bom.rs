@@ -0,0 +1,61 @@
+use kiops::{
+    parse_file::{parse_stdin, write_stdout, Result},
+    sexpr::{
+        analysis::symbols,
+        bom::{flat_bom, grouped_bom, summary_instances, to_csv, GroupBy},
+        parser::parse_s_expr,
+        project::Project,
+        simplifier::Simplifier,
+    },
+};
+use std::env;
+
+fn main() -> Result<()> {
+    let usage = "usage: ki_bom flat|grouped [-v] [-j] [--explain] [project.kicad_pro]";
+    let mut args = env::args();
+    let _exec = args.next().ok_or(usage)?;
+    let command = args.next().ok_or(usage)?;
+
+    let mut group_by = GroupBy::Mpn;
+    let mut want_json = false;
+    let mut explain = false;
+    let mut project = None;
+    for arg in args {
+        match arg.as_str() {
+            "-v" => group_by = GroupBy::ValueFootprint,
+            "-j" => want_json = true,
+            "--explain" => explain = true,
+            _ => project = Some(arg),
+        }
+    }
+
+    let instances = match project {
+        Some(path) => Project::load(path)?.symbols(),
+        None => {
+            let input = parse_stdin(parse_s_expr)?;
+            let summary = if explain {
+                symbols().explain(&input)?
+            } else {
+                symbols().simplify_or(&input, "expected a schematic")?
+            };
+            summary_instances(&summary)
+        }
+    };
+
+    let (bom, grouped) = match command.as_str() {
+        "flat" => (flat_bom(&instances), false),
+        "grouped" => (grouped_bom(&instances, group_by), true),
+        _ => Err(usage)?,
+    };
+
+    for warning in &bom.warnings {
+        eprintln!("warning: {warning}");
+    }
+
+    if want_json {
+        write_stdout(&serde_json::to_value(&bom.lines)?)?;
+    } else {
+        write_stdout(&to_csv(&bom, grouped))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use kiops::{
+    parse_file::{parse_file, write_stdout, Result},
+    sexpr::{diff::diff, parser::parse_s_expr},
+};
+use std::env;
+
+fn main() -> Result<()> {
+    let usage = "usage: ki_diff [--json] old_file new_file";
+    let mut json = false;
+    let mut paths = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ => paths.push(arg),
+        }
+    }
+    let [old, new] = paths.as_slice() else {
+        Err(usage)?
+    };
+
+    let old = parse_file(old, parse_s_expr)?;
+    let new = parse_file(new, parse_s_expr)?;
+    let changes = diff(&old, &new);
+
+    if json {
+        write_stdout(&serde_json::to_value(&changes)?)?;
+    } else {
+        for change in &changes {
+            println!("{change}");
+        }
+    }
+    Ok(())
+}
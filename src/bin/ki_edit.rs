@@ -1,7 +1,8 @@
 use kiops::{
     parse_file::{parse_stdin, read_json, write_stdout, Result},
     sexpr::{
-        edit::{editor, extract_props},
+        edit::{editor, extract_props, unmatched, KeyBy},
+        format::KicadFormat,
         parser::parse_s_expr,
         simplifier::Simplifier,
     },
@@ -9,20 +10,35 @@ use kiops::{
 use std::env;
 
 fn main() -> Result<()> {
-    let usage = "usage: ki_edit symbol_props_file";
-    let mut args = env::args();
-    let _exec = args.next().ok_or(usage)?;
-    let fname = args.next().ok_or(usage)?;
+    let usage = "usage: ki_edit [--explain] [--lib-id|--propagate] symbol_props_file";
+    let mut explain = false;
+    let mut key_by = KeyBy::Reference;
+    let mut fname = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--explain" => explain = true,
+            "--lib-id" => key_by = KeyBy::LibId,
+            "--propagate" => key_by = KeyBy::LibIdAndInstances,
+            _ => fname = Some(arg),
+        }
+    }
+    let fname = fname.ok_or(usage)?;
 
     let json = read_json(&fname)?;
-    let props = extract_props(json).ok_or("invalid symbol properties")?;
-    let simplifier = editor(props);
+    let props = extract_props(json, key_by).ok_or("invalid symbol properties")?;
+    let simplifier = editor(props.clone(), key_by);
 
     let input = parse_stdin(parse_s_expr)?;
-    let output = simplifier
-        .simplify(&input)
-        .ok_or("unrecognised input file contents")?;
+    let output = if explain {
+        simplifier.explain(&input)?
+    } else {
+        simplifier.simplify_or(&input, "unrecognised input file contents")?
+    };
 
-    write_stdout(&output)?;
+    for (reference, property) in unmatched(&props, &input, key_by) {
+        eprintln!("warning: {reference} {property} matched nothing");
+    }
+
+    write_stdout(&KicadFormat(&output))?;
     Ok(())
 }
@@ -0,0 +1,90 @@
+use kiops::{
+    parse_file::{write_stdout, Result},
+    sexpr::{
+        fplib::{
+            duplicates, merge, path_of, read_library, rename, split, write_footprint,
+            write_library, Footprint,
+        },
+        symlib::Policy,
+    },
+};
+use serde_json::json;
+use std::{collections::BTreeSet, env, fs, path::Path};
+
+fn main() -> Result<()> {
+    let usage = "usage: ki_fplib merge [--first|--last|--fail|--keep-both] output.pretty input.pretty... | dedupe input.pretty... | rename library.pretty from to | split input.pretty output.pretty name...";
+    let mut args = env::args().skip(1);
+    let command = args.next().ok_or(usage)?;
+    let mut policy = Policy::LastWins;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--first" => policy = Policy::FirstWins,
+            "--last" => policy = Policy::LastWins,
+            "--fail" => policy = Policy::FailOnConflict,
+            "--keep-both" => policy = Policy::KeepBoth,
+            _ => paths.push(arg),
+        }
+    }
+
+    match (command.as_str(), paths.as_slice()) {
+        ("merge", [output, inputs @ ..]) if !inputs.is_empty() => {
+            let mut libraries = Vec::new();
+            for input in inputs {
+                libraries.push((input.clone(), read_library(Path::new(input))?));
+            }
+            let merged = merge(&libraries, policy)?;
+            for conflict in &merged.conflicts {
+                eprintln!("warning: {conflict}");
+            }
+            write_library(Path::new(output), &merged.footprints)?;
+        }
+        ("dedupe", inputs) if !inputs.is_empty() => {
+            let mut libraries = Vec::new();
+            for input in inputs {
+                libraries.push((input, read_library(Path::new(input))?));
+            }
+            let footprints: Vec<(&String, &Footprint)> = libraries
+                .iter()
+                .flat_map(|(dir, footprints)| footprints.iter().map(move |f| (*dir, f)))
+                .collect();
+            let all: Vec<&Footprint> = footprints.iter().map(|(_, f)| *f).collect();
+            let groups: Vec<Vec<String>> = duplicates(&all)
+                .into_iter()
+                .map(|group| {
+                    group
+                        .into_iter()
+                        .map(|i| path_of(Path::new(footprints[i].0), &footprints[i].1.name))
+                        .collect()
+                })
+                .collect();
+            write_stdout(&json!(groups))?;
+        }
+        ("rename", [library, from, to]) => {
+            let dir = Path::new(library);
+            if Path::new(&path_of(dir, to)).exists() {
+                Err(format!("footprint {to:?} already exists in {library}"))?
+            }
+            let footprint = read_library(dir)?
+                .into_iter()
+                .find(|f| f.name == *from)
+                .ok_or_else(|| format!("footprint {from:?} not found in {library}"))?;
+            let renamed = Footprint {
+                name: to.clone(),
+                expr: rename(footprint.expr, from, to),
+            };
+            write_footprint(dir, &renamed)?;
+            fs::remove_file(path_of(dir, from))?;
+        }
+        ("split", [input, output, names @ ..]) if !names.is_empty() => {
+            let names: BTreeSet<String> = names.iter().cloned().collect();
+            let (moved, _) = split(read_library(Path::new(input))?, &names)?;
+            write_library(Path::new(output), &moved)?;
+            for footprint in &moved {
+                fs::remove_file(path_of(Path::new(input), &footprint.name))?;
+            }
+        }
+        _ => Err(usage)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,13 @@
+use kiops::{
+    parse_file::{write_stdout, Result},
+    sexpr::{format::KicadFormat, json::json_to_expr},
+};
+use serde_json::Value;
+use std::io::stdin;
+
+fn main() -> Result<()> {
+    let json: Value = serde_json::from_reader(stdin())?;
+    let output = json_to_expr(&json).ok_or("input is not a tagged JSON s-expression")?;
+    write_stdout(&KicadFormat(&output))?;
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use kiops::{
+    parse_file::{parse_file, parse_stdin, write_stdout, Result},
+    sexpr::{
+        libtable::{project_vars, LibEntry, LibTable, TableKind},
+        parser::parse_s_expr,
+    },
+};
+use serde_json::json;
+use std::{env, path::Path};
+
+fn main() -> Result<()> {
+    let usage = "usage: ki_libtable new sym|fp | list [project_dir] | add name uri [descr] | remove name | repoint name uri | merge table_file";
+    let mut args = env::args();
+    let _exec = args.next().ok_or(usage)?;
+    let command = args.next().ok_or(usage)?;
+    let mut arg = || args.next().ok_or(usage);
+
+    if command == "new" {
+        let kind = match arg()?.as_str() {
+            "sym" => TableKind::Symbol,
+            "fp" => TableKind::Footprint,
+            _ => Err(usage)?,
+        };
+        write_stdout(&LibTable::new(kind))?;
+        return Ok(());
+    }
+
+    let input = parse_stdin(parse_s_expr)?;
+    let mut table =
+        LibTable::from_expr(&input).ok_or_else(|| input.error("expected a library table"))?;
+    for name in table.duplicates() {
+        eprintln!("warning: library {name} appears more than once");
+    }
+
+    match command.as_str() {
+        "list" => {
+            let project_dir = args.next();
+            let libs: Vec<_> = table
+                .libs
+                .iter()
+                .map(|lib| {
+                    let mut value = json!(lib);
+                    if let Some(dir) = &project_dir {
+                        value["path"] = lib.expanded_uri(project_vars(Path::new(dir))).into();
+                    }
+                    value
+                })
+                .collect();
+            write_stdout(&json!(libs))?;
+            return Ok(());
+        }
+        "add" => {
+            let mut entry = LibEntry::new(&arg()?, &arg()?);
+            entry.descr = args.next().unwrap_or_default();
+            table.add(entry)?
+        }
+        "remove" => {
+            table.remove(&arg()?)?;
+        }
+        "repoint" => table.repoint(&arg()?, &arg()?)?,
+        "merge" => {
+            let other = parse_file(&arg()?, parse_s_expr)?;
+            let other = LibTable::from_expr(&other)
+                .ok_or_else(|| other.error("expected a library table"))?;
+            if other.kind != table.kind {
+                Err("cannot merge symbol and footprint library tables")?
+            }
+            for lib in other.libs {
+                table.set(lib);
+            }
+        }
+        _ => Err(usage)?,
+    }
+
+    write_stdout(&table)?;
+    Ok(())
+}
@@ -1,19 +1,48 @@
 use kiops::{
     parse_file::{parse_file, parse_stdin, write_stdout, Result},
-    sexpr::{parser::parse_s_expr, symlib::merge},
+    sexpr::{
+        format::KicadFormat,
+        parser::parse_s_expr,
+        symlib::{merge, Policy},
+    },
 };
 use std::env;
 
 fn main() -> Result<()> {
-    let usage = "usage: ki_merge input";
-    let mut args = env::args();
-    let _exec = args.next().ok_or(usage)?;
-    let path = args.next().ok_or(usage)?;
+    let usage = "usage: ki_merge [--first|--last|--fail|--keep-both] input...";
+    let mut policy = Policy::LastWins;
+    let mut paths = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--first" => policy = Policy::FirstWins,
+            "--last" => policy = Policy::LastWins,
+            "--fail" => policy = Policy::FailOnConflict,
+            "--keep-both" => policy = Policy::KeepBoth,
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        Err(usage)?
+    }
 
-    let input1 = parse_stdin(parse_s_expr)?;
-    let input2 = parse_file(&path, parse_s_expr)?;
-    let output = merge(input1, input2).ok_or("library version missmatch")?;
+    let mut inputs = Vec::new();
+    for path in paths {
+        if path == "-" {
+            inputs.push(("<stdin>".to_owned(), parse_stdin(parse_s_expr)?));
+        } else {
+            let input = parse_file(&path, parse_s_expr)?;
+            inputs.push((path, input));
+        }
+    }
+    let merged = merge(&inputs, policy)?;
 
-    write_stdout(&output)?;
+    for conflict in &merged.conflicts {
+        eprintln!("warning: {conflict}");
+    }
+    for (child, parent) in &merged.orphans {
+        eprintln!("warning: symbol {child:?} extends {parent:?} which is missing");
+    }
+
+    write_stdout(&KicadFormat(&merged.library))?;
     Ok(())
 }
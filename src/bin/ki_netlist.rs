@@ -0,0 +1,30 @@
+use kiops::{
+    parse_file::{parse_stdin, write_stdout, Result},
+    sexpr::{
+        format::KicadFormat,
+        netlist::{netlist_expr, Annotation, Connectivity},
+        parser::parse_s_expr,
+    },
+};
+use std::env;
+
+fn main() -> Result<()> {
+    let usage = "usage: ki_netlist [-s]";
+    let mut args = env::args();
+    let _exec = args.next().ok_or(usage)?;
+    let want_sexpr = args.next().is_some_and(|s| s == "-s");
+
+    let input = parse_stdin(parse_s_expr)?;
+    if input.head() != Some("kicad_sch") {
+        Err(input.error("expected a schematic"))?
+    }
+    let mut graph = Connectivity::new();
+    graph.add_sheet("/", &input, Annotation::of);
+
+    if want_sexpr {
+        write_stdout(&KicadFormat(&netlist_expr(&graph)))?;
+    } else {
+        write_stdout(&serde_json::to_value(graph.nets())?)?;
+    }
+    Ok(())
+}
@@ -1,28 +1,35 @@
-use kiops::parse_file::{parse_stdin, write_stdout, Result};
-use kiops::sexpr::analysis::{footprints, sheets, symbols};
-use kiops::sexpr::json::expr_to_json_value;
-use kiops::sexpr::parser::parse_s_expr;
+use kiops::parse_file::{parse_named, read_stdin, write_stdout, Result};
+use kiops::sexpr::analysis::{footprint, footprints, sheets, symbols};
+use kiops::sexpr::format::KicadFormat;
+use kiops::sexpr::json::{expr_to_json_value, expr_to_tagged_json};
+use kiops::sexpr::parser::{parse_s_expr, parse_top_level};
 use kiops::sexpr::simplifier::{Anything, Simplifier};
 use kiops::sexpr::Expr;
 use std::env;
 
 fn main() -> Result<()> {
-    let usage = "ki_parse: command [-s]";
+    let usage = "ki_parse: command [-s|-l] [--explain]";
     let mut args = env::args();
     let _exec = args.next().ok_or(usage)?;
     let command = args.next().ok_or(usage)?;
-    let want_sexpr = args.next().is_some_and(|s| s == "-s");
+    let options: Vec<String> = args.collect();
+    let want_sexpr = options.iter().any(|o| o == "-s");
+    let want_lossless = options.iter().any(|o| o == "-l");
+    let explain = options.iter().any(|o| o == "--explain");
 
     let output = match &*command {
-        "footprints" => run(footprints())?,
-        "symbols" => run(symbols())?,
-        "sheets" => run(sheets())?,
-        "format" => run(Anything)?,
+        "footprints" => run_footprints(explain)?,
+        "symbols" => run(symbols(), explain)?,
+        "sheets" => run(sheets(), explain)?,
+        "format" => run(Anything, explain)?,
         _ => Err("argument not recognised")?,
     };
 
     if want_sexpr {
-        write_stdout(&output)?;
+        write_stdout(&KicadFormat(&output))?;
+    } else if want_lossless {
+        let json = expr_to_tagged_json(&output);
+        write_stdout(&json)?;
     } else {
         let json = expr_to_json_value(output);
         write_stdout(&json)?;
@@ -31,10 +38,34 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn run(simplifier: impl Simplifier) -> Result<Expr> {
-    let input = parse_stdin(parse_s_expr)?;
+fn run(simplifier: impl Simplifier, explain: bool) -> Result<Expr> {
+    run_text(simplifier, &read_stdin()?, explain)
+}
 
-    Ok(simplifier
-        .simplify(&input)
-        .ok_or("unrecognised file contents")?)
+/// A board is read one top-level item at a time, keeping only its footprints.
+fn run_footprints(explain: bool) -> Result<Expr> {
+    let text = read_stdin()?;
+    let items = parse_top_level("<stdin>", &text)?;
+    if explain || items.head().as_atom().and_then(|a| a.as_symbol()) != Some("kicad_pcb") {
+        return run_text(footprints(), &text, explain);
+    }
+
+    let footprint = footprint();
+    let mut found = Vec::new();
+    for item in items {
+        let item = item?;
+        if item.head() == Some("footprint") {
+            found.extend(footprint.simplify(&item));
+        }
+    }
+    Ok(Expr::list(found))
+}
+
+fn run_text(simplifier: impl Simplifier, text: &str, explain: bool) -> Result<Expr> {
+    let input = parse_named("<stdin>", text, parse_s_expr)?;
+
+    if explain {
+        return Ok(simplifier.explain(&input)?);
+    }
+    Ok(simplifier.simplify_or(&input, "unrecognised file contents")?)
 }
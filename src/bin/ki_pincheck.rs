@@ -0,0 +1,56 @@
+use kiops::{
+    parse_file::{parse_file, write_stdout, Result},
+    sexpr::{
+        libtable::LibTable,
+        parser::parse_s_expr,
+        pincheck::{check_library, check_project, FootprintResolver},
+        project::Project,
+    },
+};
+use std::{env, path::Path};
+
+fn main() -> Result<()> {
+    let usage =
+        "usage: ki_pincheck [-a] fp-lib-table... file.kicad_pro|file.kicad_sch|file.kicad_sym";
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let want_all = args.first().is_some_and(|a| a == "-a");
+    if want_all {
+        args.remove(0);
+    }
+    let file = args.pop().ok_or(usage)?;
+    let file = Path::new(&file);
+    let project_dir = file
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    let mut tables = Vec::new();
+    for name in &args {
+        let expr = parse_file(name, parse_s_expr)?;
+        tables.push(
+            LibTable::from_expr(&expr).ok_or_else(|| expr.error("expected a library table"))?,
+        );
+    }
+    let resolver = FootprintResolver::new(tables, project_dir);
+
+    let reports = match file.extension().and_then(|e| e.to_str()) {
+        Some("kicad_sym") => {
+            let symlib = parse_file(&file.to_string_lossy(), parse_s_expr)?;
+            check_library(&symlib, &resolver)
+        }
+        Some("kicad_pro" | "kicad_sch") => check_project(&Project::load(file)?, &resolver),
+        _ => Err(usage)?,
+    };
+
+    let problems = reports.iter().filter(|r| r.is_problem()).count();
+    let shown: Vec<_> = reports
+        .iter()
+        .filter(|r| want_all || r.is_problem())
+        .collect();
+    write_stdout(&serde_json::to_value(shown)?)?;
+
+    if problems > 0 {
+        Err(format!("{problems} symbols do not match their footprints"))?
+    }
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use kiops::{
+    parse_file::{parse_stdin, write_stdout, Result},
+    sexpr::{
+        parser::parse_s_expr,
+        position::{placements, to_ascii, to_csv, PosOptions, Side, Units},
+    },
+};
+use std::env;
+
+fn main() -> Result<()> {
+    let usage = "usage: ki_pos ascii|csv|json [-a] [-n] [-i] [--front|--back] [--smd-only] [--exclude-th] [--exclude-dnp]";
+    let mut args = env::args();
+    let _exec = args.next().ok_or(usage)?;
+    let format = args.next().ok_or(usage)?;
+
+    let mut options = PosOptions::default();
+    for arg in args {
+        match arg.as_str() {
+            "-a" => options.aux_origin = true,
+            "-n" => options.negate_bottom_x = true,
+            "-i" => options.units = Units::Inches,
+            "--front" => options.side = Some(Side::Top),
+            "--back" => options.side = Some(Side::Bottom),
+            "--smd-only" => options.smd_only = true,
+            "--exclude-th" => options.exclude_through_hole = true,
+            "--exclude-dnp" => options.exclude_dnp = true,
+            _ => Err(usage)?,
+        }
+    }
+
+    let input = parse_stdin(parse_s_expr)?;
+    let placements = placements(&input, &options).ok_or_else(|| input.error("expected a PCB"))?;
+
+    match format.as_str() {
+        "ascii" => write_stdout(&to_ascii(&placements, &options))?,
+        "csv" => write_stdout(&to_csv(&placements))?,
+        "json" => write_stdout(&serde_json::to_value(&placements)?)?,
+        _ => Err(usage)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use kiops::{
+    parse_file::{write_stdout, Result},
+    sexpr::{format::KicadFormat, netlist::netlist_expr, project::Project},
+};
+use std::env;
+
+fn main() -> Result<()> {
+    let usage = "usage: ki_project project.kicad_pro sheets|symbols|nets [-s]";
+    let mut args = env::args();
+    let _exec = args.next().ok_or(usage)?;
+    let path = args.next().ok_or(usage)?;
+    let command = args.next().ok_or(usage)?;
+    let want_sexpr = args.next().is_some_and(|s| s == "-s");
+
+    let project = Project::load(path)?;
+
+    match command.as_str() {
+        "sheets" => write_stdout(&serde_json::to_value(&project.sheets)?)?,
+        "symbols" => write_stdout(&serde_json::to_value(project.symbols())?)?,
+        "nets" if want_sexpr => write_stdout(&KicadFormat(&netlist_expr(&project.connectivity())))?,
+        "nets" => write_stdout(&serde_json::to_value(project.connectivity().nets())?)?,
+        _ => Err(usage)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use kiops::{
+    parse_file::{parse_stdin, write_stdout, Result},
+    sexpr::{
+        format::KicadFormat,
+        json::expr_to_json_value,
+        parser::parse_s_expr,
+        pattern::compile,
+        simplifier::{Descendants, Simplifier},
+        Expr,
+    },
+};
+use serde_json::Value;
+use std::env;
+
+fn main() -> Result<()> {
+    let usage = "usage: ki_query pattern [-s]";
+    let mut args = env::args();
+    let _exec = args.next().ok_or(usage)?;
+    let pattern = compile(&args.next().ok_or(usage)?)?;
+    let want_sexpr = args.next().is_some_and(|s| s == "-s");
+
+    let input = parse_stdin(parse_s_expr)?;
+    let mut matches: Vec<Expr> = pattern.simplify(&input).into_iter().collect();
+    if let Some(found) = Descendants(pattern)
+        .simplify(&input)
+        .and_then(Expr::into_deque)
+    {
+        matches.extend(found);
+    }
+
+    if want_sexpr {
+        for m in &matches {
+            write_stdout(&KicadFormat(m))?;
+        }
+    } else {
+        let json: Vec<Value> = matches.into_iter().map(expr_to_json_value).collect();
+        write_stdout(&Value::Array(json))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use kiops::{
+    parse_file::{parse_file, write_file, Result},
+    sexpr::{
+        format::KicadFormat,
+        libtable::{expand_vars, project_vars, LibTable},
+        parser::parse_s_expr,
+        project::Project,
+        rename::{rename_board, rename_schematic, rename_symlib, rename_table, Change, Renames},
+        Expr,
+    },
+};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// The directory holding `path`, which is `.` for a bare file name.
+fn directory(path: &str) -> &Path {
+    Path::new(path)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+/// Rewrite a board or a library table.
+fn rename_other(path: &str, renames: &Renames) -> Result<(String, Expr, Vec<Change>)> {
+    let input = parse_file(path, parse_s_expr)?;
+    let (output, changes) = match input.head() {
+        Some("kicad_pcb") => rename_board(input, renames),
+        Some("sym_lib_table" | "fp_lib_table") => rename_table(input, renames),
+        _ => Err(format!(
+            "{path}: expected a .kicad_sym, .kicad_pro or .kicad_pcb file or a library table"
+        ))?,
+    };
+    Ok((path.to_owned(), output, changes))
+}
+
+fn main() -> Result<()> {
+    let usage = "usage: ki_rename [--dry-run] [-s from=to]... [-f from=to]... file...";
+    let mut dry_run = false;
+    let mut renames = Renames::default();
+    let mut paths = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "-s" => renames.symbols.0.push(args.next().ok_or(usage)?.parse()?),
+            "-f" => renames
+                .footprints
+                .0
+                .push(args.next().ok_or(usage)?.parse()?),
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        Err(usage)?
+    }
+
+    let mut outputs: Vec<(String, Expr, Vec<Change>)> = Vec::new();
+    for path in paths {
+        let extension = Path::new(&path).extension().and_then(|e| e.to_str());
+        match extension {
+            Some("kicad_sym") => {
+                let nickname = Path::new(&path)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let library = parse_file(&path, parse_s_expr)?;
+                let (output, changes) = rename_symlib(library, &nickname, &renames)?;
+                outputs.push((path, output, changes));
+            }
+            Some("kicad_pro" | "kicad_sch") => {
+                let project = Project::load(&path)?;
+                for (file, schematic) in project.files() {
+                    let (output, changes) = rename_schematic(schematic.clone(), &renames);
+                    outputs.push((file.to_string_lossy().into_owned(), output, changes));
+                }
+                let related = [
+                    Path::new(&path).with_extension("kicad_pcb"),
+                    Path::new(&path).with_file_name("sym-lib-table"),
+                    Path::new(&path).with_file_name("fp-lib-table"),
+                ];
+                for file in related.iter().filter(|f| f.exists()) {
+                    outputs.push(rename_other(&file.to_string_lossy(), &renames)?);
+                }
+            }
+            _ => outputs.push(rename_other(&path, &renames)?),
+        }
+    }
+
+    // Library files named for a renamed nickname follow their table entry.
+    let mut moves: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for (path, _, changes) in &outputs {
+        let vars = project_vars(directory(path));
+        for change in changes.iter().filter(|c| c.what == "uri") {
+            let from = PathBuf::from(expand_vars(&change.from, &vars));
+            let to = PathBuf::from(expand_vars(&change.to, &vars));
+            if to.exists() {
+                Err(format!("{}: {} already exists", path, to.display()))?
+            }
+            moves.push((from, to));
+        }
+    }
+
+    for (path, output, changes) in outputs {
+        for change in &changes {
+            let separator = if change.span.is_some() { ":" } else { ": " };
+            println!("{path}{separator}{change}");
+        }
+        if !dry_run && !changes.is_empty() {
+            match LibTable::from_expr(&output) {
+                Some(table) => write_file(&path, &table)?,
+                None => write_file(&path, &KicadFormat(&output))?,
+            }
+        }
+    }
+    if !dry_run {
+        for (from, to) in moves.iter().filter(|(from, _)| from.exists()) {
+            fs::rename(from, to)?;
+        }
+    }
+    Ok(())
+}
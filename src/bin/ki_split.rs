@@ -1,20 +1,33 @@
 use kiops::{
     parse_file::{parse_stdin, write_file, Result},
-    sexpr::{parser::parse_s_expr, symlib::split},
+    sexpr::{
+        format::KicadFormat,
+        parser::parse_s_expr,
+        symlib::{split, Derived},
+    },
 };
 use sanitize_filename::sanitize;
 use std::env;
 
 fn main() -> Result<()> {
-    let usage = "usage: ki_split output_dir";
-    let mut args = env::args();
-    let _exec = args.next().ok_or(usage)?;
-    let output = args.next().ok_or(usage)?;
+    let usage = "usage: ki_split [--flatten] output_dir";
+    let mut derived = Derived::Bundle;
+    let mut output = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--flatten" => derived = Derived::Flatten,
+            _ => output = Some(arg),
+        }
+    }
+    let output = output.ok_or(usage)?;
 
     let input = parse_stdin(parse_s_expr)?;
-    for (name, content) in split(input).ok_or("problem with symbol library contents")? {
+    for (name, content) in split(input, derived).ok_or("problem with symbol library contents")? {
         let fname = sanitize(name);
-        write_file(&format!("{output}/{fname}.kicad_sym"), &content)?
+        write_file(
+            &format!("{output}/{fname}.kicad_sym"),
+            &KicadFormat(&content),
+        )?
     }
     Ok(())
 }
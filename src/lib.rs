@@ -2,4 +2,5 @@ pub mod dts;
 pub mod memo;
 pub mod parse_file;
 pub mod sexpr;
+pub mod span;
 pub mod strings;
@@ -1,5 +1,6 @@
-use nom::error::VerboseError;
-use nom::{InputTake, Parser};
+use crate::span::Span;
+use nom::error::{VerboseError, VerboseErrorKind};
+use nom::{Offset, Parser};
 use serde_json::Value;
 use std::fmt::Display;
 use std::fs::File;
@@ -15,7 +16,7 @@ where
     let mut f = File::open(name)?;
     let mut buf = String::new();
     f.read_to_string(&mut buf)?;
-    parse_with(&buf, parser)
+    parse_named(name, &buf, parser)
 }
 
 pub fn write_file<A: Display>(path: &str, content: &A) -> Result<()> {
@@ -29,9 +30,14 @@ where
     P: for<'a> Parser<&'a str, T, VerboseError<&'a str>>,
     T: 'static,
 {
+    parse_named("<stdin>", &read_stdin()?, parser)
+}
+
+/// Read the whole of the standard input as text.
+pub fn read_stdin() -> Result<String> {
     let mut buf = String::new();
     stdin().read_to_string(&mut buf)?;
-    parse_with(&buf, parser)
+    Ok(buf)
 }
 
 pub fn write_stdout<A: Display>(content: &A) -> Result<()> {
@@ -52,22 +58,100 @@ pub fn read_json(path: &str) -> std::result::Result<Value, Box<dyn std::error::E
     Ok(value)
 }
 
-pub fn parse_with<P, T>(text: &str, mut parser: P) -> Result<T>
+pub fn parse_with<P, T>(text: &str, parser: P) -> Result<T>
+where
+    P: for<'a> Parser<&'a str, T, VerboseError<&'a str>>,
+    T: 'static,
+{
+    parse_named("<input>", text, parser)
+}
+
+/// Parse `text` which came from the source called `name`.
+/// A failure is reported as a `ParseError` giving the location in the source.
+pub fn parse_named<P, T>(name: &str, text: &str, parser: P) -> Result<T>
 where
     P: for<'a> Parser<&'a str, T, VerboseError<&'a str>>,
     T: 'static,
 {
-    Ok(parser
-        .parse(text)
-        .map(|(_, data)| data)
-        .map_err(|err| err.map(abrev))?)
+    parse_borrowed(name, text, parser)
+}
+
+/// Parse `text` as `parse_named` does into a result which may borrow from the text.
+pub fn parse_borrowed<'a, P, T>(name: &str, text: &'a str, mut parser: P) -> Result<T>
+where
+    P: Parser<&'a str, T, VerboseError<&'a str>>,
+{
+    match parser.parse(text) {
+        Ok((_, data)) => Ok(data),
+        Err(err) => Err(parse_error(name, text, err).into()),
+    }
 }
 
-fn abrev(err: VerboseError<&str>) -> VerboseError<String> {
-    let errors = err
-        .errors
-        .into_iter()
-        .map(|(i, k)| (i.take(160.min(i.len())).to_owned(), k))
-        .collect();
-    VerboseError { errors }
+/// Locate a failure to parse `text`, or part of it, from the source called `name`.
+pub(crate) fn parse_error(name: &str, text: &str, err: nom::Err<VerboseError<&str>>) -> ParseError {
+    match err {
+        nom::Err::Error(err) | nom::Err::Failure(err) => ParseError::new(name, text, err),
+        nom::Err::Incomplete(_) => ParseError {
+            name: name.to_owned(),
+            span: Span::at(text, text.len()),
+            message: "unexpected end of input".to_owned(),
+            snippet: Span::at(text, text.len()).snippet(text),
+        },
+    }
 }
+
+/// A parse failure located in the source text.
+#[derive(Clone)]
+pub struct ParseError {
+    pub name: String,
+    pub span: Span,
+    pub message: String,
+    pub snippet: String,
+}
+
+impl ParseError {
+    fn new(name: &str, text: &str, err: VerboseError<&str>) -> Self {
+        // the first entry is the innermost, most specific error
+        let offset = err.errors.first().map(|(i, _)| text.offset(i)).unwrap_or(0);
+        let span = Span::at(text, offset);
+
+        let mut message = String::new();
+        for (_, kind) in &err.errors {
+            let part = match kind {
+                VerboseErrorKind::Char(c) => format!("expected '{c}'"),
+                VerboseErrorKind::Context(s) => format!("in {s}"),
+                VerboseErrorKind::Nom(nom::error::ErrorKind::Eof) => "unexpected input".to_owned(),
+                VerboseErrorKind::Nom(k) => k.description().to_lowercase(),
+            };
+            if !message.is_empty() {
+                message.push_str(", ");
+            }
+            message.push_str(&part);
+        }
+
+        Self {
+            name: name.to_owned(),
+            span,
+            message,
+            snippet: span.snippet(text),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "{}:{}: parse error: {}\n{}",
+            self.name, self.span, self.message, self.snippet
+        ))
+    }
+}
+
+impl std::fmt::Debug for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // shown when a parse error is returned from main
+        Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -4,6 +4,15 @@ pub fn trim(x: &Expr) -> Option<Expr> {
     Some(x.as_atom()?.as_string()?.trim().into())
 }
 
+/// The value of the named property of a symbol, sheet or footprint.
+pub fn property_value<'a>(item: &'a Expr, name: &str) -> Option<&'a str> {
+    item.children("property").find_map(|p| {
+        let mut args = p.args();
+        (args.next()?.as_atom()?.as_string()? == name).then_some(())?;
+        args.next()?.as_atom()?.as_string()
+    })
+}
+
 pub fn property(name: &str) -> impl Simplifier {
     let name = name.to_owned();
 
@@ -29,7 +38,8 @@ pub fn any_property() -> impl Simplifier {
     )
 }
 
-pub fn footprints() -> impl Simplifier {
+/// Summarize a single `footprint` as found in a board or a footprint file.
+pub fn footprint() -> impl Simplifier {
     let description = property("description");
 
     let at = Cons(
@@ -44,19 +54,33 @@ pub fn footprints() -> impl Simplifier {
         Cons("reference", Cons(AnyStr, Discard(Anything))),
     );
 
-    let footprint = {
+    let layer = Cons("layer", Cons(AnyStr, Nothing));
+
+    let attr = Cons("attr", Anything);
+
+    Cons(
+        Discard("footprint"),
         Cons(
-            Discard("footprint"),
-            Cons(
-                And(AnyStr, LabelAs("library")),
-                Filter(reference.or(at).or(description).or(model)),
+            And(AnyStr, LabelAs("library")),
+            Filter(
+                reference
+                    .or(property("reference"))
+                    .or(at)
+                    .or(layer)
+                    .or(attr)
+                    .or(description)
+                    .or(model),
             ),
-        )
-    };
+        ),
+    )
+}
+
+pub fn footprints() -> impl Simplifier {
+    let footprint = footprint();
 
     let enlist = |expr: &Expr| -> Option<Expr> { Some(Expr::list([expr.clone()])) };
 
-    Cons(Discard("kicad_pcb"), Filter(footprint.clone())).or(footprint.clone().and(enlist))
+    Cons(Discard("kicad_pcb"), Filter(footprint.clone())).or(footprint.and(enlist))
 }
 
 pub fn symbols() -> impl Simplifier {
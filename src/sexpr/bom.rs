@@ -0,0 +1,367 @@
+//! Bills of materials from placed schematic symbols.
+//!
+//! Power symbols and symbols excluded from the BOM are dropped and the units
+//! of a multi-unit symbol count once.  A flat BOM has one line per reference
+//! and includes do-not-populate (DNP) parts.  A grouped BOM has one line per
+//! part to be purchased and leaves DNP parts out.
+use super::{project::SymbolInstance, Expr};
+use serde::Serialize;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    iter::Peekable,
+    str::Chars,
+};
+
+/// How parts are combined into lines of a grouped BOM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    /// Group by manufacturer part number, or by value and footprint if there is none.
+    Mpn,
+    /// Group by value and footprint.
+    ValueFootprint,
+}
+
+/// What the lines of a grouped BOM have in common.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum GroupKey {
+    Mpn(String),
+    ValueFootprint(String, String),
+}
+
+/// One line of a BOM.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct BomLine {
+    pub references: Vec<String>,
+    pub quantity: usize,
+    pub value: String,
+    pub footprint: String,
+    pub manufacturer: String,
+    #[serde(rename = "MPN")]
+    pub mpn: String,
+    pub description: String,
+    #[serde(rename = "DNP")]
+    pub dnp: bool,
+}
+
+/// The lines of a BOM and any problems found making it.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Bom {
+    pub lines: Vec<BomLine>,
+    pub warnings: Vec<String>,
+}
+
+/// A BOM with one line per reference in natural order of reference.
+pub fn flat_bom(symbols: &[SymbolInstance]) -> Bom {
+    let mut bom = Bom::default();
+    let mut seen: HashSet<&str> = HashSet::new();
+
+    for symbol in symbols {
+        if symbol.power || !symbol.in_bom || symbol.reference.starts_with('#') {
+            continue;
+        }
+        if !seen.insert(&symbol.reference) {
+            continue;
+        }
+        let line = BomLine {
+            references: vec![symbol.reference.clone()],
+            quantity: 1,
+            value: field(symbol, "Value"),
+            footprint: field(symbol, "Footprint"),
+            manufacturer: field(symbol, "Manufacturer"),
+            mpn: field(symbol, "MPN"),
+            description: field(symbol, "Description"),
+            dnp: symbol.dnp,
+        };
+        if !line.dnp && line.mpn.is_empty() {
+            bom.warnings
+                .push(format!("missing MPN for {}", symbol.reference));
+        }
+        bom.lines.push(line);
+    }
+
+    bom.lines
+        .sort_by(|a, b| natural_cmp(&a.references[0], &b.references[0]));
+    bom
+}
+
+/// A BOM with one line per part, omitting DNP parts.
+/// Fields which differ within a line are listed once each, separated by spaces.
+pub fn grouped_bom(symbols: &[SymbolInstance], group_by: GroupBy) -> Bom {
+    let flat = flat_bom(symbols);
+    let mut groups: BTreeMap<GroupKey, Vec<BomLine>> = BTreeMap::new();
+
+    for line in flat.lines.into_iter().filter(|l| !l.dnp) {
+        let key = match group_by {
+            GroupBy::Mpn if !line.mpn.is_empty() => GroupKey::Mpn(line.mpn.clone()),
+            _ => GroupKey::ValueFootprint(line.value.clone(), line.footprint.clone()),
+        };
+        groups.entry(key).or_default().push(line);
+    }
+
+    let gather = |lines: &[BomLine], get: fn(&BomLine) -> &str| {
+        let mut values: Vec<&str> = Vec::new();
+        for value in lines.iter().map(get) {
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        values.join(" ")
+    };
+
+    let mut lines: Vec<BomLine> = groups
+        .into_values()
+        .map(|lines| BomLine {
+            references: lines
+                .iter()
+                .flat_map(|l| l.references.iter().cloned())
+                .collect(),
+            quantity: lines.len(),
+            value: gather(&lines, |l| &l.value),
+            footprint: gather(&lines, |l| &l.footprint),
+            manufacturer: gather(&lines, |l| &l.manufacturer),
+            mpn: gather(&lines, |l| &l.mpn),
+            description: gather(&lines, |l| &l.description),
+            dnp: false,
+        })
+        .collect();
+
+    lines.sort_by(|a, b| natural_cmp(&a.references[0], &b.references[0]));
+    Bom {
+        lines,
+        warnings: flat.warnings,
+    }
+}
+
+/// A property of a symbol, matching its name without regard to case.
+fn field(symbol: &SymbolInstance, name: &str) -> String {
+    symbol
+        .properties
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.trim().to_owned())
+        .unwrap_or_default()
+}
+
+/// The placed symbols of a single sheet as summarised by `analysis::symbols()`.
+/// References come from the symbols' own `Reference` properties.
+pub fn summary_instances(summary: &Expr) -> Vec<SymbolInstance> {
+    let Some(items) = summary.as_list() else {
+        return Vec::new();
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let mut properties: BTreeMap<String, String> = item
+                .as_list()?
+                .iter()
+                .filter_map(|p| Some((p.head()?.to_owned(), p.str_arg()?.to_owned())))
+                .collect();
+            let flag = |key: &str, default: bool| {
+                item.child(key)
+                    .and_then(Expr::symbol_arg)
+                    .map_or(default, |v| v == "yes")
+            };
+            Some(SymbolInstance {
+                sheet: "/".to_owned(),
+                reference: properties.remove("Reference")?,
+                unit: item
+                    .child("unit")
+                    .and_then(Expr::num_arg)
+                    .map_or(1, |n| n as i64),
+                lib_id: properties.remove("lib_id").unwrap_or_default(),
+                in_bom: flag("in_bom", true),
+                on_board: true,
+                dnp: flag("dnp", false),
+                power: false,
+                properties,
+            })
+        })
+        .collect()
+}
+
+/// Compare strings treating runs of digits as numbers, so that `R2` comes before `R10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (digits(&mut a), digits(&mut b));
+                let (xs, ys) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = xs.len().cmp(&ys.len()).then(xs.cmp(ys));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// Take a run of digits from the front of `chars`.
+fn digits(chars: &mut Peekable<Chars>) -> String {
+    let mut run = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        run.push(c);
+    }
+    run
+}
+
+/// The BOM as CSV with a header row.
+/// A flat BOM has the columns KiCad's BOM export produces,
+/// a grouped BOM has a quantity column and the references in one field.
+pub fn to_csv(bom: &Bom, grouped: bool) -> String {
+    let mut csv = String::new();
+    let mut row = |fields: &[&str]| {
+        let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        writeln!(csv, "{}", fields.join(",")).unwrap();
+    };
+
+    if grouped {
+        row(&[
+            "Refs",
+            "Quantity",
+            "Value",
+            "Manufacturer",
+            "Part",
+            "Description",
+            "Footprint",
+        ]);
+        for line in &bom.lines {
+            row(&[
+                &line.references.join(" "),
+                &line.quantity.to_string(),
+                &line.value,
+                &line.manufacturer,
+                &line.mpn,
+                &line.description,
+                &line.footprint,
+            ]);
+        }
+    } else {
+        row(&[
+            "Reference",
+            "Manufacturer",
+            "MPN",
+            "Value",
+            "Description",
+            "Footprint",
+            "DNP",
+        ]);
+        for line in &bom.lines {
+            row(&[
+                &line.references.join(" "),
+                &line.manufacturer,
+                &line.mpn,
+                &line.value,
+                &line.description,
+                &line.footprint,
+                if line.dnp { "DNP" } else { "" },
+            ]);
+        }
+    }
+    csv
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{flat_bom, grouped_bom, natural_cmp, summary_instances, to_csv, GroupBy};
+    use crate::{
+        parse_file::parse_with,
+        sexpr::{
+            analysis::symbols, parser::parse_s_expr, project::SymbolInstance,
+            simplifier::Simplifier,
+        },
+    };
+    use std::cmp::Ordering;
+
+    fn instances() -> Vec<SymbolInstance> {
+        let s = r##"
+            (kicad_sch
+                (symbol (lib_id "Device:R") (unit 1) (in_bom yes) (dnp no)
+                    (property "Reference" "R10") (property "Value" "10k")
+                    (property "Footprint" "R_0603") (property "MPN" "RC0603-10K"))
+                (symbol (lib_id "Device:R") (unit 1) (in_bom yes) (dnp no)
+                    (property "Reference" "R2") (property "Value" "10k")
+                    (property "Footprint" "R_0603") (property "MPN" "RC0603-10K"))
+                (symbol (lib_id "Device:C") (unit 1) (in_bom yes) (dnp no)
+                    (property "Reference" "C1") (property "Value" "100n, 50V")
+                    (property "Footprint" "C_0603"))
+                (symbol (lib_id "Device:C") (unit 1) (in_bom yes) (dnp yes)
+                    (property "Reference" "C2") (property "Value" "1u")
+                    (property "Footprint" "C_0603"))
+                (symbol (lib_id "Connector:TestPoint") (unit 1) (in_bom no) (dnp no)
+                    (property "Reference" "TP1") (property "Value" "TP"))
+                (symbol (lib_id "power:GND") (unit 1) (in_bom yes) (dnp no)
+                    (property "Reference" "#PWR01") (property "Value" "GND"))
+            )
+        "##;
+        let expr = parse_with(s, parse_s_expr).unwrap();
+        summary_instances(&symbols().simplify(&expr).unwrap())
+    }
+
+    #[test]
+    fn test_natural_order() {
+        assert_eq!(natural_cmp("R2", "R10"), Ordering::Less);
+        assert_eq!(natural_cmp("R10", "R10"), Ordering::Equal);
+        assert_eq!(natural_cmp("C10", "R1"), Ordering::Less);
+        assert_eq!(natural_cmp("U1A", "U1"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_flat_bom() {
+        let bom = flat_bom(&instances());
+        let references: Vec<_> = bom.lines.iter().map(|l| l.references[0].as_str()).collect();
+        assert_eq!(references, ["C1", "C2", "R2", "R10"]);
+        assert!(bom.lines[1].dnp);
+        assert_eq!(bom.warnings, ["missing MPN for C1"]);
+        assert_eq!(
+            to_csv(&bom, false).lines().nth(1),
+            Some("C1,,,\"100n, 50V\",,C_0603,")
+        );
+    }
+
+    #[test]
+    fn test_grouped_bom() {
+        let bom = grouped_bom(&instances(), GroupBy::Mpn);
+        assert_eq!(
+            to_csv(&bom, true),
+            "Refs,Quantity,Value,Manufacturer,Part,Description,Footprint\n\
+             C1,1,\"100n, 50V\",,,,C_0603\n\
+             R2 R10,2,10k,,RC0603-10K,,R_0603\n"
+        );
+    }
+
+    #[test]
+    fn test_mpn_equal_to_value() {
+        let mut symbols = instances();
+        let mut odd = symbols[0].clone();
+        odd.reference = "X1".to_owned();
+        odd.properties = [("Value".to_owned(), "RC0603-10K".to_owned())].into();
+        symbols.push(odd);
+        let bom = grouped_bom(&symbols, GroupBy::Mpn);
+        let references: Vec<_> = bom.lines.iter().map(|l| l.references.join(" ")).collect();
+        assert_eq!(references, ["C1", "R2 R10", "X1"]);
+    }
+}
@@ -0,0 +1,673 @@
+//! S-expressions which borrow from their source text.
+//!
+//! `borrowed::Expr<'a>` mirrors `sexpr::Expr` but its symbols, numbers and
+//! unescaped strings are slices of the text it was parsed from and its lists
+//! are `Vec`s, so a list can be matched a slice at a time.  Parsing a large
+//! board this way copies almost nothing.
+//!
+//! Each node records its byte offset in the source text, from which a `Span`
+//! can be found when needed.  `Expr::to_expr` converts a borrowed expression
+//! to an owned one with its spans.
+//!
+//! The combinators of `simplifier` also implement `RefSimplifier` which works
+//! on borrowed expressions by reference.  `Cons` passes the tail of a list to
+//! its second simplifier as a slice rather than a copy, so matching a whole
+//! board is linear in its size.
+use super::{
+    parser::parse_scalar,
+    simplifier::{
+        AnyNum, AnyStr, Anything, Cons, Descendants, Discard, Ensure, Filter, Find, FindDeep, Head,
+        LabelAs, Not, Nothing, Or,
+    },
+    Numeric,
+};
+use crate::{
+    parse_file::{parse_borrowed, Result},
+    span::{Cursor, Span},
+    strings,
+};
+use nom::{
+    bytes::complete::is_not,
+    character::complete::{char, multispace0},
+    combinator::{all_consuming, cut, map},
+    error::{context, VerboseError},
+    sequence::delimited,
+    IResult, Needed, Parser,
+};
+use std::borrow::Cow;
+use uuid::Uuid;
+
+/// Indivisible values in a borrowed S-expression.
+/// Numbers keep their spelling in the source text.
+#[derive(Debug, Clone)]
+pub enum Atom<'a> {
+    Symbol(&'a str),
+    Str(Cow<'a, str>),
+    Num(f64, &'a str),
+    Bits(u64, &'a str),
+    Bool(bool),
+    Uuid(Uuid),
+}
+
+impl<'a> Atom<'a> {
+    pub fn as_string(&self) -> Option<&str> {
+        match self {
+            Atom::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub fn as_symbol(&self) -> Option<&'a str> {
+        match self {
+            Atom::Symbol(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub fn as_num(&self) -> Option<f64> {
+        match self {
+            Atom::Num(n, _) => Some(*n),
+            _ => None,
+        }
+    }
+    pub fn as_bits(&self) -> Option<u64> {
+        match self {
+            Atom::Bits(n, _) => Some(*n),
+            _ => None,
+        }
+    }
+    pub fn as_boolean(&self) -> Option<bool> {
+        match self {
+            Atom::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+    pub fn as_uuid(&self) -> Option<Uuid> {
+        match self {
+            Atom::Uuid(u) => Some(*u),
+            _ => None,
+        }
+    }
+
+    /// The equivalent owned atom.
+    pub fn to_atom(&self) -> super::Atom {
+        match self {
+            Atom::Symbol(s) => super::Atom::Symbol(s.to_string()),
+            Atom::Str(s) => super::Atom::Str(s.to_string()),
+            Atom::Num(n, text) => super::Atom::Num(Numeric::with_text(*n, text)),
+            Atom::Bits(n, text) => super::Atom::Bits(Numeric::with_text(*n, text)),
+            Atom::Bool(b) => super::Atom::Bool(*b),
+            Atom::Uuid(u) => super::Atom::Uuid(*u),
+        }
+    }
+}
+
+/// As with owned atoms, only the value of a number takes part in equality.
+impl PartialEq for Atom<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Atom::Symbol(a), Atom::Symbol(b)) => a == b,
+            (Atom::Str(a), Atom::Str(b)) => a == b,
+            (Atom::Num(a, _), Atom::Num(b, _)) => a == b,
+            (Atom::Bits(a, _), Atom::Bits(b, _)) => a == b,
+            (Atom::Bool(a), Atom::Bool(b)) => a == b,
+            (Atom::Uuid(a), Atom::Uuid(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq<super::Atom> for Atom<'_> {
+    fn eq(&self, other: &super::Atom) -> bool {
+        match (self, other) {
+            (Atom::Symbol(a), super::Atom::Symbol(b)) => a == b,
+            (Atom::Str(a), super::Atom::Str(b)) => a == b,
+            (Atom::Num(a, _), super::Atom::Num(b)) => *a == b.value(),
+            (Atom::Bits(a, _), super::Atom::Bits(b)) => *a == b.value(),
+            (Atom::Bool(a), super::Atom::Bool(b)) => a == b,
+            (Atom::Uuid(a), super::Atom::Uuid(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// A borrowed S-expression.
+/// Each node records its byte offset in the source text,
+/// which does not take part in equality.
+#[derive(Debug, Clone)]
+pub enum Expr<'a> {
+    Constant(Atom<'a>, usize),
+    List(Vec<Expr<'a>>, usize),
+}
+
+impl<'a> Expr<'a> {
+    pub fn as_atom(&self) -> Option<&Atom<'a>> {
+        match self {
+            Expr::Constant(a, _) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Expr<'a>]> {
+        match self {
+            Expr::List(elems, _) => Some(elems),
+            _ => None,
+        }
+    }
+
+    pub fn into_vec(self) -> Option<Vec<Expr<'a>>> {
+        match self {
+            Expr::List(elems, _) => Some(elems),
+            _ => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Expr::List(elems, _) => elems.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// The symbol at the head of a list such as `(at 1 2)`.
+    pub fn head(&self) -> Option<&'a str> {
+        self.as_list()?.first()?.as_atom()?.as_symbol()
+    }
+
+    /// The elements of a list following its head.
+    pub fn args(&self) -> impl Iterator<Item = &Expr<'a>> {
+        self.as_list().into_iter().flat_map(|xs| xs.iter().skip(1))
+    }
+
+    /// The first element after the head, if it is a string, as in `(lib_id "Device:R")`.
+    pub fn str_arg(&self) -> Option<&str> {
+        self.args().next()?.as_atom()?.as_string()
+    }
+
+    /// The first element after the head, if it is a symbol, as in `(in_bom yes)`.
+    pub fn symbol_arg(&self) -> Option<&'a str> {
+        self.args().next()?.as_atom()?.as_symbol()
+    }
+
+    /// The first element after the head, if it is a number, as in `(unit 1)`.
+    pub fn num_arg(&self) -> Option<f64> {
+        self.args().next()?.as_atom()?.as_num()
+    }
+
+    /// The elements of a list which are lists headed by the symbol `key`.
+    pub fn children<'e>(&'e self, key: &'e str) -> impl Iterator<Item = &'e Expr<'a>> {
+        self.as_list()
+            .into_iter()
+            .flatten()
+            .filter(move |x| x.head() == Some(key))
+    }
+
+    /// The first element of a list which is a list headed by the symbol `key`.
+    pub fn child(&self, key: &str) -> Option<&Expr<'a>> {
+        self.as_list()?.iter().find(|x| x.head() == Some(key))
+    }
+
+    /// The byte offset of this expression in its source text.
+    pub fn offset(&self) -> usize {
+        match self {
+            Expr::Constant(_, offset) | Expr::List(_, offset) => *offset,
+        }
+    }
+
+    /// The location of this expression in its source text.
+    pub fn span(&self, text: &str) -> Span {
+        Span::at(text, self.offset())
+    }
+
+    /// The equivalent owned expression, located in the source text.
+    pub fn to_expr(&self, text: &str) -> super::Expr {
+        fn convert(expr: &Expr, cursor: &mut Cursor) -> super::Expr {
+            let span = Some(cursor.advance(expr.offset()));
+            match expr {
+                Expr::Constant(atom, _) => super::Expr::Constant(atom.to_atom(), span),
+                Expr::List(elems, _) => {
+                    super::Expr::List(elems.iter().map(|x| convert(x, cursor)).collect(), span)
+                }
+            }
+        }
+        convert(self, &mut Cursor::new(text))
+    }
+}
+
+impl PartialEq for Expr<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Expr::Constant(a, _), Expr::Constant(b, _)) => a == b,
+            (Expr::List(a, _), Expr::List(b, _)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// A quoted string, borrowed unless it contains escapes.
+fn parse_string(i: &str) -> IResult<&str, Cow<'_, str>, VerboseError<&str>> {
+    let plain = delimited(char('"'), is_not("\"\\"), char('"'));
+    map(plain, Cow::Borrowed)
+        .or(map(strings::parse_string, Cow::Owned))
+        .parse(i)
+}
+
+/// An atom is a quoted string or a scalar.
+fn parse_atom(i: &str) -> IResult<&str, Atom<'_>, VerboseError<&str>> {
+    if i.starts_with('"') {
+        map(parse_string, Atom::Str).or(parse_scalar).parse(i)
+    } else {
+        parse_scalar(i)
+    }
+}
+
+// While parsing, each node records the length of the remaining input.
+// `settle` converts these to offsets once the parse is complete.
+
+/// An atom is a constant expression
+fn parse_constant(i: &str) -> IResult<&str, Expr<'_>, VerboseError<&str>> {
+    let remaining = i.len();
+    map(parse_atom, move |atom| Expr::Constant(atom, remaining)).parse(i)
+}
+
+/// A list is zero or more expressions in brackets.
+fn parse_list(i: &str) -> IResult<&str, Expr<'_>, VerboseError<&str>> {
+    let remaining = i.len();
+    map(
+        delimited(
+            char('('),
+            parse_bare_list,
+            context("closing paren", cut(char(')'))),
+        ),
+        move |elems| Expr::List(elems, remaining),
+    )
+    .parse(i)
+}
+
+/// An expression is either a list or a constant
+fn parse_expr(i: &str) -> IResult<&str, Expr<'_>, VerboseError<&str>> {
+    if i.is_empty() {
+        // reported as an unexpected end of input
+        Err(nom::Err::Incomplete(Needed::Unknown))
+    } else if i.starts_with('(') {
+        parse_list(i)
+    } else {
+        parse_constant(i)
+    }
+}
+
+/// An unbracketed list of zero or more expressions.
+/// A closing bracket ends the list without the cost of a failed parse.
+fn parse_bare_list(i: &str) -> IResult<&str, Vec<Expr<'_>>, VerboseError<&str>> {
+    let mut elems = Vec::new();
+    let (mut i, _) = multispace0(i)?;
+    while !i.starts_with(')') {
+        let (rest, elem) = parse_expr(i)?;
+        elems.push(elem);
+        (i, _) = multispace0(rest)?;
+    }
+    Ok((i, elems))
+}
+
+/// Replace the remaining lengths recorded while parsing with offsets in text of length `len`.
+fn settle(expr: &mut Expr, len: usize) {
+    match expr {
+        Expr::Constant(_, offset) => *offset = len - *offset,
+        Expr::List(elems, offset) => {
+            *offset = len - *offset;
+            for elem in elems {
+                settle(elem, len)
+            }
+        }
+    }
+}
+
+/// The parser accepts a single expression, usually a bracketed list,
+/// as `parser::parse_s_expr` does.
+pub fn parse_s_expr(i: &str) -> IResult<&str, Expr<'_>, VerboseError<&str>> {
+    map(
+        all_consuming(delimited(multispace0, parse_expr, multispace0)),
+        |mut expr| {
+            settle(&mut expr, i.len());
+            expr
+        },
+    )
+    .parse(i)
+}
+
+/// Parse `text` which came from the source called `name`.
+pub fn parse<'a>(name: &str, text: &'a str) -> Result<Expr<'a>> {
+    parse_borrowed(name, text, parse_s_expr)
+}
+
+/// A simplifier which works on borrowed expressions by reference.
+/// The result may borrow from the same text as the subject.
+pub trait RefSimplifier {
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>>;
+
+    /// Simplify the elements of a list, which may be the tail of a longer list.
+    /// `offset` locates the result.  The default copies the elements into
+    /// a new list, so combinators which match lists should override it.
+    fn simplify_elems<'a>(&self, elems: &[Expr<'a>], offset: usize) -> Option<Expr<'a>> {
+        self.simplify_ref(&Expr::List(elems.to_vec(), offset))
+    }
+}
+
+impl<F> RefSimplifier for F
+where
+    F: for<'a> Fn(&Expr<'a>) -> Option<Expr<'a>>,
+{
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        self(subject)
+    }
+}
+
+impl RefSimplifier for super::Atom {
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        (subject.as_atom()? == self).then(|| subject.clone())
+    }
+
+    fn simplify_elems<'a>(&self, _: &[Expr<'a>], _: usize) -> Option<Expr<'a>> {
+        None
+    }
+}
+
+impl RefSimplifier for &'static str {
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        (subject.as_atom()?.as_symbol()? == *self).then(|| subject.clone())
+    }
+
+    fn simplify_elems<'a>(&self, _: &[Expr<'a>], _: usize) -> Option<Expr<'a>> {
+        None
+    }
+}
+
+impl RefSimplifier for AnyNum {
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        subject.as_atom()?.as_num().map(|_| subject.clone())
+    }
+
+    fn simplify_elems<'a>(&self, _: &[Expr<'a>], _: usize) -> Option<Expr<'a>> {
+        None
+    }
+}
+
+impl RefSimplifier for AnyStr {
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        subject.as_atom()?.as_string().map(|_| subject.clone())
+    }
+
+    fn simplify_elems<'a>(&self, _: &[Expr<'a>], _: usize) -> Option<Expr<'a>> {
+        None
+    }
+}
+
+impl RefSimplifier for Anything {
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        Some(subject.clone())
+    }
+}
+
+impl RefSimplifier for Nothing {
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        self.simplify_elems(subject.as_list()?, subject.offset())
+    }
+
+    fn simplify_elems<'a>(&self, elems: &[Expr<'a>], offset: usize) -> Option<Expr<'a>> {
+        elems.is_empty().then(|| Expr::List(Vec::new(), offset))
+    }
+}
+
+impl<A, B> RefSimplifier for Cons<A, B>
+where
+    A: RefSimplifier,
+    B: RefSimplifier,
+{
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        self.simplify_elems(subject.as_list()?, subject.offset())
+    }
+
+    fn simplify_elems<'a>(&self, elems: &[Expr<'a>], offset: usize) -> Option<Expr<'a>> {
+        let (first, rest) = elems.split_first()?;
+        let head = self.0.simplify_ref(first)?;
+        let rest_offset = rest.first().map_or(offset, Expr::offset);
+        let tail = self.1.simplify_elems(rest, rest_offset)?;
+        if head.is_empty() {
+            return Some(match tail {
+                Expr::List(elems, _) => Expr::List(elems, offset),
+                atom => atom,
+            });
+        }
+        let mut result = tail.into_vec()?;
+        result.insert(0, head);
+        Some(Expr::List(result, offset))
+    }
+}
+
+impl<A> RefSimplifier for Head<A>
+where
+    A: RefSimplifier,
+{
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        self.0.simplify_ref(subject.as_list()?.first()?)
+    }
+
+    fn simplify_elems<'a>(&self, elems: &[Expr<'a>], _: usize) -> Option<Expr<'a>> {
+        self.0.simplify_ref(elems.first()?)
+    }
+}
+
+impl<A, B> RefSimplifier for Or<A, B>
+where
+    A: RefSimplifier,
+    B: RefSimplifier,
+{
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        self.0
+            .simplify_ref(subject)
+            .or_else(|| self.1.simplify_ref(subject))
+    }
+
+    fn simplify_elems<'a>(&self, elems: &[Expr<'a>], offset: usize) -> Option<Expr<'a>> {
+        self.0
+            .simplify_elems(elems, offset)
+            .or_else(|| self.1.simplify_elems(elems, offset))
+    }
+}
+
+impl<A, B> RefSimplifier for super::simplifier::And<A, B>
+where
+    A: RefSimplifier,
+    B: RefSimplifier,
+{
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        self.1.simplify_ref(&self.0.simplify_ref(subject)?)
+    }
+
+    fn simplify_elems<'a>(&self, elems: &[Expr<'a>], offset: usize) -> Option<Expr<'a>> {
+        self.1.simplify_ref(&self.0.simplify_elems(elems, offset)?)
+    }
+}
+
+impl<A> RefSimplifier for Filter<A>
+where
+    A: RefSimplifier,
+{
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        self.simplify_elems(subject.as_list()?, subject.offset())
+    }
+
+    fn simplify_elems<'a>(&self, elems: &[Expr<'a>], offset: usize) -> Option<Expr<'a>> {
+        let found = elems.iter().filter_map(|x| self.0.simplify_ref(x));
+        Some(Expr::List(found.collect(), offset))
+    }
+}
+
+impl<A> RefSimplifier for Find<A>
+where
+    A: RefSimplifier,
+{
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        self.simplify_elems(subject.as_list()?, subject.offset())
+    }
+
+    fn simplify_elems<'a>(&self, elems: &[Expr<'a>], _: usize) -> Option<Expr<'a>> {
+        elems.iter().find_map(|x| self.0.simplify_ref(x))
+    }
+}
+
+impl<A> RefSimplifier for Discard<A>
+where
+    A: RefSimplifier,
+{
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        self.0.simplify_ref(subject)?;
+        Some(Expr::List(Vec::new(), subject.offset()))
+    }
+
+    fn simplify_elems<'a>(&self, elems: &[Expr<'a>], offset: usize) -> Option<Expr<'a>> {
+        self.0.simplify_elems(elems, offset)?;
+        Some(Expr::List(Vec::new(), offset))
+    }
+}
+
+impl<A> RefSimplifier for Ensure<A>
+where
+    A: RefSimplifier,
+{
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        self.0.simplify_ref(subject)?;
+        Some(subject.clone())
+    }
+
+    fn simplify_elems<'a>(&self, elems: &[Expr<'a>], offset: usize) -> Option<Expr<'a>> {
+        self.0.simplify_elems(elems, offset)?;
+        Some(Expr::List(elems.to_vec(), offset))
+    }
+}
+
+impl<A> RefSimplifier for Not<A>
+where
+    A: RefSimplifier,
+{
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        self.0
+            .simplify_ref(subject)
+            .is_none()
+            .then(|| subject.clone())
+    }
+
+    fn simplify_elems<'a>(&self, elems: &[Expr<'a>], offset: usize) -> Option<Expr<'a>> {
+        self.0
+            .simplify_elems(elems, offset)
+            .is_none()
+            .then(|| Expr::List(elems.to_vec(), offset))
+    }
+}
+
+impl RefSimplifier for LabelAs {
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        let offset = subject.offset();
+        let label = Expr::Constant(Atom::Symbol(self.0), offset);
+        Some(Expr::List(vec![label, subject.clone()], offset))
+    }
+}
+
+impl<A> RefSimplifier for Descendants<A>
+where
+    A: RefSimplifier,
+{
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        fn visit<'a>(simplifier: &impl RefSimplifier, expr: &Expr<'a>, found: &mut Vec<Expr<'a>>) {
+            for x in expr.as_list().into_iter().flatten() {
+                found.extend(simplifier.simplify_ref(x));
+                visit(simplifier, x, found);
+            }
+        }
+        let mut found = Vec::new();
+        visit(&self.0, subject, &mut found);
+        Some(Expr::List(found, subject.offset()))
+    }
+}
+
+impl<A> RefSimplifier for FindDeep<A>
+where
+    A: RefSimplifier,
+{
+    fn simplify_ref<'a>(&self, subject: &Expr<'a>) -> Option<Expr<'a>> {
+        subject
+            .as_list()?
+            .iter()
+            .find_map(|x| self.0.simplify_ref(x).or_else(|| self.simplify_ref(x)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, RefSimplifier};
+    use crate::{
+        parse_file::parse_with,
+        sexpr::{
+            parser::parse_s_expr,
+            simplifier::{AnyStr, Anything, Cons, Discard, Filter, Nothing, Simplifier},
+        },
+    };
+    use std::borrow::Cow;
+
+    const PCB: &str = r#"(kicad_pcb (version 20240108) (generator "pcbnew")
+  (footprint "Resistor_SMD:R_0603" (layer "F.Cu")
+    (uuid "6f0e7c5e-3d8a-4b8e-9f1a-2b7c1d9e0a11") (at 100.330 50 90)
+    (property "Reference" "R1") (property "Value" "10k \"1%\"") (attr smd))
+  (footprint "Capacitor_SMD:C_0603" (layer "B.Cu")
+    (uuid 9a1b2c3d-4e5f-4a6b-8c7d-0e1f2a3b4c5d) (at 0x1f 2e1)
+    (property "Reference" "C1") (locked true))
+)"#;
+
+    #[test]
+    fn test_parse() {
+        let expr = parse("<input>", PCB).unwrap();
+        let owned = parse_with(PCB, parse_s_expr).unwrap();
+        assert_eq!(expr.to_expr(PCB), owned);
+
+        let footprint = expr.child("footprint").unwrap();
+        assert_eq!(footprint.str_arg(), Some("Resistor_SMD:R_0603"));
+        let span = footprint.span(PCB);
+        assert_eq!((span.line, span.column), (2, 3));
+        assert_eq!(
+            footprint.to_expr(PCB).span(),
+            owned.child("footprint").unwrap().span()
+        );
+
+        let value = footprint.children("property").nth(1).unwrap();
+        let mut args = value.args();
+        assert!(matches!(
+            args.next().unwrap().as_atom().unwrap().as_string(),
+            Some("Value")
+        ));
+        match args.next().unwrap().as_atom().unwrap() {
+            super::Atom::Str(Cow::Owned(s)) => assert_eq!(s, "10k \"1%\""),
+            other => panic!("expected an unescaped string, got {other:?}"),
+        }
+        assert!(parse("<input>", "(a (b)").is_err());
+    }
+
+    #[test]
+    fn test_simplify() {
+        let footprint = Cons(
+            Discard("footprint"),
+            Cons(AnyStr, Filter(Cons("layer", Cons(AnyStr, Nothing)))),
+        );
+        let layers = Cons(Discard("kicad_pcb"), Filter(footprint));
+
+        let expr = parse("<input>", PCB).unwrap();
+        let owned = parse_with(PCB, parse_s_expr).unwrap();
+        let result = layers.simplify_ref(&expr).unwrap();
+        assert_eq!(result.to_expr(PCB), layers.simplify(&owned).unwrap());
+        assert_eq!(result.as_list().unwrap().len(), 2);
+
+        let anything = Cons("kicad_pcb", Anything);
+        assert_eq!(
+            anything.simplify_ref(&expr).unwrap().to_expr(PCB),
+            anything.simplify(&owned).unwrap()
+        );
+        assert!(Cons("kicad_sch", Anything).simplify_ref(&expr).is_none());
+    }
+}
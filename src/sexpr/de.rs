@@ -0,0 +1,1103 @@
+//! Deserialize Rust values from S-expressions following KiCad's conventions.
+//!
+//! A list headed by a symbol, such as `(pad "1" smd rect (at 1 2) (layers "F.Cu" "F.Mask"))`,
+//! deserializes as a struct:
+//!
+//! - a child list `(key value)` gives the field `key`,
+//! - a field whose name begins with `_` is positional and takes the next argument
+//!   that is not a child list, so `_number`, `_type` and `_shape` above are `1`, `smd` and `rect`,
+//! - a bare symbol naming a field, as in the older `(effects (font (size 1 1)) hide)`, sets it to true.
+//!
+//! The value of a field depends on its type:
+//!
+//! |Field type|Deserialized from|
+//! |---|---|
+//! |`bool`|`(key yes)` or `(key no)`, `(key)` meaning true and a missing key false|
+//! |number or string|the single argument of `(key value)`|
+//! |struct|the child list itself|
+//! |tuple|the arguments, as `(at 1 2 90)`|
+//! |`Option`|`None` if the key is missing|
+//! |`Vec` of numbers, strings or unit enums|the arguments of every `(key ...)`, as `(layers "F.Cu" "F.Mask")`|
+//! |other `Vec`|one element for each time the key is repeated, none if it is missing|
+//!
+//! A unit enum variant is read from a symbol or string, and any other variant
+//! from a list headed by the variant name.
+use super::{Atom, Expr};
+use serde::de::{
+    self, value::BorrowedStrDeserializer, DeserializeSeed, EnumAccess, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::{forward_to_deserialize_any, Deserialize};
+use std::{collections::VecDeque, fmt::Display};
+
+/// A problem converting between S-expressions and Rust values.
+#[derive(Clone, PartialEq)]
+pub struct Error {
+    message: String,
+    located: bool,
+}
+
+impl Error {
+    /// An error at the location of `expr`, if known.
+    fn at(expr: &Expr, message: &str) -> Error {
+        Error {
+            message: expr.error(message),
+            located: expr.span().is_some(),
+        }
+    }
+
+    /// Add the location of `expr` unless a more precise one is already given.
+    fn locate(self, expr: &Expr) -> Error {
+        if self.located {
+            self
+        } else {
+            Error::at(expr, &self.message)
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.message.fmt(f)
+    }
+}
+
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error {
+            message: msg.to_string(),
+            located: false,
+        }
+    }
+}
+
+impl serde::ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        <Error as de::Error>::custom(msg)
+    }
+}
+
+/// Deserialize a value from an S-expression.
+pub fn from_expr<'de, T: Deserialize<'de>>(expr: &'de Expr) -> Result<T, Error> {
+    T::deserialize(Deserializer::new(expr))
+}
+
+/// The value of a flag such as `(hide)`.
+static TRUE: Expr = Expr::Constant(Atom::Bool(true), None);
+
+/// The value of a flag that is absent.
+static FALSE: Expr = Expr::Constant(Atom::Bool(false), None);
+
+/// Deserializes a whole expression.
+#[derive(Clone, Copy)]
+pub struct Deserializer<'de> {
+    expr: &'de Expr,
+}
+
+impl<'de> Deserializer<'de> {
+    pub fn new(expr: &'de Expr) -> Self {
+        Self { expr }
+    }
+
+    fn list(&self) -> Result<&'de VecDeque<Expr>, Error> {
+        self.expr
+            .as_list()
+            .ok_or_else(|| Error::at(self.expr, "expected a list"))
+    }
+}
+
+/// The elements of a list after its head symbol, if it has one.
+fn items(expr: &Expr) -> impl Iterator<Item = &Expr> {
+    let skip = usize::from(expr.head().is_some());
+    expr.as_list()
+        .into_iter()
+        .flat_map(move |xs| xs.iter().skip(skip))
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.expr {
+            Expr::Constant(atom, _) => match atom {
+                Atom::Symbol(s) | Atom::Str(s) => visitor.visit_borrowed_str(s),
+                Atom::Num(n) => {
+                    let text = n.text().unwrap_or_default();
+                    let n = n.value();
+                    if let Ok(i) = text.parse::<i64>() {
+                        visitor.visit_i64(i)
+                    } else if let Ok(u) = text.parse::<u64>() {
+                        visitor.visit_u64(u)
+                    } else if n.fract() == 0.0 && n.abs() < 9e15 {
+                        visitor.visit_i64(n as i64)
+                    } else {
+                        visitor.visit_f64(n)
+                    }
+                }
+                Atom::Bits(n) => visitor.visit_u64(n.value()),
+                Atom::Bool(b) => visitor.visit_bool(*b),
+                Atom::Uuid(u) => visitor.visit_string(u.to_string()),
+            },
+            Expr::List(..) if self.expr.head().is_some() => self.deserialize_map(visitor),
+            Expr::List(..) => self.deserialize_seq(visitor),
+        }
+        .map_err(|e| e.locate(self.expr))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.expr.as_atom() {
+            Some(Atom::Bool(b)) => visitor.visit_bool(*b),
+            Some(Atom::Symbol(s)) if s == "yes" || s == "true" => visitor.visit_bool(true),
+            Some(Atom::Symbol(s)) if s == "no" || s == "false" => visitor.visit_bool(false),
+            _ => Err(Error::at(self.expr, "expected yes or no")),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.expr.as_atom() {
+            Some(Atom::Num(n)) => match n.text() {
+                Some(text) => visitor.visit_str(text),
+                None => visitor.visit_string(n.value().to_string()),
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_tuple(0, visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.list()?;
+        visitor
+            .visit_seq(Items {
+                items: items(self.expr),
+                len,
+            })
+            .map_err(|e| e.locate(self.expr))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.list()?;
+        visitor
+            .visit_map(Fields::new(self.expr, &[]))
+            .map_err(|e| e.locate(self.expr))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.list()?;
+        visitor
+            .visit_map(Fields::new(self.expr, fields))
+            .map_err(|e| e.locate(self.expr))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.expr.as_atom() {
+            Some(Atom::Symbol(s) | Atom::Str(s)) => {
+                let name: BorrowedStrDeserializer<Error> = BorrowedStrDeserializer::new(s);
+                visitor.visit_enum(name)
+            }
+            Some(_) => Err(Error::at(self.expr, "expected a symbol")),
+            None if self.expr.head().is_some() => visitor.visit_enum(self),
+            None => Err(Error::at(self.expr, "expected a list headed by a symbol")),
+        }
+        .map_err(|e| e.locate(self.expr))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        bytes byte_buf identifier
+    }
+}
+
+/// A list headed by a variant name is an enum value.
+impl<'de> EnumAccess<'de> for Deserializer<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, Self), Error> {
+        let head = self.expr.head().unwrap_or_default();
+        let name: BorrowedStrDeserializer<Error> = BorrowedStrDeserializer::new(head);
+        Ok((seed.deserialize(name)?, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Deserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_struct(self, "", fields, visitor)
+    }
+}
+
+/// The elements of a list as a sequence.
+/// A tuple of length `len` may leave out trailing `Option` elements, as in `(at 1 2)`.
+struct Items<I> {
+    items: I,
+    len: usize,
+}
+
+impl<'de, I> SeqAccess<'de> for Items<I>
+where
+    I: Iterator<Item = &'de Expr>,
+{
+    type Error = Error;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Error> {
+        let missing = self.len > 0;
+        self.len = self.len.saturating_sub(1);
+        match self.items.next() {
+            Some(x) => seed.deserialize(Deserializer::new(x)).map(Some),
+            None if missing => seed.deserialize(Missing).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A tuple element beyond the end of a list.
+struct Missing;
+
+impl<'de> de::Deserializer<'de> for Missing {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom("too few values"))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_none()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple tuple_struct
+        map struct enum identifier ignored_any
+    }
+}
+
+/// The value of one struct field or map entry.
+enum Entry<'de> {
+    /// A positional argument.
+    Arg(&'de Expr),
+    /// Each child list with the field's key, which may be none.
+    Keyed(Vec<&'de Expr>),
+}
+
+/// The fields of a list, for a struct or a map.
+struct Fields<'de> {
+    list: &'de Expr,
+    entries: VecDeque<(&'de str, Entry<'de>)>,
+}
+
+impl<'de> Fields<'de> {
+    /// Gather the fields named in `fields` in that order followed by any other keys.
+    fn new(list: &'de Expr, fields: &'static [&'static str]) -> Self {
+        let mut keyed: Vec<(&'de str, Vec<&'de Expr>)> = Vec::new();
+        let mut args: VecDeque<&'de Expr> = VecDeque::new();
+        for item in items(list) {
+            match item.head() {
+                Some(key) => match keyed.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, found)) => found.push(item),
+                    None => keyed.push((key, vec![item])),
+                },
+                None => args.push_back(item),
+            }
+        }
+
+        // bare symbols naming a field are flags
+        args.retain(|arg| {
+            let flag = arg
+                .as_atom()
+                .and_then(Atom::as_symbol)
+                .filter(|s| !s.starts_with('_') && fields.contains(s))
+                .filter(|s| !keyed.iter().any(|(k, _)| k == s));
+            match flag {
+                Some(name) => {
+                    keyed.push((name, vec![&TRUE]));
+                    false
+                }
+                None => true,
+            }
+        });
+
+        let mut entries = VecDeque::new();
+        for &field in fields {
+            if field.starts_with('_') {
+                if let Some(arg) = args.pop_front() {
+                    entries.push_back((field, Entry::Arg(arg)));
+                }
+            } else {
+                let found = match keyed.iter().position(|(k, _)| *k == field) {
+                    Some(i) => keyed.remove(i).1,
+                    None => Vec::new(),
+                };
+                entries.push_back((field, Entry::Keyed(found)));
+            }
+        }
+        entries.extend(keyed.into_iter().map(|(k, xs)| (k, Entry::Keyed(xs))));
+
+        Self { list, entries }
+    }
+}
+
+impl<'de> MapAccess<'de> for Fields<'de> {
+    type Error = Error;
+
+    fn next_key_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Error> {
+        match self.entries.front() {
+            Some((key, _)) => {
+                let key: BorrowedStrDeserializer<Error> = BorrowedStrDeserializer::new(key);
+                seed.deserialize(key).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value, Error> {
+        let (key, entry) = self
+            .entries
+            .pop_front()
+            .ok_or_else(|| <Error as de::Error>::custom("value without a key"))?;
+        match entry {
+            Entry::Arg(arg) => seed.deserialize(Deserializer::new(arg)),
+            Entry::Keyed(found) => seed.deserialize(Field {
+                key,
+                list: self.list,
+                found,
+            }),
+        }
+    }
+}
+
+/// Deserialize each method by forwarding it to a single value found by `self.value()`.
+macro_rules! forward_to_value {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                de::Deserializer::$method(self.value()?, visitor)
+            }
+        )*
+    };
+}
+
+/// A field given by the child lists with its key.
+struct Field<'de> {
+    key: &'de str,
+    list: &'de Expr,
+    found: Vec<&'de Expr>,
+}
+
+impl<'de> Field<'de> {
+    /// The first child with the key, as the only value of the field.
+    fn value(&self) -> Result<Child<'de>, Error> {
+        match self.found.first() {
+            Some(child) => Ok(Child(child)),
+            None => Err(Error::at(self.list, &format!("missing ({} ...)", self.key))),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Field<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.found.len() > 1 {
+            self.deserialize_seq(visitor)
+        } else {
+            self.value()?.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.found.first() {
+            Some(child) => Child(child).deserialize_bool(visitor),
+            None => Deserializer::new(&FALSE).deserialize_bool(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.found.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(Repeated {
+            found: self.found.into(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.value()?.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value()?.deserialize_tuple_struct(name, len, visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value()?.deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value()?.deserialize_unit_struct(name, visitor)
+    }
+
+    forward_to_value! {
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_unit deserialize_map
+        deserialize_identifier
+    }
+}
+
+/// A child list `(key ...)` as the value of its field.
+struct Child<'de>(&'de Expr);
+
+impl<'de> Child<'de> {
+    /// The single argument of the child, with `(key)` meaning true.
+    fn value(&self) -> Result<Deserializer<'de>, Error> {
+        let mut args = self.0.args();
+        match (args.next(), args.next()) {
+            (None, _) => Ok(Deserializer::new(&TRUE)),
+            (Some(arg), None) => Ok(Deserializer::new(arg)),
+            (Some(_), Some(_)) => Err(Error::at(self.0, "expected a single value")),
+        }
+    }
+
+    fn whole(&self) -> Deserializer<'de> {
+        Deserializer::new(self.0)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Child<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value() {
+            Ok(value) if value.expr.as_atom().is_some() => value.deserialize_any(visitor),
+            _ => self.whole().deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.whole().deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.whole().deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.whole().deserialize_tuple_struct(name, len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.whole().deserialize_map(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.whole().deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value()?.deserialize_unit_struct(name, visitor)
+    }
+
+    forward_to_value! {
+        deserialize_bool
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_unit deserialize_identifier
+    }
+}
+
+/// The children with a repeated key as a sequence.
+/// Elements that deserialize as single values take every argument of
+/// each child in turn, so `(layers "F.Cu" "F.Mask")` gives two strings.
+struct Repeated<'de> {
+    found: VecDeque<&'de Expr>,
+    pending: VecDeque<&'de Expr>,
+}
+
+impl<'de> SeqAccess<'de> for Repeated<'de> {
+    type Error = Error;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Error> {
+        if let Some(arg) = self.pending.pop_front() {
+            return seed.deserialize(Deserializer::new(arg)).map(Some);
+        }
+        match self.found.pop_front() {
+            Some(child) => seed
+                .deserialize(Element {
+                    child,
+                    pending: &mut self.pending,
+                })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// One child in a sequence of children with the same key.
+struct Element<'a, 'de> {
+    child: &'de Expr,
+    pending: &'a mut VecDeque<&'de Expr>,
+}
+
+impl<'de> Element<'_, 'de> {
+    /// The first argument of the child, leaving the others to follow in the sequence.
+    fn value(self) -> Result<Deserializer<'de>, Error> {
+        let mut args = self.child.args();
+        match args.next() {
+            Some(first) => {
+                self.pending.extend(args);
+                Ok(Deserializer::new(first))
+            }
+            None => Child(self.child).value(),
+        }
+    }
+
+    /// Are the arguments all atoms, so that they are the elements of the sequence?
+    fn is_flat(&self) -> bool {
+        self.child.args().all(|x| x.as_atom().is_some())
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Element<'_, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.is_flat() {
+            self.value()?.deserialize_any(visitor)
+        } else {
+            Child(self.child).deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        Child(self.child).deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        Child(self.child).deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        Child(self.child).deserialize_tuple_struct(name, len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        Child(self.child).deserialize_map(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        Child(self.child).deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        if self.is_flat() {
+            self.value()?.deserialize_enum(name, variants, visitor)
+        } else {
+            Child(self.child).deserialize_enum(name, variants, visitor)
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value()?.deserialize_unit_struct(name, visitor)
+    }
+
+    forward_to_value! {
+        deserialize_bool
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_unit deserialize_identifier
+    }
+}
+
+/// Deserialize a struct or map from named values, such as the captures of a simplifier.
+/// A name may be given any number of times.  Given once, it is a single value
+/// or a sequence of one, and not given at all it is `None` or an empty sequence.
+pub fn from_named<'de, T: Deserialize<'de>>(named: &[(&'de str, &'de Expr)]) -> Result<T, Error> {
+    T::deserialize(Named(named.to_vec()))
+}
+
+/// Named values as a struct or map.
+struct Named<'de>(Vec<(&'de str, &'de Expr)>);
+
+impl<'de> Named<'de> {
+    /// Each name with its values, with those in `fields` first even if not given.
+    fn entries(self, fields: &[&'static str]) -> NamedEntries<'de> {
+        let mut entries: VecDeque<(&'de str, Vec<&'de Expr>)> =
+            fields.iter().map(|&field| (field, Vec::new())).collect();
+        for (name, value) in self.0 {
+            match entries.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, values)) => values.push(value),
+                None => entries.push_back((name, vec![value])),
+            }
+        }
+        NamedEntries(entries)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Named<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(self.entries(&[]))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_map(self.entries(fields))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct
+        map enum identifier ignored_any
+    }
+}
+
+struct NamedEntries<'de>(VecDeque<(&'de str, Vec<&'de Expr>)>);
+
+impl<'de> MapAccess<'de> for NamedEntries<'de> {
+    type Error = Error;
+
+    fn next_key_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Error> {
+        match self.0.front() {
+            Some((name, _)) => {
+                let name: BorrowedStrDeserializer<Error> = BorrowedStrDeserializer::new(name);
+                seed.deserialize(name).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value, Error> {
+        let (name, values) = self
+            .0
+            .pop_front()
+            .ok_or_else(|| <Error as de::Error>::custom("value without a name"))?;
+        seed.deserialize(Values { name, values })
+    }
+}
+
+/// The values given for one name.
+struct Values<'de> {
+    name: &'de str,
+    values: Vec<&'de Expr>,
+}
+
+impl<'de> Values<'de> {
+    /// The first value, as the only value of a field.
+    fn value(&self) -> Result<Deserializer<'de>, Error> {
+        match self.values.first() {
+            Some(value) => Ok(Deserializer::new(value)),
+            None => Err(de::Error::custom(format!("nothing named {}", self.name))),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Values<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.values.len() > 1 {
+            self.deserialize_seq(visitor)
+        } else {
+            self.value()?.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.values.first() {
+            Some(value) => Deserializer::new(value).deserialize_bool(visitor),
+            None => Deserializer::new(&FALSE).deserialize_bool(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.values.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(Items {
+            items: self.values.into_iter(),
+            len: 0,
+        })
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.value()?.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value()?.deserialize_tuple_struct(name, len, visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value()?.deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value()?.deserialize_unit_struct(name, visitor)
+    }
+
+    forward_to_value! {
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_unit deserialize_map
+        deserialize_identifier
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::from_expr;
+    use crate::{parse_file::parse_with, sexpr::parser::parse_s_expr};
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum PadType {
+        Smd,
+        ThruHole,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Pad {
+        #[serde(rename = "_number")]
+        number: String,
+        #[serde(rename = "_type")]
+        pad_type: PadType,
+        #[serde(rename = "_shape")]
+        shape: String,
+        at: (f64, f64, Option<f64>),
+        size: (f64, f64),
+        drill: Option<f64>,
+        layers: Vec<String>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Property {
+        #[serde(rename = "_name")]
+        name: String,
+        #[serde(rename = "_value")]
+        value: String,
+        effects: Option<Effects>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Effects {
+        hide: bool,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Footprint {
+        #[serde(rename = "_lib_id")]
+        lib_id: String,
+        layer: String,
+        locked: bool,
+        property: Vec<Property>,
+        pad: Vec<Pad>,
+    }
+
+    const FOOTPRINT: &str = r#"
+        (footprint "Resistor_SMD:R_0603" (layer "F.Cu") (locked yes) (at 100 100)
+            (property "Reference" "R1" (at 0 -1.43 0) (effects (font (size 1 1)) (hide yes)))
+            (property "Value" "10k" (at 0 1.43 0) (effects (font (size 1 1)) hide))
+            (pad "1" smd roundrect (at -0.8 0) (size 0.8 0.95) (layers "F.Cu" "F.Paste" "F.Mask"))
+            (pad "2" thru_hole circle (at 0.8 0 90) (size 1.7 1.7) (drill 1) (layers "*.Cu")))
+    "#;
+
+    #[test]
+    fn test_footprint() {
+        let expr = parse_with(FOOTPRINT, parse_s_expr).unwrap();
+        let footprint: Footprint = from_expr(&expr).unwrap();
+        assert_eq!(footprint.lib_id, "Resistor_SMD:R_0603");
+        assert!(footprint.locked);
+        assert_eq!(footprint.property[1].value, "10k");
+        assert_eq!(footprint.property[1].effects, Some(Effects { hide: true }));
+        assert_eq!(
+            footprint.pad[1],
+            Pad {
+                number: "2".to_owned(),
+                pad_type: PadType::ThruHole,
+                shape: "circle".to_owned(),
+                at: (0.8, 0.0, Some(90.0)),
+                size: (1.7, 1.7),
+                drill: Some(1.0),
+                layers: vec!["*.Cu".to_owned()],
+            }
+        );
+        assert_eq!(footprint.pad[0].layers, ["F.Cu", "F.Paste", "F.Mask"]);
+        assert_eq!(footprint.pad[0].at.2, None);
+    }
+
+    #[test]
+    fn test_map() {
+        let expr = parse_with(r#"(title_block (title "Board") (rev 3))"#, parse_s_expr).unwrap();
+        let fields: BTreeMap<String, String> = from_expr(&expr).unwrap();
+        assert_eq!(fields["title"], "Board");
+        assert_eq!(fields["rev"], "3");
+    }
+
+    #[test]
+    fn test_errors() {
+        let expr = parse_with("(pad \"1\" smd rect (at x 0) (size 1 1))", parse_s_expr).unwrap();
+        let err = from_expr::<Pad>(&expr).unwrap_err().to_string();
+        assert!(err.starts_with("1:23: invalid type"), "{err}");
+
+        let expr = parse_with("(pad \"1\" smd rect (at 0 0))", parse_s_expr).unwrap();
+        let err = from_expr::<Pad>(&expr).unwrap_err().to_string();
+        assert!(err.ends_with("missing (size ...)"), "{err}");
+    }
+}
@@ -0,0 +1,556 @@
+//! Structural differences between two versions of a KiCad file.
+//!
+//! The items within each list are aligned by their `uuid` (or `tstamp`),
+//! or failing that by their `Reference` property or name.  Items with none
+//! of these are matched by being equal.  Aligned items are compared element
+//! by element so that a change is reported in terms of what it means:
+//! a property value, a position, an attribute or a net connection.
+use super::{analysis::property_value, netlist::netlist, Atom, Expr};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::{self, Display, Formatter};
+
+/// One difference between two files.
+///
+/// Items are labelled by their kind and identity, such as `symbol R12`,
+/// and nested items by the path to them, such as `footprint U3 pad 1`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    Added {
+        item: String,
+    },
+    Removed {
+        item: String,
+    },
+    Property {
+        item: String,
+        name: String,
+        from: Option<String>,
+        to: Option<String>,
+    },
+    Moved {
+        item: String,
+        by: (f64, f64),
+    },
+    Rotated {
+        item: String,
+        by: f64,
+    },
+    Changed {
+        item: String,
+        from: String,
+        to: String,
+    },
+    NetGained {
+        net: String,
+        pin: String,
+    },
+    NetLost {
+        net: String,
+        pin: String,
+    },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { item } => write!(f, "added {item}"),
+            Change::Removed { item } => write!(f, "removed {item}"),
+            Change::Property {
+                item,
+                name,
+                from,
+                to,
+            } => match (from, to) {
+                (Some(from), Some(to)) => write!(f, "{item} {name} {from} → {to}"),
+                (None, Some(to)) => write!(f, "{item} {name} added as {to}"),
+                (Some(from), None) => write!(f, "{item} {name} removed, was {from}"),
+                (None, None) => write!(f, "{item} {name}"),
+            },
+            Change::Moved { item, by: (x, y) } => write!(f, "{item} moved by ({x}, {y})"),
+            Change::Rotated { item, by } => write!(f, "{item} rotated by {by}"),
+            Change::Changed { item, from, to } => write!(f, "{item} {from} → {to}"),
+            Change::NetGained { net, pin } => write!(f, "net {net} gained pin {pin}"),
+            Change::NetLost { net, pin } => write!(f, "net {net} lost pin {pin}"),
+        }
+    }
+}
+
+/// The changes that turn `old` into `new`.
+pub fn diff(old: &Expr, new: &Expr) -> Vec<Change> {
+    let mut changes = Vec::new();
+    compare("", old, new, &mut changes);
+    compare_nets(old, new, &mut changes);
+    changes
+}
+
+/// Compare two versions of one item.
+fn compare(label: &str, old: &Expr, new: &Expr, changes: &mut Vec<Change>) {
+    if old == new {
+        return;
+    }
+    let (Some(old_elems), Some(new_elems)) = (old.as_list(), new.as_list()) else {
+        changes.push(Change::Changed {
+            item: label.to_owned(),
+            from: text(old),
+            to: text(new),
+        });
+        return;
+    };
+
+    let atoms = |elems: &VecDeque<Expr>| -> Vec<String> {
+        elems
+            .iter()
+            .filter(|e| e.as_atom().is_some())
+            .map(text)
+            .collect()
+    };
+    let (old_atoms, new_atoms) = (atoms(old_elems), atoms(new_elems));
+    if old_atoms != new_atoms {
+        changes.push(Change::Changed {
+            item: label.to_owned(),
+            from: old_atoms.join(" "),
+            to: new_atoms.join(" "),
+        });
+    }
+
+    compare_position(label, old.child("at"), new.child("at"), changes);
+    compare_properties(label, old, new, changes);
+
+    let mut old_groups = groups(old);
+    let mut new_groups = groups(new);
+    let heads: BTreeSet<&str> = old_groups
+        .keys()
+        .chain(new_groups.keys())
+        .copied()
+        .collect();
+    let mut single = Vec::new();
+    let mut repeated = (Vec::new(), Vec::new());
+    for head in heads {
+        let old_items = old_groups.remove(head).unwrap_or_default();
+        let new_items = new_groups.remove(head).unwrap_or_default();
+        if old_items.len() <= 1 && new_items.len() <= 1 {
+            single.push((head, old_items.first().copied(), new_items.first().copied()));
+        } else {
+            repeated.0.extend(old_items);
+            repeated.1.extend(new_items);
+        }
+    }
+
+    for (head, old_item, new_item) in single {
+        let item = join(label, head);
+        match (old_item, new_item) {
+            (Some(old_item), Some(new_item)) if is_simple(old_item) && is_simple(new_item) => {
+                if old_item != new_item {
+                    changes.push(Change::Changed {
+                        item,
+                        from: args_text(old_item),
+                        to: args_text(new_item),
+                    });
+                }
+            }
+            (Some(old_item), Some(new_item)) => {
+                if has_identity(old_item) || has_identity(new_item) {
+                    compare_items(label, vec![old_item], vec![new_item], changes);
+                } else {
+                    compare(&item, old_item, new_item, changes);
+                }
+            }
+            (Some(old_item), None) => changes.push(Change::Removed {
+                item: describe(label, old_item),
+            }),
+            (None, Some(new_item)) => changes.push(Change::Added {
+                item: describe(label, new_item),
+            }),
+            (None, None) => (),
+        }
+    }
+    compare_items(label, repeated.0, repeated.1, changes);
+}
+
+/// Align two lists of items and compare those that correspond.
+fn compare_items(label: &str, old: Vec<&Expr>, new: Vec<&Expr>, changes: &mut Vec<Change>) {
+    let old = keyed(old);
+    let new = keyed(new);
+    let new_keys: BTreeMap<&String, &Expr> = new
+        .iter()
+        .filter_map(|(key, item)| Some((key.as_ref()?, *item)))
+        .collect();
+    let old_keys: BTreeSet<&String> = old.iter().filter_map(|(key, _)| key.as_ref()).collect();
+
+    let mut old_unkeyed = Vec::new();
+    for (key, item) in &old {
+        match key {
+            Some(key) => match new_keys.get(key) {
+                Some(new_item) => compare(&describe(label, new_item), item, new_item, changes),
+                None => changes.push(Change::Removed {
+                    item: describe(label, item),
+                }),
+            },
+            None => old_unkeyed.push(*item),
+        }
+    }
+
+    let mut new_unkeyed = Vec::new();
+    for (key, item) in &new {
+        match key {
+            Some(key) if !old_keys.contains(key) => changes.push(Change::Added {
+                item: describe(label, item),
+            }),
+            Some(_) => (),
+            None => new_unkeyed.push(*item),
+        }
+    }
+
+    for item in old_unkeyed {
+        match new_unkeyed.iter().position(|other| *other == item) {
+            Some(index) => {
+                new_unkeyed.remove(index);
+            }
+            None => changes.push(Change::Removed {
+                item: describe(label, item),
+            }),
+        }
+    }
+    for item in new_unkeyed {
+        changes.push(Change::Added {
+            item: describe(label, item),
+        });
+    }
+}
+
+/// Report a change of position as a move and a rotation.
+fn compare_position(
+    label: &str,
+    old: Option<&Expr>,
+    new: Option<&Expr>,
+    changes: &mut Vec<Change>,
+) {
+    let (Some(old), Some(new)) = (old.map(numbers), new.map(numbers)) else {
+        return;
+    };
+    let at = |values: &[f64], index: usize| values.get(index).copied().unwrap_or_default();
+    let (dx, dy) = (at(&new, 0) - at(&old, 0), at(&new, 1) - at(&old, 1));
+    if round(dx) != 0.0 || round(dy) != 0.0 {
+        changes.push(Change::Moved {
+            item: label.to_owned(),
+            by: (round(dx), round(dy)),
+        });
+    }
+    let mut turn = (at(&new, 2) - at(&old, 2)) % 360.0;
+    if turn > 180.0 {
+        turn -= 360.0;
+    } else if turn <= -180.0 {
+        turn += 360.0;
+    }
+    if round(turn) != 0.0 {
+        changes.push(Change::Rotated {
+            item: label.to_owned(),
+            by: round(turn),
+        });
+    }
+}
+
+/// Report changes to the values of properties, matched by name.
+fn compare_properties(label: &str, old: &Expr, new: &Expr, changes: &mut Vec<Change>) {
+    let values = |item: &Expr| -> BTreeMap<String, String> {
+        item.children("property")
+            .filter_map(|p| {
+                let mut args = p.args();
+                Some((text(args.next()?), text(args.next()?)))
+            })
+            .collect()
+    };
+    let (old, new) = (values(old), values(new));
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    for name in names {
+        let (from, to) = (old.get(name), new.get(name));
+        if from != to {
+            changes.push(Change::Property {
+                item: label.to_owned(),
+                name: name.clone(),
+                from: from.cloned(),
+                to: to.cloned(),
+            });
+        }
+    }
+}
+
+/// Report pins that joined or left each net.
+fn compare_nets(old: &Expr, new: &Expr, changes: &mut Vec<Change>) {
+    let (Some(old), Some(new)) = (net_pins(old), net_pins(new)) else {
+        return;
+    };
+    // A pin left unconnected is already reported as leaving its net
+    let nets: BTreeSet<&String> = old
+        .keys()
+        .chain(new.keys())
+        .filter(|net| !net.starts_with("unconnected-"))
+        .collect();
+    let none = BTreeSet::new();
+    for net in nets {
+        let from = old.get(net).unwrap_or(&none);
+        let to = new.get(net).unwrap_or(&none);
+        for pin in to.difference(from) {
+            changes.push(Change::NetGained {
+                net: net.clone(),
+                pin: pin.clone(),
+            });
+        }
+        for pin in from.difference(to) {
+            changes.push(Change::NetLost {
+                net: net.clone(),
+                pin: pin.clone(),
+            });
+        }
+    }
+}
+
+/// The pins, written `reference.pin`, on each net of a schematic or board.
+fn net_pins(file: &Expr) -> Option<BTreeMap<String, BTreeSet<String>>> {
+    let mut nets: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    match file.head()? {
+        "kicad_sch" => {
+            for net in netlist(file)? {
+                let pins = net
+                    .nodes
+                    .iter()
+                    .map(|node| format!("{}.{}", node.reference, node.pin));
+                nets.entry(net.name).or_default().extend(pins);
+            }
+        }
+        "kicad_pcb" => {
+            for footprint in file.children("footprint") {
+                let Some(reference) = reference_of(footprint) else {
+                    continue;
+                };
+                for pad in footprint.children("pad") {
+                    let net = pad
+                        .child("net")
+                        .and_then(|net| net.args().filter_map(|a| a.as_atom()?.as_string()).last());
+                    if let (Some(number), Some(net)) = (pad.str_arg(), net) {
+                        nets.entry(net.to_owned())
+                            .or_default()
+                            .insert(format!("{reference}.{number}"));
+                    }
+                }
+            }
+        }
+        _ => return None,
+    }
+    Some(nets)
+}
+
+/// The children of an item that are lists, by head, except those compared separately.
+fn groups(item: &Expr) -> BTreeMap<&str, Vec<&Expr>> {
+    let mut groups: BTreeMap<&str, Vec<&Expr>> = BTreeMap::new();
+    for child in item.args().filter(|c| c.as_list().is_some()) {
+        let head = child.head().unwrap_or_default();
+        if !matches!(head, "at" | "uuid" | "tstamp" | "property") {
+            groups.entry(head).or_default().push(child);
+        }
+    }
+    groups
+}
+
+/// Items paired with the keys that align them, made unique by counting repeats.
+fn keyed(items: Vec<&Expr>) -> Vec<(Option<String>, &Expr)> {
+    let mut seen: BTreeMap<String, usize> = BTreeMap::new();
+    items
+        .into_iter()
+        .map(|item| {
+            let key = key_of(item).map(|key| {
+                let count = seen.entry(key.clone()).or_default();
+                *count += 1;
+                if *count == 1 {
+                    key
+                } else {
+                    format!("{key}#{count}")
+                }
+            });
+            (key, item)
+        })
+        .collect()
+}
+
+fn key_of(item: &Expr) -> Option<String> {
+    let head = item.head().unwrap_or_default();
+    match uuid_of(item) {
+        Some(uuid) => Some(format!("{head} {uuid}")),
+        None => Some(format!("{head} {}", name_of(item)?)),
+    }
+}
+
+fn has_identity(item: &Expr) -> bool {
+    uuid_of(item).is_some() || reference_of(item).is_some()
+}
+
+fn uuid_of(item: &Expr) -> Option<String> {
+    let uuid = item.child("uuid").or(item.child("tstamp"))?;
+    Some(text(uuid.args().next()?))
+}
+
+fn reference_of(item: &Expr) -> Option<String> {
+    let reference = property_value(item, "Reference").or_else(|| {
+        item.children("fp_text")
+            .find(|t| t.symbol_arg() == Some("reference"))?
+            .args()
+            .nth(1)?
+            .as_atom()?
+            .as_string()
+    })?;
+    Some(reference.to_owned())
+}
+
+/// The reference of an item or its first string argument.
+fn name_of(item: &Expr) -> Option<String> {
+    reference_of(item).or_else(|| {
+        let name = item.args().find_map(|a| a.as_atom()?.as_string())?;
+        Some(name.to_owned())
+    })
+}
+
+/// A label for an item: its kind followed by its name, position or uuid.
+fn describe(label: &str, item: &Expr) -> String {
+    let head = item.head().unwrap_or("item");
+    let identity = name_of(item)
+        .or_else(|| {
+            let at = numbers(item.child("at").or(item.child("start"))?);
+            Some(format!("at ({}, {})", at.first()?, at.get(1)?))
+        })
+        .or_else(|| {
+            let xy = numbers(item.child("pts")?.child("xy")?);
+            Some(format!("at ({}, {})", xy.first()?, xy.get(1)?))
+        })
+        .or_else(|| uuid_of(item));
+    match identity {
+        Some(identity) => join(label, &format!("{head} {identity}")),
+        None => join(label, head),
+    }
+}
+
+fn join(label: &str, item: &str) -> String {
+    if label.is_empty() {
+        item.to_owned()
+    } else {
+        format!("{label} {item}")
+    }
+}
+
+/// Is this a list of atoms such as `(layer "F.Cu")`?
+fn is_simple(item: &Expr) -> bool {
+    item.args().all(|a| a.as_atom().is_some())
+}
+
+fn args_text(item: &Expr) -> String {
+    item.args().map(text).collect::<Vec<_>>().join(" ")
+}
+
+fn numbers(item: &Expr) -> Vec<f64> {
+    item.args()
+        .map_while(|a| a.as_atom().and_then(Atom::as_num))
+        .collect()
+}
+
+/// Round away floating point noise, and negative zero.
+fn round(value: f64) -> f64 {
+    (value * 10_000.0).round() / 10_000.0 + 0.0
+}
+
+/// An atom as plain text, or a list as an S-expression.
+fn text(expr: &Expr) -> String {
+    match expr.as_atom() {
+        Some(Atom::Str(s)) => s.clone(),
+        _ => expr.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::diff;
+    use crate::{parse_file::parse_with, sexpr::parser::parse_s_expr, sexpr::Expr};
+
+    fn schematic(value: &str, wire: &str) -> Expr {
+        let s = format!(
+            r##"
+            (kicad_sch
+                (version 20250114)
+                (lib_symbols
+                    (symbol "Device:R"
+                        (symbol "R_1_1"
+                            (pin passive line (at 0 3.81 270) (length 1.27)
+                                (name "~") (number "1"))
+                            (pin passive line (at 0 -3.81 90) (length 1.27)
+                                (name "~") (number "2"))
+                        )
+                    )
+                )
+                (wire (pts (xy 100 103.81) (xy 106.19 103.81)))
+                {wire}
+                (label "SIG" (at 103 103.81 0))
+                (symbol (lib_id "Device:R") (at 100 100 0) (unit 1)
+                    (uuid "11111111-0000-0000-0000-000000000000")
+                    (property "Reference" "R1") (property "Value" "{value}"))
+                (symbol (lib_id "Device:R") (at 110 100 90) (unit 1)
+                    (uuid "22222222-0000-0000-0000-000000000000")
+                    (property "Reference" "R2") (property "Value" "4k7"))
+            )
+        "##
+        );
+        parse_with(&s, parse_s_expr).unwrap()
+    }
+
+    fn report(old: &Expr, new: &Expr) -> Vec<String> {
+        diff(old, new).iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn test_schematic() {
+        let wire = "(wire (pts (xy 106.19 103.81) (xy 106.19 100)))";
+        let old = schematic("10k", wire);
+        assert!(diff(&old, &old).is_empty());
+        let new = schematic("1k", "");
+        assert_eq!(
+            report(&old, &new),
+            [
+                "symbol R1 Value 10k → 1k",
+                "removed wire at (106.19, 103.81)",
+                "net /SIG lost pin R2.1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_board() {
+        let board = |at: &str, net: &str| {
+            let s = format!(
+                r#"
+                (kicad_pcb (version 20240108)
+                    (footprint "Device:R_0603" (layer "F.Cu") (uuid "u3") (at {at})
+                        (property "Reference" "U3" (uuid "p1"))
+                        (pad "1" smd rect (at 0 0) (net 1 "GND") (uuid "pad1")))
+                    (footprint "Conn:J" (layer "F.Cu") (uuid "j1") (at 0 0 0)
+                        (property "Reference" "J1")
+                        (pad "3" thru_hole circle (at 0 0) (net {net}) (uuid "jp3"))))
+                "#
+            );
+            parse_with(&s, parse_s_expr).unwrap()
+        };
+        let old = board("10 20 0", r#"2 "VIN""#);
+        let new = board("11.2 20 270", r#"1 "GND""#);
+        assert_eq!(
+            report(&old, &new),
+            [
+                "footprint U3 moved by (1.2, 0)",
+                "footprint U3 rotated by -90",
+                "footprint J1 pad 3 net 2 VIN → 1 GND",
+                "net GND gained pin J1.3",
+                "net VIN lost pin J1.3",
+            ]
+        );
+        let json = serde_json::to_value(diff(&old, &new)).unwrap();
+        assert_eq!(json[0]["change"], "moved");
+        assert_eq!(json[0]["by"][0], 1.2);
+    }
+}
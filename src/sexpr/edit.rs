@@ -1,9 +1,12 @@
 use super::{
-    simplifier::{Anything, Cons, Discard, Filter, Find, Head, Simplifier},
+    simplifier::{AnyStr, Anything, Capture, Cons, Discard, Filter, Find, Head, Simplifier},
     Atom, Expr,
 };
 use serde_json::{Map, Value};
-use std::{collections::BTreeMap, rc::Rc};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    rc::Rc,
+};
 
 /// The name of the special reference property
 const REFERENCE: &str = "Reference";
@@ -12,28 +15,70 @@ const REFERENCE: &str = "Reference";
 /// consisting of a symbol name and a property name
 pub type Key = (String, String);
 
-/// The map of properties
-pub type Props = BTreeMap<Key, Atom>;
+/// How the symbol name in a key identifies the symbols it applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyBy {
+    /// The `Reference` property of each symbol, such as `R1`.
+    Reference,
+    /// The name of a library symbol, such as `Cuprous:TPS62130`,
+    /// in the `lib_symbols` of a schematic or in a symbol library.
+    /// A name without a library nickname matches any library.
+    LibId,
+    /// As `LibId`, and also the `lib_id` of every placed symbol.
+    LibIdAndInstances,
+}
+
+impl KeyBy {
+    /// The member of each JSON object which gives the symbol name.
+    pub fn member(&self) -> &'static str {
+        match self {
+            KeyBy::Reference => REFERENCE,
+            KeyBy::LibId | KeyBy::LibIdAndInstances => "lib_id",
+        }
+    }
+
+    /// Does the symbol name in a key match the name found for a symbol?
+    fn matches(&self, key: &str, name: &str) -> bool {
+        fn item(id: &str) -> &str {
+            id.split_once(':').map_or(id, |(_, item)| item)
+        }
+        match self {
+            KeyBy::Reference => key == name,
+            KeyBy::LibId | KeyBy::LibIdAndInstances => {
+                key == name
+                    || (!(key.contains(':') && name.contains(':')) && item(key) == item(name))
+            }
+        }
+    }
+}
+
+/// The map of properties.
+/// A value of `None` marks the property for removal.
+pub type Props = BTreeMap<Key, Option<Atom>>;
 
 /// Interpret a serde json `Value` as an array of objects and reform this as a `BTreeMap`.
 /// Each member of each object becomes an entry in the map.
-/// Each object has a string-valued member called `REFERENCE`
+/// Each object has a string-valued member named by `key_by`, `Reference` or `lib_id`,
 /// which becomes the first part of the key.
-pub fn extract_props(json: Value) -> Option<Props> {
+/// A member whose value is `null` marks the property for removal.
+pub fn extract_props(json: Value, key_by: KeyBy) -> Option<Props> {
+    let member = key_by.member();
     json.as_array().map(|records| {
         let records = records.iter().filter_map(|r| {
             let record = r.as_object()?.clone();
-            let reference = record.get(REFERENCE)?.as_str()?.to_string();
+            let reference = record.get(member)?.as_str()?.to_string();
             Some((reference, record))
         });
 
         let to_key_value = |reference, property, value: Value| {
             let key = (reference, property);
 
-            let value = value
-                .as_f64()
-                .map(Atom::from)
-                .or(value.as_str().map(Atom::from))?;
+            let value = match value {
+                Value::Null => None,
+                Value::Number(n) => Some(Atom::from(n.as_f64()?)),
+                Value::String(s) => Some(Atom::from(s)),
+                _ => None?,
+            };
 
             Some((key, value))
         };
@@ -41,7 +86,7 @@ pub fn extract_props(json: Value) -> Option<Props> {
         let to_key_values = |(reference, record): (String, Map<String, Value>)| {
             record
                 .into_iter()
-                .filter(|(property, _)| property != REFERENCE)
+                .filter(|(property, _)| property != member)
                 .filter_map(move |(property, value)| {
                     to_key_value(reference.clone(), property, value)
                 })
@@ -52,54 +97,158 @@ pub fn extract_props(json: Value) -> Option<Props> {
 }
 
 /// Produce a `Simplifier` that matches the s-expr representing a schematic or a symbol library.
-/// Return the input but with symbol properties updated according to the given `props`.
-/// If `props` contains an entry matching a symbol property that property's value is updated.
-pub fn editor(props: Props) -> impl Simplifier {
+/// Return the input but with symbol properties updated according to the given `props`,
+/// whose keys identify symbols as `key_by` says.
+/// If `props` contains an entry matching a symbol property that property's value is updated,
+/// or the property is removed if the entry's value is `None`.
+/// Entries naming a property the symbol lacks add it, hidden, at the symbol's position.
+pub fn editor(props: Props, key_by: KeyBy) -> impl Simplifier {
     let props = Rc::new(props);
 
-    let property_body = move |sym_name: String| {
-        let props = props.clone();
+    let symbol_body = move |expr: &Expr| {
+        let sym_name = symbol_name(expr, key_by)?;
+        edit_symbol(&props, key_by, &sym_name, expr)
+    };
 
+    let symbol = Cons("symbol", symbol_body);
+
+    let lib_symbols = {
+        let symbol = symbol.clone();
         move |expr: &Expr| {
-            let elems = expr.as_list()?;
-            let prop_name = elems.front()?.as_atom()?.as_string()?.to_string();
-            let key = (sym_name.clone(), prop_name);
-            let value = props.get(&key)?.clone();
-            let mut elems = elems.clone();
-            *elems.get_mut(1)? = value.into();
-            Some(Expr::List(elems))
+            (key_by != KeyBy::Reference).then_some(())?;
+            Cons("lib_symbols", Filter(symbol.clone().or(Anything))).simplify(expr)
         }
     };
 
-    let property = move |sym_name: String| Cons("property", property_body(sym_name).or(Anything));
+    Cons(
+        "kicad_sch".or("kicad_symbol_lib"),
+        Filter(symbol.or(lib_symbols).or(Anything)),
+    )
+}
+
+/// The keys of `props` which the editor would not apply to `input`:
+/// those whose symbol is absent and removals of properties the symbol lacks.
+pub fn unmatched<'a>(props: &'a Props, input: &Expr, key_by: KeyBy) -> Vec<&'a Key> {
+    let cached = input
+        .children("lib_symbols")
+        .filter(|_| key_by != KeyBy::Reference)
+        .flat_map(|l| l.children("symbol"));
+    let mut found = BTreeSet::new();
+    for symbol in input.children("symbol").chain(cached) {
+        let body = Expr::list(symbol.args().cloned());
+        let Some(sym_name) = symbol_name(&body, key_by) else {
+            continue;
+        };
+        for ((key, name), value) in props {
+            if key_by.matches(key, &sym_name)
+                && (value.is_some() || property_of(symbol, name).is_some())
+            {
+                found.insert((key, name));
+            }
+        }
+    }
+    props
+        .keys()
+        .filter(|(r, n)| !found.contains(&(r, n)))
+        .collect()
+}
+
+/// The name by which `key_by` identifies a symbol, given the elements following its head.
+/// A library symbol starts with its name while a placed symbol starts with its `lib_id`.
+fn symbol_name(body: &Expr, key_by: KeyBy) -> Option<String> {
+    let first = body.as_list()?.front()?;
+    let library_name = first.as_atom().and_then(|a| a.as_string());
+    match (key_by, library_name) {
+        (KeyBy::Reference, _) => reference(body),
+        (_, Some(name)) => Some(name.to_owned()),
+        (KeyBy::LibId, None) => None,
+        (KeyBy::LibIdAndInstances, None) => Some(body.child("lib_id")?.str_arg()?.to_owned()),
+    }
+}
 
+/// The value of the `Reference` property of a symbol.
+fn reference(body: &Expr) -> Option<String> {
     let reference = Find(Cons(
         Discard("property"),
-        Cons(Discard(Atom::from(REFERENCE)), Head(Anything)),
+        Cons(
+            Discard(Atom::from(REFERENCE)),
+            Head(Capture("reference", AnyStr)),
+        ),
     ));
+    Some(reference.capture(body)?.get_str("reference")?.to_string())
+}
 
-    let symbol_body = move |expr: &Expr| {
-        let sym_name = reference
-            .simplify(expr)?
-            .as_atom()?
-            .as_string()?
-            .to_string();
+/// The property of a symbol with the given name.
+fn property_of<'a>(symbol: &'a Expr, name: &str) -> Option<&'a Expr> {
+    symbol
+        .children("property")
+        .find(|p| p.str_arg() == Some(name))
+}
 
-        Filter(property(sym_name).or(Anything)).simplify(expr)
-    };
+/// Apply the entries of `props` for `sym_name` to the elements of a symbol following its head.
+fn edit_symbol(props: &Props, key_by: KeyBy, sym_name: &str, body: &Expr) -> Option<Expr> {
+    let edits: BTreeMap<&str, &Option<Atom>> = props
+        .iter()
+        .filter(|((key, _), _)| key_by.matches(key, sym_name))
+        .map(|((_, name), value)| (name.as_str(), value))
+        .collect();
 
-    let symbol = Cons("symbol", symbol_body);
+    let mut elems = VecDeque::new();
+    let mut present = BTreeSet::new();
+    let mut insert_at = None;
+    for elem in body.as_list()? {
+        let name = (elem.head() == Some("property"))
+            .then(|| elem.str_arg())
+            .flatten();
+        let Some(name) = name else {
+            elems.push_back(elem.clone());
+            continue;
+        };
+        present.insert(name);
+        match edits.get(name) {
+            Some(None) => {}
+            Some(Some(value)) => {
+                let mut property = elem.as_list()?.clone();
+                *property.get_mut(2)? = value.clone().into();
+                elems.push_back(Expr::List(property, elem.span()));
+            }
+            None => elems.push_back(elem.clone()),
+        }
+        insert_at = Some(elems.len());
+    }
 
-    Cons(
-        "kicad_sch".or("kicad_symbol_lib"),
-        Filter(symbol.or(Anything)),
-    )
+    let at = body.child("at");
+    let x = at.and_then(|at| at.args().next()?.as_atom()?.as_num());
+    let y = at.and_then(|at| at.args().nth(1)?.as_atom()?.as_num());
+    let mut insert_at = insert_at.unwrap_or(elems.len());
+    for (name, value) in edits {
+        if let Some(value) = value.as_ref().filter(|_| !present.contains(name)) {
+            let property = new_property(name, value, x.unwrap_or(0.0), y.unwrap_or(0.0));
+            elems.insert(insert_at, property);
+            insert_at += 1;
+        }
+    }
+    Some(Expr::List(elems, body.span()))
+}
+
+/// A hidden property placed at the given position.
+fn new_property(name: &str, value: &Atom, x: f64, y: f64) -> Expr {
+    let size = Expr::list([Expr::key("size"), 1.27.into(), 1.27.into()]);
+    let font = Expr::list([Expr::key("font"), size]);
+    let hide = Expr::list([Expr::key("hide"), Expr::key("yes")]);
+    Expr::list([
+        Expr::key("property"),
+        name.into(),
+        value.clone().into(),
+        Expr::list([Expr::key("at"), x.into(), y.into(), 0.0.into()]),
+        Expr::list([Expr::key("effects"), font, hide]),
+    ])
 }
 
 #[cfg(test)]
 mod test {
 
-    use super::{editor, extract_props};
+    use super::{editor, extract_props, unmatched, KeyBy};
     use crate::{
         parse_file::parse_with,
         sexpr::{edit::Props, parser::parse_s_expr, simplifier::Simplifier, Atom, Expr},
@@ -110,21 +259,26 @@ mod test {
     fn test_extract() {
         let value = json!([
             { "Reference": "a", "Footprint": "b" },
-            { "Reference": "c", "Footprint": "d" },
+            { "Reference": "c", "Footprint": "d", "Datasheet": null },
             {},
             { "Footprint": "e" }
         ]);
 
-        let m = extract_props(value).unwrap();
+        let m = extract_props(value, KeyBy::Reference).unwrap();
 
         assert_eq!(
             m.get(&("a".to_string(), "Footprint".to_string())).unwrap(),
-            &Atom::from("b")
+            &Some(Atom::from("b"))
         );
 
         assert_eq!(
             m.get(&("c".to_string(), "Footprint".to_string())).unwrap(),
-            &Atom::from("d")
+            &Some(Atom::from("d"))
+        );
+
+        assert_eq!(
+            m.get(&("c".to_string(), "Datasheet".to_string())).unwrap(),
+            &None
         );
     }
 
@@ -340,10 +494,12 @@ mod test {
 
     #[test]
     fn test_no_edits() {
-        let m: Props = [(("".to_string(), "".to_string()), Atom::from(""))]
+        let m: Props = [(("".to_string(), "".to_string()), Some(Atom::from("")))]
             .into_iter()
             .collect();
-        let s = editor(m).simplify(&schematic_before()).unwrap();
+        let s = editor(m, KeyBy::Reference)
+            .simplify(&schematic_before())
+            .unwrap();
         assert_eq!(s, schematic_before());
     }
 
@@ -351,11 +507,94 @@ mod test {
     fn test_edits() {
         let m: Props = [(
             ("J8".to_string(), "Footprint".to_string()),
-            Atom::from("Cuprous:other_footprint"),
+            Some(Atom::from("Cuprous:other_footprint")),
         )]
         .into_iter()
         .collect();
-        let s = editor(m).simplify(&schematic_before()).unwrap();
+        let s = editor(m, KeyBy::Reference)
+            .simplify(&schematic_before())
+            .unwrap();
         assert_eq!(s, schematic_after());
     }
+    #[test]
+    fn test_add_and_remove() {
+        let key = |r: &str, p: &str| (r.to_string(), p.to_string());
+        let m: Props = [
+            (key("J8", "MPN"), Some(Atom::from("3220-10-0300-00"))),
+            (key("J8", "Datasheet"), None),
+            (key("J8", "Manufacturer"), None),
+            (key("J9", "Value"), Some(Atom::from("1k"))),
+        ]
+        .into_iter()
+        .collect();
+
+        let before = schematic_before();
+        let after = editor(m.clone(), KeyBy::Reference)
+            .simplify(&before)
+            .unwrap();
+        let symbol = after.child("symbol").unwrap();
+        let names: Vec<&str> = symbol
+            .children("property")
+            .filter_map(|p| p.str_arg())
+            .collect();
+        assert_eq!(names, ["Reference", "Value", "Footprint", "MPN"]);
+
+        let mpn = symbol.children("property").last().unwrap();
+        let expected = parse_with(
+            r#"(property "MPN" "3220-10-0300-00" (at 53.34 134.62 0)
+                (effects (font (size 1.27 1.27)) (hide yes)))"#,
+            parse_s_expr,
+        )
+        .unwrap();
+        assert_eq!(mpn, &expected);
+
+        assert_eq!(
+            unmatched(&m, &before, KeyBy::Reference),
+            [&key("J8", "Manufacturer"), &key("J9", "Value")]
+        );
+    }
+    #[test]
+    fn test_lib_id() {
+        let key = |r: &str, p: &str| (r.to_string(), p.to_string());
+        let m: Props = [
+            (
+                key("Cuprous:3220-10-0300-00", "Datasheet"),
+                Some(Atom::from("d.pdf")),
+            ),
+            (key("3220-10-0300-00", "Value"), Some(Atom::from("Panel"))),
+            (key("Other:3220-10-0300-00", "MPN"), Some(Atom::from("x"))),
+        ]
+        .into_iter()
+        .collect();
+        let datasheet = |symbol: &Expr| {
+            symbol
+                .children("property")
+                .find(|p| p.str_arg() == Some("Datasheet"))
+                .and_then(|p| p.args().nth(1)?.as_atom()?.as_string().map(str::to_owned))
+        };
+
+        let before = schematic_before();
+        let after = editor(m.clone(), KeyBy::LibId).simplify(&before).unwrap();
+        let cached = after.child("lib_symbols").unwrap().child("symbol").unwrap();
+        assert_eq!(datasheet(cached).as_deref(), Some("d.pdf"));
+        let value = cached.children("property").nth(1).unwrap();
+        assert_eq!(value.args().nth(1), Some(&Expr::from("Panel")));
+        assert_eq!(
+            datasheet(after.child("symbol").unwrap()).as_deref(),
+            Some("")
+        );
+
+        let after = editor(m.clone(), KeyBy::LibIdAndInstances)
+            .simplify(&before)
+            .unwrap();
+        assert_eq!(
+            datasheet(after.child("symbol").unwrap()).as_deref(),
+            Some("d.pdf")
+        );
+
+        assert_eq!(
+            unmatched(&m, &before, KeyBy::LibId),
+            [&key("Other:3220-10-0300-00", "MPN")]
+        );
+    }
 }
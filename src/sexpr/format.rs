@@ -0,0 +1,308 @@
+//! Serialize an `Expr` with the layout used by KiCad 8 and 9.
+//!
+//! KiCad writes each file compactly and then reflows it with a `Prettify` pass.
+//! This module does the same so that a file which is parsed and
+//! written back unchanged is identical to the original.
+//!
+//! The layout rules are:
+//! - every list starts on a new line indented by one tab per level of nesting
+//! - a list closes on its own line if its last element was a list
+//!   or if its contents were wrapped
+//! - consecutive `(xy ..)` lists share a line until column 99
+//! - tokens are separated by a space until column 72, after which they wrap
+//! - the file ends with a newline
+use super::{Atom, Expr};
+use std::fmt::{Display, Write};
+
+/// Tokens wrap onto a new line after this column.
+const TOKEN_WRAP_COLUMN: usize = 72;
+
+/// Consecutive `xy` lists share a line up to this column.
+const XY_COLUMN_LIMIT: usize = 99;
+
+/// Display an `Expr` in KiCad file layout.
+pub struct KicadFormat<'a>(pub &'a Expr);
+
+impl Display for KicadFormat<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&to_kicad_string(self.0))
+    }
+}
+
+/// Serialize an `Expr` in KiCad file layout.
+pub fn to_kicad_string(expr: &Expr) -> String {
+    let mut compact = String::new();
+    write_compact(expr, &mut compact);
+    prettify(&compact)
+}
+
+/// Format a number as KiCad does: `%.10g` except for very small values
+/// which are written in fixed point.
+pub fn format_number(value: f64) -> String {
+    if value != 0.0 && value.abs() <= 0.0001 {
+        let s = format!("{value:.10}");
+        trim_fraction(&s).to_owned()
+    } else {
+        format_general(value, 10)
+    }
+}
+
+/// Emulate the C `%.*g` conversion.
+fn format_general(value: f64, precision: usize) -> String {
+    if value == 0.0 {
+        return if value.is_sign_negative() { "-0" } else { "0" }.to_owned();
+    }
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    let sci = format!("{:.*e}", precision - 1, value);
+    let (mantissa, exponent) = sci.split_once('e').unwrap_or((&sci, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    if exponent < -4 || exponent >= precision as i32 {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!(
+            "{}e{sign}{:02}",
+            trim_fraction(mantissa),
+            exponent.unsigned_abs()
+        )
+    } else {
+        let decimals = (precision as i32 - 1 - exponent).max(0) as usize;
+        trim_fraction(&format!("{value:.decimals$}")).to_owned()
+    }
+}
+
+/// Remove trailing zeros after a decimal point and then the point itself.
+fn trim_fraction(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+/// Quote a string as KiCad does.  Only newlines, returns,
+/// backslashes and quotes are escaped.
+fn write_quoted(s: &str, target: &mut String) {
+    target.push('"');
+    for c in s.chars() {
+        match c {
+            '\n' => target.push_str("\\n"),
+            '\r' => target.push_str("\\r"),
+            '\\' => target.push_str("\\\\"),
+            '"' => target.push_str("\\\""),
+            c => target.push(c),
+        }
+    }
+    target.push('"');
+}
+
+fn write_atom(atom: &Atom, target: &mut String) {
+    match atom {
+        Atom::Num(v) => match v.text() {
+            Some(t) => target.push_str(t),
+            None => target.push_str(&format_number(v.value())),
+        },
+        Atom::Str(s) => write_quoted(s, target),
+        _ => write!(target, "{atom}").unwrap(),
+    }
+}
+
+/// Write an expression on one line with single spaces between elements.
+fn write_compact(expr: &Expr, target: &mut String) {
+    match expr {
+        Expr::Constant(atom, _) => write_atom(atom, target),
+        Expr::List(exprs, _) => {
+            target.push('(');
+            for (i, expr) in exprs.iter().enumerate() {
+                if i > 0 {
+                    target.push(' ');
+                }
+                write_compact(expr, target)
+            }
+            target.push(')');
+        }
+    }
+}
+
+/// Reflow compact S-expression text following KiCad's `Prettify`.
+/// Columns are counted in bytes, as KiCad does.
+pub fn prettify(source: &str) -> String {
+    let bytes = source.as_bytes();
+    let mut formatted: Vec<u8> = Vec::with_capacity(source.len() * 2);
+
+    let mut list_depth = 0usize;
+    let mut last_non_whitespace = 0u8;
+    let mut in_quote = false;
+    let mut has_inserted_space = false;
+    let mut in_multi_line_list = false;
+    let mut in_xy = false;
+    let mut column = 0usize;
+    let mut backslash_count = 0usize;
+
+    let is_whitespace = |c: u8| matches!(c, b' ' | b'\t' | b'\n' | b'\r');
+
+    let next_non_whitespace = |i: usize| {
+        bytes[i..]
+            .iter()
+            .copied()
+            .find(|c| !is_whitespace(*c))
+            .unwrap_or(0)
+    };
+
+    let is_xy = |i: usize| bytes[i + 1..].starts_with(b"xy ");
+
+    let newline = |formatted: &mut Vec<u8>, depth: usize| {
+        formatted.push(b'\n');
+        formatted.extend(std::iter::repeat_n(b'\t', depth));
+    };
+
+    for (i, &c) in bytes.iter().enumerate() {
+        if is_whitespace(c) && !in_quote {
+            let next = next_non_whitespace(i);
+            if !has_inserted_space
+                && list_depth > 0
+                && last_non_whitespace != b'('
+                && next != b')'
+                && next != b'('
+            {
+                if in_xy || column < TOKEN_WRAP_COLUMN {
+                    formatted.push(b' ');
+                    column += 1;
+                } else {
+                    newline(&mut formatted, list_depth);
+                    column = list_depth;
+                    in_multi_line_list = true;
+                }
+                has_inserted_space = true;
+            }
+        } else {
+            has_inserted_space = false;
+
+            if c == b'(' && !in_quote {
+                let current_is_xy = is_xy(i);
+
+                if formatted.is_empty() {
+                    formatted.push(b'(');
+                    column += 1;
+                } else if in_xy && current_is_xy && column < XY_COLUMN_LIMIT {
+                    formatted.extend_from_slice(b" (");
+                    column += 2;
+                } else {
+                    newline(&mut formatted, list_depth);
+                    formatted.push(b'(');
+                    column = list_depth + 1;
+                }
+
+                in_xy = current_is_xy;
+                list_depth += 1;
+            } else if c == b')' && !in_quote {
+                list_depth = list_depth.saturating_sub(1);
+
+                if last_non_whitespace == b')' || in_multi_line_list {
+                    newline(&mut formatted, list_depth);
+                    formatted.push(b')');
+                    column = list_depth + 1;
+                    in_multi_line_list = false;
+                } else {
+                    formatted.push(b')');
+                    column += 1;
+                }
+            } else {
+                // a quote preceded by an odd number of backslashes is escaped
+                if c == b'\\' {
+                    backslash_count += 1;
+                } else if c == b'"' && backslash_count & 1 == 0 {
+                    in_quote = !in_quote;
+                }
+
+                if c != b'\\' {
+                    backslash_count = 0;
+                }
+
+                formatted.push(c);
+                column += 1;
+            }
+
+            last_non_whitespace = c;
+        }
+    }
+
+    formatted.push(b'\n');
+
+    // only ASCII bytes are inserted or removed so the result is valid UTF-8
+    String::from_utf8(formatted).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_number, to_kicad_string};
+    use crate::{parse_file::parse_with, sexpr::parser::parse_s_expr};
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(format_number(0.0), "0");
+        assert_eq!(format_number(1.27), "1.27");
+        assert_eq!(format_number(-8.89), "-8.89");
+        assert_eq!(format_number(20250114.0), "20250114");
+        assert_eq!(format_number(0.1 + 0.2), "0.3");
+        assert_eq!(format_number(0.00005), "0.00005");
+        assert_eq!(format_number(1e12), "1e+12");
+    }
+
+    #[test]
+    fn test_strings() {
+        let expr =
+            parse_with(r#"(a "tab\there" "q\"uote\\" "line\nbreak")"#, parse_s_expr).unwrap();
+        assert_eq!(
+            to_kicad_string(&expr),
+            "(a \"tab\there\" \"q\\\"uote\\\\\" \"line\\nbreak\")\n"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "(kicad_sch
+\t(version 20250114)
+\t(generator \"eeschema\")
+\t(generator_version \"9.0\")
+\t(uuid \"e63e39d7-6ac0-4ffd-8aa3-1841a4541b55\")
+\t(paper \"A4\")
+\t(lib_symbols
+\t\t(symbol \"Device:R\"
+\t\t\t(pin_numbers
+\t\t\t\t(hide yes)
+\t\t\t)
+\t\t\t(exclude_from_sim no)
+\t\t\t(property \"Reference\" \"R\"
+\t\t\t\t(at 2.032 0 90)
+\t\t\t\t(effects
+\t\t\t\t\t(font
+\t\t\t\t\t\t(size 1.27 1.27)
+\t\t\t\t\t)
+\t\t\t\t)
+\t\t\t)
+\t\t)
+\t)
+\t(polyline
+\t\t(pts
+\t\t\t(xy 101.6 50.8) (xy 104.14 50.8) (xy 106.68 53.34) (xy 109.22 53.34) (xy 111.76 55.88) (xy 114.3 55.88)
+\t\t\t(xy 116.84 58.42)
+\t\t)
+\t\t(stroke
+\t\t\t(width 0)
+\t\t\t(type default)
+\t\t)
+\t)
+\t(group \"\"
+\t\t(uuid \"11cc29a1-afce-483d-83cc-2917e2a927e5\")
+\t\t(members \"0b6b2c5e-5c0e-4e0e-9a2c-1f4c1b9b0a01\" \"1b6b2c5e-5c0e-4e0e-9a2c-1f4c1b9b0a02\"
+\t\t\t\"2b6b2c5e-5c0e-4e0e-9a2c-1f4c1b9b0a03\"
+\t\t)
+\t)
+\t(embedded_fonts no)
+)
+";
+        let expr = parse_with(text, parse_s_expr).unwrap();
+        assert_eq!(to_kicad_string(&expr), text);
+    }
+}
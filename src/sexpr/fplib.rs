@@ -0,0 +1,302 @@
+//! Footprint libraries: `.pretty` directories of `.kicad_mod` files.
+//!
+//! KiCad names a footprint after its file, so the `footprint` name field
+//! inside each file is rewritten to match whenever a library is written.
+//! Footprints are compared structurally, ignoring formatting, uuids and
+//! timestamps, so copies saved at different times are still the same.
+use super::{
+    format::KicadFormat,
+    parser::parse_s_expr,
+    symlib::{resolve, Conflict, Policy, Resolution},
+    Atom, Expr,
+};
+use crate::parse_file::{parse_file, write_file, Result};
+use std::collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Lists that differ between copies of the same footprint.
+const VOLATILE: [&str; 3] = ["uuid", "tstamp", "tedit"];
+
+/// A footprint and the name of the file it belongs in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Footprint {
+    pub name: String,
+    pub expr: Expr,
+}
+
+/// The result of `merge`: the footprints of a library and the conflicts resolved.
+#[derive(Debug, Clone)]
+pub struct Merged {
+    pub footprints: Vec<Footprint>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Read every footprint in a `.pretty` directory, in name order.
+pub fn read_library(dir: &Path) -> Result<Vec<Footprint>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("{}: {e}", dir.display()))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "kicad_mod"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let expr = parse_file(&path.to_string_lossy(), parse_s_expr)?;
+            Ok(Footprint { name, expr })
+        })
+        .collect()
+}
+
+/// Write footprints into a `.pretty` directory, creating it if need be.
+pub fn write_library(dir: &Path, footprints: &[Footprint]) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    for footprint in footprints {
+        write_footprint(dir, footprint)?;
+    }
+    Ok(())
+}
+
+/// Write one footprint to its file with its name field set to match.
+pub fn write_footprint(dir: &Path, footprint: &Footprint) -> Result<()> {
+    write_file(
+        &path_of(dir, &footprint.name),
+        &KicadFormat(&named(footprint)),
+    )
+}
+
+/// A footprint with its name field set to the name of its file.
+fn named(footprint: &Footprint) -> Expr {
+    match name_in(&footprint.expr) {
+        Some(name) => rename(footprint.expr.clone(), &name, &footprint.name),
+        None => footprint.expr.clone(),
+    }
+}
+
+/// The file holding the named footprint in a library.
+pub fn path_of(dir: &Path, name: &str) -> String {
+    dir.join(format!("{name}.kicad_mod"))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The name field of a footprint, or of a KiCad 5 `module`.
+pub fn name_in(footprint: &Expr) -> Option<String> {
+    if !matches!(footprint.head(), Some("footprint" | "module")) {
+        return None;
+    }
+    let name = footprint.args().next()?.as_atom()?;
+    Some(name.as_string().or(name.as_symbol())?.to_owned())
+}
+
+/// Rename a footprint, setting its name field and any value equal to the old name.
+pub fn rename(footprint: Expr, from: &str, to: &str) -> Expr {
+    let span = footprint.span();
+    let Some(elems) = footprint.clone().into_deque() else {
+        return footprint;
+    };
+    let is_value = |elem: &Expr| match elem.head() {
+        Some("property") => elem.str_arg() == Some("Value"),
+        Some("fp_text") => elem.symbol_arg() == Some("value"),
+        _ => false,
+    };
+    let elems = elems.into_iter().enumerate().map(|(index, elem)| {
+        if index == 1 {
+            Expr::Constant(to.into(), elem.span())
+        } else if is_value(&elem) {
+            replace_arg(elem, 2, from, to)
+        } else {
+            elem
+        }
+    });
+    Expr::list(elems).with_span(span)
+}
+
+/// Replace the argument at `index` if it is the string `from`.
+fn replace_arg(expr: Expr, index: usize, from: &str, to: &str) -> Expr {
+    let Expr::List(mut elems, span) = expr else {
+        return expr;
+    };
+    if let Some(elem) = elems.get_mut(index) {
+        if elem.as_atom().and_then(|a| a.as_string()) == Some(from) {
+            *elem = Expr::Constant(to.into(), elem.span());
+        }
+    }
+    Expr::List(elems, span)
+}
+
+/// A footprint without its uuids and timestamps, and with numbers
+/// no longer spelled as they were in the file.
+pub fn normalize(expr: &Expr) -> Expr {
+    match expr {
+        // adding zero turns -0 into 0, which compares equal to it
+        Expr::Constant(Atom::Num(n), span) => Expr::Constant((n.value() + 0.0).into(), *span),
+        Expr::List(elems, span) => Expr::List(
+            elems
+                .iter()
+                .filter(|elem| !elem.head().is_some_and(|h| VOLATILE.contains(&h)))
+                .map(normalize)
+                .collect(),
+            *span,
+        ),
+        _ => expr.clone(),
+    }
+}
+
+/// Are two footprints the same apart from uuids, timestamps and formatting?
+pub fn same(a: &Expr, b: &Expr) -> bool {
+    normalize(a) == normalize(b)
+}
+
+/// The text of a footprint without its name, uuids and timestamps.
+/// Footprints that are the same apart from their names have the same text.
+fn anonymous(footprint: &Footprint) -> String {
+    normalize(&rename(named(footprint), &footprint.name, "")).to_string()
+}
+
+/// Merge any number of named footprint libraries into one.
+///
+/// A footprint repeated in several libraries is kept once.  Differing
+/// footprints of the same name are resolved by `policy`.
+pub fn merge(inputs: &[(String, Vec<Footprint>)], policy: Policy) -> Result<Merged> {
+    let mut definitions: BTreeMap<String, Vec<(&str, Expr)>> = BTreeMap::new();
+    for (source, footprints) in inputs {
+        for footprint in footprints {
+            let expr = named(footprint);
+            let defs = definitions.entry(footprint.name.clone()).or_default();
+            if !defs.iter().any(|(_, other)| same(other, &expr)) {
+                defs.push((source, expr));
+            }
+        }
+    }
+    let Resolution {
+        items, conflicts, ..
+    } = resolve("footprint", definitions, policy, rename)?;
+    let footprints = items
+        .into_iter()
+        .map(|(name, (_, expr))| Footprint { name, expr })
+        .collect();
+    Ok(Merged {
+        footprints,
+        conflicts,
+    })
+}
+
+/// Groups of two or more footprints that are the same apart from their names,
+/// given as indexes into `footprints`.
+pub fn duplicates(footprints: &[&Footprint]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut found: HashMap<String, usize> = HashMap::new();
+    for (index, footprint) in footprints.iter().enumerate() {
+        match found.entry(anonymous(footprint)) {
+            Entry::Occupied(group) => groups[*group.get()].push(index),
+            Entry::Vacant(group) => {
+                group.insert(groups.len());
+                groups.push(vec![index]);
+            }
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// Separate the named footprints from the rest of a library.
+pub fn split(
+    footprints: Vec<Footprint>,
+    names: &BTreeSet<String>,
+) -> Result<(Vec<Footprint>, Vec<Footprint>)> {
+    let found: BTreeSet<&String> = footprints.iter().map(|f| &f.name).collect();
+    if let Some(missing) = names.iter().find(|name| !found.contains(name)) {
+        Err(format!("footprint {missing:?} not found"))?
+    }
+    Ok(footprints
+        .into_iter()
+        .partition(|footprint| names.contains(&footprint.name)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{duplicates, merge, name_in, same, split, Footprint};
+    use crate::{
+        parse_file::parse_with,
+        sexpr::{parser::parse_s_expr, symlib::Policy},
+    };
+
+    fn footprint(name: &str, text: &str) -> Footprint {
+        let expr = parse_with(text, parse_s_expr).unwrap();
+        Footprint {
+            name: name.to_owned(),
+            expr,
+        }
+    }
+
+    fn r0603(uuid: &str, width: &str) -> Footprint {
+        let text = format!(
+            r#"(footprint "R_0603" (layer "F.Cu")
+                (property "Value" "R_0603" (at 0 1.43 0) (uuid "{uuid}"))
+                (fp_line (start -0.8 -0.4) (end 0.8 -0.4) (width {width}) (uuid "{uuid}")))"#
+        );
+        footprint("R_0603", &text)
+    }
+
+    #[test]
+    fn test_same() {
+        let a = r0603("a", "0.12");
+        let b = r0603("b", "0.120");
+        let c = r0603("a", "0.15");
+        assert!(same(&a.expr, &b.expr));
+        assert!(!same(&a.expr, &c.expr));
+    }
+
+    #[test]
+    fn test_merge() {
+        let inputs = vec![
+            ("a.pretty".to_owned(), vec![r0603("a", "0.12")]),
+            ("b.pretty".to_owned(), vec![r0603("b", "0.12")]),
+            ("c.pretty".to_owned(), vec![r0603("c", "0.15")]),
+        ];
+        let merged = merge(&inputs[..2], Policy::FailOnConflict).unwrap();
+        assert_eq!(merged.footprints.len(), 1);
+        assert!(merged.conflicts.is_empty());
+
+        let merged = merge(&inputs, Policy::KeepBoth).unwrap();
+        assert_eq!(
+            merged.conflicts[0].to_string(),
+            r#"footprint "R_0603" differs in a.pretty, c.pretty, kept "R_0603" from a.pretty and "R_0603_2" from c.pretty"#
+        );
+        let renamed = &merged.footprints[1];
+        assert_eq!(renamed.name, "R_0603_2");
+        assert_eq!(name_in(&renamed.expr).unwrap(), "R_0603_2");
+        let value = renamed.expr.child("property").unwrap();
+        assert_eq!(
+            value.args().nth(1).unwrap().as_atom().unwrap().as_string(),
+            Some("R_0603_2")
+        );
+
+        assert!(merge(&inputs, Policy::FailOnConflict).is_err());
+    }
+
+    #[test]
+    fn test_duplicates_and_split() {
+        let copy = footprint(
+            "Copy",
+            r#"(footprint "Copy" (layer "F.Cu")
+                (property "Value" "Copy" (at 0 1.430 0) (uuid "x"))
+                (fp_line (start -0.8 -0.4) (end 0.8 -0.40) (width 0.120) (uuid "y")))"#,
+        );
+        let library = [r0603("a", "0.12"), r0603("b", "0.15"), copy];
+        let all: Vec<&Footprint> = library.iter().collect();
+        assert_eq!(duplicates(&all), [[0, 2]]);
+
+        let names = ["Copy".to_owned()].into();
+        let (moved, kept) = split(library.to_vec(), &names).unwrap();
+        assert_eq!(moved.len(), 1);
+        assert_eq!(kept.len(), 2);
+        assert!(split(kept, &names).is_err());
+    }
+}
@@ -1,6 +1,25 @@
-use super::{Atom, Expr};
-use serde_json::{Map, Number, Value};
+//! Conversions between S-expressions and JSON.
+//!
+//! `expr_to_json_value` produces a readable summary but cannot be reversed.
+//!
+//! `expr_to_tagged_json` produces a lossless encoding which `json_to_expr` inverts:
+//!
+//! |S-expression|JSON|
+//! |---|---|
+//! |list|array of its elements, in order|
+//! |`Str`|string|
+//! |`Bool`|`true` or `false`|
+//! |`Symbol`|`{"sym": name}`|
+//! |`Num`|`{"num": value, "text": spelling}`|
+//! |`Bits`|`{"bits": value, "text": spelling}`|
+//! |`Uuid`|`{"uuid": hyphenated}`|
+//!
+//! The `text` member is optional. It is used only if it still agrees with the value,
+//! so editing `num` or `bits` without removing `text` takes effect.
+use super::{Atom, Expr, Numeric};
+use serde_json::{json, Map, Number, Value};
 use std::collections::BTreeMap;
+use uuid::Uuid;
 
 /// Convert an s-expression to a JSON value.
 /// A list beginning with symbol becomes a JSON property.
@@ -31,19 +50,19 @@ use Particle::*;
 
 fn particle(expr: Expr) -> Particle {
     match expr {
-        Expr::Constant(a) => match a {
+        Expr::Constant(a, _) => match a {
             Atom::Symbol(v) => Free(Value::String(v)),
-            Atom::Bits(v) => Free(Value::Number(v.into())),
+            Atom::Bits(v) => Free(Value::Number(v.value().into())),
             Atom::Bool(v) => Free(Value::Bool(v)),
             Atom::Str(v) => Free(Value::String(v)),
-            Atom::Num(v) => Number::from_f64(v)
+            Atom::Num(v) => Number::from_f64(v.value())
                 .map(|x| Free(Value::Number(x)))
                 .unwrap_or(Free(Value::Null)),
             Atom::Uuid(v) => Free(Value::String(v.to_string())),
         },
 
-        Expr::List(xs) => match xs.front() {
-            Some(Expr::Constant(Atom::Symbol(s))) => {
+        Expr::List(xs, _) => match xs.front() {
+            Some(Expr::Constant(Atom::Symbol(s), _)) => {
                 Property(s.to_owned(), gather(xs.into_iter().skip(1)))
             }
             _ => Free(gather(xs.into_iter())),
@@ -94,3 +113,110 @@ fn gather(exprs: impl Iterator<Item = Expr>) -> Value {
         },
     }
 }
+
+/// Convert an s-expression to the lossless, tagged JSON encoding.
+pub fn expr_to_tagged_json(expr: &Expr) -> Value {
+    match expr {
+        Expr::List(xs, _) => Value::Array(xs.iter().map(expr_to_tagged_json).collect()),
+        Expr::Constant(a, _) => match a {
+            Atom::Str(v) => Value::String(v.clone()),
+            Atom::Bool(v) => Value::Bool(*v),
+            Atom::Symbol(v) => json!({ "sym": v }),
+            Atom::Num(v) => tagged_numeric("num", json!(v.value()), v.text()),
+            Atom::Bits(v) => tagged_numeric("bits", json!(v.value()), v.text()),
+            Atom::Uuid(v) => json!({ "uuid": v.to_string() }),
+        },
+    }
+}
+
+fn tagged_numeric(tag: &str, value: Value, text: Option<&str>) -> Value {
+    let mut obj = Map::new();
+    obj.insert(tag.to_owned(), value);
+    if let Some(text) = text {
+        obj.insert("text".to_owned(), Value::String(text.to_owned()));
+    }
+    Value::Object(obj)
+}
+
+/// Convert the tagged JSON encoding back to an s-expression.
+/// Returns `None` if the value is not a valid encoding.
+pub fn json_to_expr(value: &Value) -> Option<Expr> {
+    let atom = match value {
+        Value::Array(xs) => {
+            return Some(Expr::list(
+                xs.iter().map(json_to_expr).collect::<Option<Vec<_>>>()?,
+            ))
+        }
+        Value::String(v) => Atom::Str(v.clone()),
+        Value::Bool(v) => Atom::Bool(*v),
+        Value::Object(obj) => {
+            let text = obj.get("text").and_then(Value::as_str);
+            if let Some(v) = obj.get("sym") {
+                Atom::Symbol(v.as_str()?.to_owned())
+            } else if let Some(v) = obj.get("num") {
+                let v = v.as_f64()?;
+                let agrees = |t: &&str| t.parse::<f64>().is_ok_and(|x| x == v);
+                Atom::Num(match text.filter(agrees) {
+                    Some(t) => Numeric::with_text(v, t),
+                    None => Numeric::new(v),
+                })
+            } else if let Some(v) = obj.get("bits") {
+                let v = v.as_u64()?;
+                let agrees = |t: &&str| parse_hex(t) == Some(v);
+                Atom::Bits(match text.filter(agrees) {
+                    Some(t) => Numeric::with_text(v, t),
+                    None => Numeric::new(v),
+                })
+            } else if let Some(v) = obj.get("uuid") {
+                Atom::Uuid(Uuid::try_parse(v.as_str()?).ok()?)
+            } else {
+                None?
+            }
+        }
+        _ => None?,
+    };
+    Some(atom.into())
+}
+
+/// Parse the `0x` hex notation used for `Bits`, allowing `_` separators.
+fn parse_hex(text: &str) -> Option<u64> {
+    let digits: String = text
+        .strip_prefix("0x")?
+        .chars()
+        .filter(|c| *c != '_')
+        .collect();
+    u64::from_str_radix(&digits, 16).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{expr_to_tagged_json, json_to_expr};
+    use crate::{
+        parse_file::parse_with,
+        sexpr::{format::to_kicad_string, parser::parse_s_expr},
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_tagged_round_trip() {
+        let text = r#"(symbol (lib_id "Device:R") (at 1.270 -2 0) (in_bom yes) (mask 0x00ff_0000) (tstamp 5e0b1c2a-8b0e-4c1e-9d3a-2f5a1b6c7d8e) (hide true))"#;
+        let expr = parse_with(text, parse_s_expr).unwrap();
+        let value = expr_to_tagged_json(&expr);
+
+        assert_eq!(value[0], json!({ "sym": "symbol" }));
+        assert_eq!(value[1], json!([{ "sym": "lib_id" }, "Device:R"]));
+        assert_eq!(value[2][1], json!({ "num": 1.27, "text": "1.270" }));
+
+        let back = json_to_expr(&value).unwrap();
+        assert_eq!(back, expr);
+        assert_eq!(to_kicad_string(&back), to_kicad_string(&expr));
+    }
+
+    #[test]
+    fn test_edited_number() {
+        let value = json!([{ "sym": "at" }, { "num": 2.5, "text": "1.270" }, { "num": 0 }]);
+        let expr = json_to_expr(&value).unwrap();
+        assert_eq!(to_kicad_string(&expr), "(at 2.5 0)\n");
+        assert!(json_to_expr(&json!({ "other": 1 })).is_none());
+    }
+}
@@ -0,0 +1,365 @@
+//! Symbol and footprint library tables (`sym-lib-table` and `fp-lib-table`).
+//!
+//! A table maps library nicknames to the files or directories holding them.
+//! Tables are written in the layout KiCad uses, one line per library.
+use super::{format::to_kicad_string, Atom, Expr};
+use serde::Serialize;
+use std::{error::Error, fmt::Display, path::Path};
+
+/// The table format version written by KiCad 7 and later.
+pub const TABLE_VERSION: i64 = 7;
+
+/// Which kind of library a table lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TableKind {
+    Symbol,
+    Footprint,
+}
+
+impl TableKind {
+    /// The head of the table S-expression.
+    pub fn heading(self) -> &'static str {
+        match self {
+            TableKind::Symbol => "sym_lib_table",
+            TableKind::Footprint => "fp_lib_table",
+        }
+    }
+
+    fn from_heading(heading: &str) -> Option<TableKind> {
+        match heading {
+            "sym_lib_table" => Some(TableKind::Symbol),
+            "fp_lib_table" => Some(TableKind::Footprint),
+            _ => None,
+        }
+    }
+}
+
+/// One library in a table.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LibEntry {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub lib_type: String,
+    pub uri: String,
+    pub options: String,
+    pub descr: String,
+    pub disabled: bool,
+    pub hidden: bool,
+}
+
+impl LibEntry {
+    /// A KiCad format library with no options or description.
+    pub fn new(name: &str, uri: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            lib_type: "KiCad".to_owned(),
+            uri: uri.to_owned(),
+            options: String::new(),
+            descr: String::new(),
+            disabled: false,
+            hidden: false,
+        }
+    }
+
+    fn from_expr(lib: &Expr) -> Option<LibEntry> {
+        let text = |key: &str| lib.child(key).and_then(text_arg).unwrap_or_default();
+        Some(LibEntry {
+            name: lib.child("name").and_then(text_arg)?,
+            lib_type: text("type"),
+            uri: text("uri"),
+            options: text("options"),
+            descr: text("descr"),
+            disabled: lib.child("disabled").is_some(),
+            hidden: lib.child("hidden").is_some(),
+        })
+    }
+
+    fn to_expr(&self) -> Expr {
+        let item = |key: &str, value: &str| Expr::list([Expr::key(key), value.into()]);
+        let mut lib = vec![
+            Expr::key("lib"),
+            item("name", &self.name),
+            item("type", &self.lib_type),
+            item("uri", &self.uri),
+            item("options", &self.options),
+            item("descr", &self.descr),
+        ];
+        if self.disabled {
+            lib.push(Expr::list([Expr::key("disabled")]));
+        }
+        if self.hidden {
+            lib.push(Expr::list([Expr::key("hidden")]));
+        }
+        Expr::list(lib)
+    }
+
+    /// The uri with `${VAR}` references replaced using `lookup`.
+    pub fn expanded_uri(&self, lookup: impl Fn(&str) -> Option<String>) -> String {
+        expand_vars(&self.uri, lookup)
+    }
+}
+
+/// A problem editing a library table.
+#[derive(Clone, PartialEq)]
+pub enum TableError {
+    Duplicate(String),
+    NotFound(String),
+}
+
+impl Display for TableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TableError::Duplicate(name) => write!(f, "library {name} is already in the table"),
+            TableError::NotFound(name) => write!(f, "library {name} is not in the table"),
+        }
+    }
+}
+
+impl std::fmt::Debug for TableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Error for TableError {}
+
+/// A symbol or footprint library table.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LibTable {
+    pub kind: TableKind,
+    pub version: Option<i64>,
+    pub libs: Vec<LibEntry>,
+}
+
+impl LibTable {
+    /// An empty table of the current version.
+    pub fn new(kind: TableKind) -> Self {
+        Self {
+            kind,
+            version: Some(TABLE_VERSION),
+            libs: Vec::new(),
+        }
+    }
+
+    /// Read a table from its S-expression.
+    pub fn from_expr(table: &Expr) -> Option<LibTable> {
+        let kind = TableKind::from_heading(table.head()?)?;
+        Some(LibTable {
+            kind,
+            version: table
+                .child("version")
+                .and_then(Expr::num_arg)
+                .map(|v| v as i64),
+            libs: table
+                .children("lib")
+                .filter_map(LibEntry::from_expr)
+                .collect(),
+        })
+    }
+
+    /// The table as an S-expression.
+    pub fn to_expr(&self) -> Expr {
+        let version = self
+            .version
+            .map(|v| Expr::list([Expr::key("version"), Atom::from(v as f64).into()]));
+        Expr::list(
+            [Expr::key(self.kind.heading())]
+                .into_iter()
+                .chain(version)
+                .chain(self.libs.iter().map(LibEntry::to_expr)),
+        )
+    }
+
+    /// The library with the given nickname.
+    pub fn get(&self, name: &str) -> Option<&LibEntry> {
+        self.libs.iter().find(|l| l.name == name)
+    }
+
+    /// Append a library unless its nickname is already used.
+    pub fn add(&mut self, entry: LibEntry) -> Result<(), TableError> {
+        if self.get(&entry.name).is_some() {
+            return Err(TableError::Duplicate(entry.name));
+        }
+        self.libs.push(entry);
+        Ok(())
+    }
+
+    /// Replace the library with the same nickname, or append it if there is none.
+    pub fn set(&mut self, entry: LibEntry) {
+        match self.libs.iter_mut().find(|l| l.name == entry.name) {
+            Some(existing) => *existing = entry,
+            None => self.libs.push(entry),
+        }
+    }
+
+    /// Remove every library with the given nickname and return the first.
+    pub fn remove(&mut self, name: &str) -> Result<LibEntry, TableError> {
+        let index = self
+            .libs
+            .iter()
+            .position(|l| l.name == name)
+            .ok_or_else(|| TableError::NotFound(name.to_owned()))?;
+        let entry = self.libs.remove(index);
+        self.libs.retain(|l| l.name != name);
+        Ok(entry)
+    }
+
+    /// Change the uri of a library.
+    pub fn repoint(&mut self, name: &str, uri: &str) -> Result<(), TableError> {
+        let entry = self
+            .libs
+            .iter_mut()
+            .find(|l| l.name == name)
+            .ok_or_else(|| TableError::NotFound(name.to_owned()))?;
+        entry.uri = uri.to_owned();
+        Ok(())
+    }
+
+    /// Nicknames used by more than one library, in table order.
+    pub fn duplicates(&self) -> Vec<&str> {
+        let mut result: Vec<&str> = Vec::new();
+        for (i, lib) in self.libs.iter().enumerate() {
+            let repeated = self.libs[..i].iter().any(|l| l.name == lib.name);
+            if repeated && !result.contains(&lib.name.as_str()) {
+                result.push(&lib.name);
+            }
+        }
+        result
+    }
+}
+
+/// Write the table as KiCad does, with each library on a single line.
+impl Display for LibTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "({}", self.kind.heading())?;
+        if let Some(version) = self.version {
+            writeln!(f, "  (version {version})")?;
+        }
+        for lib in &self.libs {
+            let mut line = String::new();
+            for item in lib.to_expr().args() {
+                line.push_str(to_kicad_string(item).trim_end());
+            }
+            writeln!(f, "  (lib {line})")?;
+        }
+        writeln!(f, ")")
+    }
+}
+
+/// A string or symbol atom as text.
+fn text_arg(expr: &Expr) -> Option<String> {
+    let atom = expr.args().next()?.as_atom()?;
+    Some(match atom {
+        Atom::Str(s) | Atom::Symbol(s) => s.clone(),
+        _ => atom.to_string(),
+    })
+}
+
+/// Replace `${VAR}` references in `text` using `lookup`.
+/// Unknown variables are left as they are.
+pub fn expand_vars(text: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + len];
+        result.push_str(&rest[..start]);
+        match lookup(name) {
+            Some(value) => result.push_str(&value),
+            None => result.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Look up `KIPRJMOD` as the given project directory
+/// and other variables in the environment.
+/// An empty directory, the parent of a bare file name, is `.`.
+pub fn project_vars(project_dir: &Path) -> impl Fn(&str) -> Option<String> + '_ {
+    move |name| {
+        if name == "KIPRJMOD" && project_dir.as_os_str().is_empty() {
+            Some(".".to_owned())
+        } else if name == "KIPRJMOD" {
+            Some(project_dir.to_string_lossy().into_owned())
+        } else {
+            std::env::var(name).ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{expand_vars, project_vars, LibEntry, LibTable, TableError, TableKind};
+    use crate::{parse_file::parse_with, sexpr::parser::parse_s_expr};
+    use std::path::Path;
+
+    const TABLE: &str = r#"(sym_lib_table
+  (version 7)
+  (lib (name "Device")(type "KiCad")(uri "${KICAD8_SYMBOL_DIR}/Device.kicad_sym")(options "")(descr "Resistors, capacitors and so on"))
+  (lib (name "Cuprous")(type "KiCad")(uri "${KIPRJMOD}/../library/cuprous.kicad_sym")(options "")(descr "")(disabled))
+  (lib (name "Device")(type "KiCad")(uri "${KIPRJMOD}/Device.kicad_sym")(options "")(descr ""))
+)
+"#;
+
+    #[test]
+    fn test_round_trip() {
+        let expr = parse_with(TABLE, parse_s_expr).unwrap();
+        let table = LibTable::from_expr(&expr).unwrap();
+        assert_eq!(table.kind, TableKind::Symbol);
+        assert!(table.libs[1].disabled);
+        assert_eq!(table.duplicates(), ["Device"]);
+        assert_eq!(table.to_string(), TABLE);
+    }
+
+    #[test]
+    fn test_edit() {
+        let old = "(fp_lib_table (lib (name Cuprous) (type KiCad) (uri /old/Cuprous.pretty)))";
+        let mut table = LibTable::from_expr(&parse_with(old, parse_s_expr).unwrap()).unwrap();
+        assert_eq!(table.libs[0].lib_type, "KiCad");
+
+        assert_eq!(
+            table.add(LibEntry::new("Cuprous", "x")),
+            Err(TableError::Duplicate("Cuprous".to_owned()))
+        );
+        table
+            .add(LibEntry::new("Extra", "${KIPRJMOD}/Extra.pretty"))
+            .unwrap();
+        table
+            .repoint("Cuprous", "${KIPRJMOD}/Cuprous.pretty")
+            .unwrap();
+        assert_eq!(table.remove("Extra").unwrap().name, "Extra");
+        assert!(table.remove("Extra").is_err());
+        assert_eq!(
+            table.to_string(),
+            "(fp_lib_table\n  (lib (name \"Cuprous\")(type \"KiCad\")(uri \"${KIPRJMOD}/Cuprous.pretty\")(options \"\")(descr \"\"))\n)\n"
+        );
+    }
+
+    #[test]
+    fn test_expand_vars() {
+        let lookup = |name: &str| (name == "KIPRJMOD").then(|| "/proj".to_owned());
+        assert_eq!(
+            expand_vars("${KIPRJMOD}/a/${UNKNOWN}/b", lookup),
+            "/proj/a/${UNKNOWN}/b"
+        );
+        assert_eq!(expand_vars("${KIPRJMOD", lookup), "${KIPRJMOD");
+    }
+
+    #[test]
+    fn test_bare_project_file() {
+        let dir = Path::new("demo.kicad_pro").parent().unwrap();
+        assert_eq!(
+            expand_vars("${KIPRJMOD}/Test.pretty", project_vars(dir)),
+            "./Test.pretty"
+        );
+        let dir = Path::new("boards/demo.kicad_pro").parent().unwrap();
+        assert_eq!(
+            expand_vars("${KIPRJMOD}/Test.pretty", project_vars(dir)),
+            "boards/Test.pretty"
+        );
+    }
+}
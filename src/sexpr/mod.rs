@@ -1,7 +1,22 @@
 pub mod analysis;
+pub mod bom;
+pub mod borrowed;
+pub mod de;
+pub mod diff;
 pub mod edit;
+pub mod format;
+pub mod fplib;
 pub mod json;
+pub mod libtable;
+pub mod model;
+pub mod netlist;
 pub mod parser;
+pub mod pattern;
+pub mod pincheck;
+pub mod position;
+pub mod project;
+pub mod rename;
+pub mod ser;
 pub mod simplifier;
 pub mod symlib;
 
@@ -9,18 +24,72 @@ use std::{collections::VecDeque, fmt::Display};
 use uuid::Uuid;
 
 use self::simplifier::Simplifier;
+use crate::span::Span;
 
 /// Indivisible values in an S-expression
 #[derive(Debug, PartialEq, Clone)]
 pub enum Atom {
     Symbol(String),
     Str(String),
-    Num(f64),
-    Bits(u64),
+    Num(Numeric<f64>),
+    Bits(Numeric<u64>),
     Bool(bool),
     Uuid(Uuid),
 }
 
+/// A numeric value and, if it was parsed, its original spelling.
+/// The spelling is kept so that `1.270` or `0x00010fc_ffffffff`
+/// are written back exactly as they were read.
+/// Changing the value discards the spelling.
+/// Only the value takes part in equality.
+#[derive(Debug, Clone)]
+pub struct Numeric<T> {
+    value: T,
+    text: Option<String>,
+}
+
+impl<T: Copy> Numeric<T> {
+    /// A value with no original spelling.
+    pub fn new(value: T) -> Self {
+        Self { value, text: None }
+    }
+
+    /// A value as spelled in the source text.
+    pub fn with_text(value: T, text: &str) -> Self {
+        Self {
+            value,
+            text: Some(text.to_owned()),
+        }
+    }
+
+    pub fn value(&self) -> T {
+        self.value
+    }
+
+    /// The original spelling, unless the value has been changed.
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    /// Change the value, discarding the original spelling.
+    pub fn set_value(&mut self, value: T) {
+        self.value = value;
+        self.text = None;
+    }
+}
+
+impl<T: PartialEq> PartialEq for Numeric<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: Copy> From<T> for Numeric<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
 impl Atom {
     pub fn as_string(&self) -> Option<&str> {
         match self {
@@ -36,13 +105,13 @@ impl Atom {
     }
     pub fn as_num(&self) -> Option<f64> {
         match self {
-            Atom::Num(n) => Some(*n),
+            Atom::Num(n) => Some(n.value()),
             _ => None,
         }
     }
     pub fn as_bits(&self) -> Option<u64> {
         match self {
-            Atom::Bits(n) => Some(*n),
+            Atom::Bits(n) => Some(n.value()),
             _ => None,
         }
     }
@@ -74,13 +143,13 @@ impl From<String> for Atom {
 
 impl From<f64> for Atom {
     fn from(value: f64) -> Self {
-        Atom::Num(value)
+        Atom::Num(value.into())
     }
 }
 
 impl From<u64> for Atom {
     fn from(value: u64) -> Self {
-        Atom::Bits(value)
+        Atom::Bits(value.into())
     }
 }
 
@@ -96,54 +165,117 @@ impl From<Uuid> for Atom {
     }
 }
 
-/// An S-expression
-#[derive(Debug, PartialEq, Clone)]
+/// An S-expression.
+/// Each node optionally records its location in the source text.
+/// The location does not take part in equality.
+#[derive(Debug, Clone)]
 pub enum Expr {
-    Constant(Atom),
-    List(VecDeque<Expr>),
+    Constant(Atom, Option<Span>),
+    List(VecDeque<Expr>, Option<Span>),
 }
 
 impl Expr {
     pub fn key(name: &str) -> Expr {
-        Expr::Constant(Atom::Symbol(name.to_string()))
+        Expr::Constant(Atom::Symbol(name.to_string()), None)
     }
 
     pub fn list(values: impl IntoIterator<Item = Expr>) -> Expr {
-        Expr::List(values.into_iter().collect())
+        Expr::List(values.into_iter().collect(), None)
     }
 
     pub fn empty() -> Expr {
-        Expr::List(VecDeque::new())
+        Expr::List(VecDeque::new(), None)
     }
 
     pub fn as_atom(&self) -> Option<&Atom> {
         match self {
-            Expr::Constant(a) => Some(a),
+            Expr::Constant(a, _) => Some(a),
             _ => None,
         }
     }
 
     pub fn as_list(&self) -> Option<&VecDeque<Expr>> {
         match self {
-            Expr::List(value) => Some(value),
+            Expr::List(value, _) => Some(value),
             _ => None,
         }
     }
 
     pub fn is_empty(&self) -> bool {
         match self {
-            Expr::List(value) => value.is_empty(),
+            Expr::List(value, _) => value.is_empty(),
             _ => false,
         }
     }
 
     pub fn into_deque(self) -> Option<VecDeque<Expr>> {
         match self {
-            Expr::List(elems) => Some(elems),
+            Expr::List(elems, _) => Some(elems),
             _ => None,
         }
     }
 
+    /// The symbol at the head of a list such as `(at 1 2)`.
+    pub fn head(&self) -> Option<&str> {
+        self.as_list()?.front()?.as_atom()?.as_symbol()
+    }
+
+    /// The elements of a list following its head.
+    pub fn args(&self) -> impl Iterator<Item = &Expr> {
+        self.as_list().into_iter().flat_map(|xs| xs.iter().skip(1))
+    }
+
+    /// The first element after the head, if it is a string, as in `(lib_id "Device:R")`.
+    pub fn str_arg(&self) -> Option<&str> {
+        self.args().next()?.as_atom()?.as_string()
+    }
+
+    /// The first element after the head, if it is a symbol, as in `(in_bom yes)`.
+    pub fn symbol_arg(&self) -> Option<&str> {
+        self.args().next()?.as_atom()?.as_symbol()
+    }
+
+    /// The first element after the head, if it is a number, as in `(unit 1)`.
+    pub fn num_arg(&self) -> Option<f64> {
+        self.args().next()?.as_atom()?.as_num()
+    }
+
+    /// The elements of a list which are lists headed by the symbol `key`.
+    pub fn children<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a Expr> {
+        self.as_list()
+            .into_iter()
+            .flat_map(|xs| xs.iter())
+            .filter(move |x| x.head() == Some(key))
+    }
+
+    /// The first element of a list which is a list headed by the symbol `key`.
+    pub fn child(&self, key: &str) -> Option<&Expr> {
+        self.as_list()?.iter().find(|x| x.head() == Some(key))
+    }
+
+    /// The location of this expression in its source text, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expr::Constant(_, span) | Expr::List(_, span) => *span,
+        }
+    }
+
+    /// This expression with its location replaced.
+    pub fn with_span(self, span: Option<Span>) -> Expr {
+        match self {
+            Expr::Constant(a, _) => Expr::Constant(a, span),
+            Expr::List(xs, _) => Expr::List(xs, span),
+        }
+    }
+
+    /// Describe a problem with this expression, prefixed by its location if known.
+    pub fn error(&self, message: &str) -> String {
+        match self.span() {
+            Some(span) => format!("{span}: {message}"),
+            None => message.to_owned(),
+        }
+    }
+
     pub fn extract(&self, simplifier: &impl Simplifier) -> Option<Expr> {
         simplifier.simplify(self)
     }
@@ -154,7 +286,17 @@ where
     A: Into<Atom>,
 {
     fn from(value: A) -> Self {
-        Expr::Constant(value.into())
+        Expr::Constant(value.into(), None)
+    }
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Expr::Constant(a, _), Expr::Constant(b, _)) => a == b,
+            (Expr::List(a, _), Expr::List(b, _)) => a == b,
+            _ => false,
+        }
     }
 }
 
@@ -169,8 +311,14 @@ impl Display for Atom {
         match self {
             Atom::Symbol(v) => v.fmt(f),
             Atom::Str(v) => serde_json::to_string(v).unwrap().fmt(f),
-            Atom::Num(v) => v.fmt(f),
-            Atom::Bits(v) => f.write_fmt(format_args!("0x{v:x}")),
+            Atom::Num(v) => match v.text() {
+                Some(t) => t.fmt(f),
+                None => v.value().fmt(f),
+            },
+            Atom::Bits(v) => match v.text() {
+                Some(t) => t.fmt(f),
+                None => f.write_fmt(format_args!("0x{:x}", v.value())),
+            },
             Atom::Bool(v) => v.fmt(f),
             Atom::Uuid(v) => v.fmt(f),
         }
@@ -185,8 +333,8 @@ impl Display for Expr {
 
 fn fmt_expr(expr: &Expr, indent: usize, target: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match expr {
-        Expr::Constant(atom) => atom.fmt(target)?,
-        Expr::List(exprs) => {
+        Expr::Constant(atom, _) => atom.fmt(target)?,
+        Expr::List(exprs, _) => {
             "(".fmt(target)?;
             let mut iter = exprs.iter();
             if let Some(expr) = iter.next() {
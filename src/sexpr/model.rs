@@ -0,0 +1,1502 @@
+//! A typed model of KiCad schematics and boards.
+//!
+//! Each type is read with `from_expr` and written with `to_expr`.  Items
+//! that a type does not model, or cannot read, are kept in its `unknown`
+//! field and written back after the item they followed, so nothing is lost
+//! when a file is read and written again, even one from a newer KiCad.
+//!
+//! Items are written back in the order they were read, and values that have
+//! not changed are written as they were spelled, so `1.270` stays `1.270`
+//! and the bare uuids and `true` of older files are kept.  New items are
+//! written in the order KiCad uses and new values in the spelling of
+//! KiCad 8: booleans as `yes` or `no` and uuids as strings.
+use super::{Atom, Expr};
+use std::collections::HashMap;
+
+/// Items of a list that a model type does not recognise.
+/// Each is kept with the key of the recognised item before it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Unknown(Vec<(Anchor, Expr)>);
+
+/// Where an unknown item is written back.
+#[derive(Debug, Clone, PartialEq)]
+enum Anchor {
+    /// After the positional arguments.
+    Start,
+    /// After the last item with this key.
+    After(String),
+    /// At the end.
+    End,
+}
+
+impl Unknown {
+    /// The unknown items in their original order.
+    pub fn items(&self) -> impl Iterator<Item = &Expr> {
+        self.0.iter().map(|(_, x)| x)
+    }
+
+    /// Add an item to be written at the end of the list.
+    pub fn push(&mut self, item: Expr) {
+        self.0.push((Anchor::End, item));
+    }
+
+    /// Remove the unknown items headed by `key`.
+    pub fn remove(&mut self, key: &str) {
+        self.0.retain(|(_, x)| x.head() != Some(key));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// How the items of a list were spelled and ordered when it was read.
+#[derive(Debug, Clone, Default)]
+pub struct Spelling {
+    /// The positional arguments and the children read as plain values.
+    items: Vec<Expr>,
+    /// The key of each item of the list, or `None` for an atom.
+    order: Vec<Option<String>>,
+}
+
+/// Spellings are not part of a value, so they never make two values differ.
+impl PartialEq for Spelling {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Spelling {
+    /// The positional argument at `index` as it was read.
+    fn arg(&self, index: usize) -> Option<&Expr> {
+        self.items
+            .iter()
+            .filter(|x| x.as_atom().is_some())
+            .nth(index)
+    }
+
+    /// The child headed by `key` as it was read.
+    fn child(&self, key: &str) -> Option<&Expr> {
+        self.items.iter().find(|x| x.head() == Some(key))
+    }
+
+    /// Put the items of a list back in the order they were read.
+    /// An item that was not read stays after the item before it.
+    fn reorder(&self, items: &mut [Expr]) {
+        if self.order.is_empty() {
+            return;
+        }
+        let mut places: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (place, key) in self.order.iter().enumerate() {
+            if let Some(key) = key {
+                places.entry(key).or_default().push(place);
+            }
+        }
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        let mut rank = None;
+        let ranks: Vec<Option<usize>> = items
+            .iter()
+            .map(|item| {
+                if let Some(key) = item.head() {
+                    let count = seen.entry(key).or_default();
+                    if let Some(&place) = places.get(key).and_then(|p| p.get(*count)) {
+                        rank = Some(place);
+                    }
+                    *count += 1;
+                }
+                rank
+            })
+            .collect();
+        let mut ranked: Vec<(Option<usize>, Expr)> =
+            ranks.into_iter().zip(items.iter().cloned()).collect();
+        ranked.sort_by_key(|(rank, _)| *rank);
+        for (item, (_, sorted)) in items.iter_mut().zip(ranked) {
+            *item = sorted;
+        }
+    }
+}
+
+/// `new`, with each atom that means the same as the one in its place in `old`
+/// spelled as it is there.
+fn respell(new: Expr, old: &Expr) -> Expr {
+    match (new, old) {
+        (Expr::Constant(a, _), Expr::Constant(b, _)) if same(&a, b) => old.clone(),
+        (Expr::List(xs, _), Expr::List(ys, span)) if xs.len() == ys.len() => Expr::List(
+            xs.into_iter().zip(ys).map(|(x, y)| respell(x, y)).collect(),
+            *span,
+        ),
+        (new, _) => new,
+    }
+}
+
+/// Do two atoms mean the same to the model?
+fn same(a: &Atom, b: &Atom) -> bool {
+    match (a, b) {
+        (Atom::Num(x), Atom::Num(y)) => x == y,
+        _ => meaning(a).is_some() && meaning(a) == meaning(b),
+    }
+}
+
+/// An atom as text, with booleans as `yes` or `no`.
+fn meaning(atom: &Atom) -> Option<String> {
+    match atom {
+        Atom::Str(s) | Atom::Symbol(s) => Some(s.clone()),
+        Atom::Uuid(u) => Some(u.to_string()),
+        Atom::Bool(b) => Some(if *b { "yes" } else { "no" }.to_owned()),
+        _ => None,
+    }
+}
+
+/// Reads the items of a list, keeping track of those not recognised.
+struct Reader<'a> {
+    items: Vec<(&'a Expr, bool)>,
+    spelled: Vec<Expr>,
+}
+
+impl<'a> Reader<'a> {
+    /// Read a list headed by one of `heads`.
+    fn new(list: &'a Expr, heads: &[&str]) -> Option<Self> {
+        list.head().filter(|h| heads.contains(h))?;
+        Some(Self {
+            items: list.args().map(|x| (x, false)).collect(),
+            spelled: Vec::new(),
+        })
+    }
+
+    /// The next positional argument, if `parse` accepts it.
+    fn arg<T>(&mut self, parse: impl Fn(&Expr) -> Option<T>) -> Option<T> {
+        let (item, used) = self
+            .items
+            .iter_mut()
+            .find(|(x, used)| !used && x.as_atom().is_some())?;
+        let value = parse(item)?;
+        *used = true;
+        self.spelled.push((*item).clone());
+        Some(value)
+    }
+
+    /// The first child headed by `key` that `parse` accepts.
+    fn take<T>(&mut self, key: &str, parse: impl Fn(&Expr) -> Option<T>) -> Option<T> {
+        let (value, item) = self.take_item(key, parse)?;
+        self.spelled.push(item.clone());
+        Some(value)
+    }
+
+    /// The first child headed by `key` that `parse` accepts, when `parse`
+    /// reads a model type, which keeps its own spelling.
+    fn take_model<T>(&mut self, key: &str, parse: impl Fn(&Expr) -> Option<T>) -> Option<T> {
+        Some(self.take_item(key, parse)?.0)
+    }
+
+    fn take_item<T>(
+        &mut self,
+        key: &str,
+        parse: impl Fn(&Expr) -> Option<T>,
+    ) -> Option<(T, &'a Expr)> {
+        self.items
+            .iter_mut()
+            .filter(|(x, used)| !used && x.head() == Some(key))
+            .find_map(|(x, used)| {
+                let value = parse(x)?;
+                *used = true;
+                Some((value, *x))
+            })
+    }
+
+    /// Every child headed by `key` that `parse` accepts.
+    fn take_all<T>(&mut self, key: &str, parse: impl Fn(&Expr) -> Option<T>) -> Vec<T> {
+        self.take_any(&[key], parse)
+    }
+
+    /// Every child headed by one of `keys` that `parse` accepts, in their original order.
+    fn take_any<T>(&mut self, keys: &[&str], parse: impl Fn(&Expr) -> Option<T>) -> Vec<T> {
+        self.items
+            .iter_mut()
+            .filter(|(x, used)| !used && x.head().is_some_and(|h| keys.contains(&h)))
+            .filter_map(|(x, used)| {
+                let value = parse(x)?;
+                *used = true;
+                Some(value)
+            })
+            .collect()
+    }
+
+    /// The spelling of the items read so far and the order of all the items.
+    fn spelling(&mut self) -> Spelling {
+        Spelling {
+            items: std::mem::take(&mut self.spelled),
+            order: self
+                .items
+                .iter()
+                .map(|(x, _)| x.head().map(str::to_owned))
+                .collect(),
+        }
+    }
+
+    /// The items not taken, anchored to the taken child before each.
+    fn finish(self) -> Unknown {
+        let mut anchor = Anchor::Start;
+        let mut unknown = Vec::new();
+        for (item, used) in self.items {
+            if !used {
+                unknown.push((anchor.clone(), item.clone()));
+            } else if let Some(key) = item.head() {
+                anchor = Anchor::After(key.to_owned());
+            }
+        }
+        Unknown(unknown)
+    }
+}
+
+/// Builds a list, placing unknown items after the items they followed
+/// and spelling unchanged values as they were read.
+struct Writer<'u> {
+    items: Vec<Expr>,
+    unknown: &'u Unknown,
+    spelling: &'u Spelling,
+    written: Vec<bool>,
+    current: Option<String>,
+    args: usize,
+}
+
+impl<'u> Writer<'u> {
+    fn new(head: &str, unknown: &'u Unknown, spelling: &'u Spelling) -> Self {
+        Self {
+            items: vec![Expr::key(head)],
+            unknown,
+            spelling,
+            written: vec![false; unknown.0.len()],
+            current: None,
+            args: 0,
+        }
+    }
+
+    /// Add a positional argument.
+    fn arg(&mut self, value: Expr) {
+        let value = match self.spelling.arg(self.args) {
+            Some(old) => respell(value, old),
+            None => value,
+        };
+        self.args += 1;
+        self.items.push(value);
+    }
+
+    /// Add a child list, first writing the unknown items which belong before it.
+    fn child(&mut self, child: Expr) {
+        let key = child.head().map(str::to_owned);
+        let child = match key.as_deref().and_then(|k| self.spelling.child(k)) {
+            Some(old) => respell(child, old),
+            None => child,
+        };
+        if self.current.is_none() {
+            self.flush(|a| *a == Anchor::Start);
+        }
+        if self.current != key {
+            self.flush_current();
+            self.current = key;
+        }
+        self.items.push(child);
+    }
+
+    /// Add `(key args..)`.
+    fn item(&mut self, key: &str, args: impl IntoIterator<Item = Expr>) {
+        self.child(list(key, args));
+    }
+
+    fn flush_current(&mut self) {
+        if let Some(current) = self.current.clone() {
+            self.flush(|a| *a == Anchor::After(current.clone()));
+        }
+    }
+
+    fn flush(&mut self, select: impl Fn(&Anchor) -> bool) {
+        for ((anchor, item), written) in self.unknown.0.iter().zip(&mut self.written) {
+            if !*written && select(anchor) {
+                self.items.push(item.clone());
+                *written = true;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Expr {
+        self.flush(|a| *a == Anchor::Start);
+        self.flush_current();
+        self.flush(|_| true);
+        self.spelling.reorder(&mut self.items[1..]);
+        Expr::list(self.items)
+    }
+}
+
+fn list(key: &str, args: impl IntoIterator<Item = Expr>) -> Expr {
+    Expr::list([Expr::key(key)].into_iter().chain(args))
+}
+
+fn num(value: f64) -> Expr {
+    Atom::from(value).into()
+}
+
+fn yes_no(value: bool) -> Expr {
+    Expr::key(if value { "yes" } else { "no" })
+}
+
+/// A string, symbol or uuid as text.
+fn text(expr: &Expr) -> Option<String> {
+    match expr.as_atom()? {
+        Atom::Str(s) | Atom::Symbol(s) => Some(s.clone()),
+        Atom::Uuid(u) => Some(u.to_string()),
+        _ => None,
+    }
+}
+
+fn symbol(expr: &Expr) -> Option<String> {
+    expr.as_atom()?.as_symbol().map(str::to_owned)
+}
+
+fn number(expr: &Expr) -> Option<f64> {
+    expr.as_atom()?.as_num()
+}
+
+/// The arguments of a child which has exactly `n` of them.
+fn args(child: &Expr, n: usize) -> Option<Vec<&Expr>> {
+    let args: Vec<&Expr> = child.args().collect();
+    (args.len() == n).then_some(args)
+}
+
+fn text_of(child: &Expr) -> Option<String> {
+    text(args(child, 1)?[0])
+}
+
+fn symbol_of(child: &Expr) -> Option<String> {
+    symbol(args(child, 1)?[0])
+}
+
+fn num_of(child: &Expr) -> Option<f64> {
+    number(args(child, 1)?[0])
+}
+
+fn integer(expr: &Expr) -> Option<i64> {
+    number(expr).filter(|n| n.fract() == 0.0).map(|n| n as i64)
+}
+
+fn int_of(child: &Expr) -> Option<i64> {
+    integer(args(child, 1)?[0])
+}
+
+fn bool_of(child: &Expr) -> Option<bool> {
+    match args(child, 1)?[0].as_atom()? {
+        Atom::Bool(b) => Some(*b),
+        Atom::Symbol(s) if s == "yes" => Some(true),
+        Atom::Symbol(s) if s == "no" => Some(false),
+        _ => None,
+    }
+}
+
+fn xy_of(child: &Expr) -> Option<(f64, f64)> {
+    let xy = args(child, 2)?;
+    Some((number(xy[0])?, number(xy[1])?))
+}
+
+fn texts_of(child: &Expr) -> Option<Vec<String>> {
+    child.args().map(text).collect()
+}
+
+/// `(pts (xy x y) ...)`
+fn pts_of(child: &Expr) -> Option<Vec<(f64, f64)>> {
+    child
+        .args()
+        .map(|xy| xy.head().filter(|h| *h == "xy").and(xy_of(xy)))
+        .collect()
+}
+
+fn pts(points: &[(f64, f64)]) -> Expr {
+    list(
+        "pts",
+        points.iter().map(|(x, y)| list("xy", [num(*x), num(*y)])),
+    )
+}
+
+/// A position and rotation, as in `(at 10 20 90)`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+    pub angle: Option<f64>,
+}
+
+impl Position {
+    pub fn from_expr(at: &Expr) -> Option<Position> {
+        let args: Vec<f64> = at.args().map(number).collect::<Option<_>>()?;
+        match args[..] {
+            [x, y] => Some(Position { x, y, angle: None }),
+            [x, y, angle] => Some(Position {
+                x,
+                y,
+                angle: Some(angle),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        list(
+            "at",
+            [num(self.x), num(self.y)]
+                .into_iter()
+                .chain(self.angle.map(num)),
+        )
+    }
+}
+
+/// A named value on a symbol, sheet or footprint.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+    pub at: Option<Position>,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Property {
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_expr(expr: &Expr) -> Option<Property> {
+        let mut r = Reader::new(expr, &["property"])?;
+        Some(Property {
+            name: r.arg(text)?,
+            value: r.arg(text)?,
+            at: r.take("at", Position::from_expr),
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new("property", &self.unknown, &self.spelling);
+        w.arg(self.name.as_str().into());
+        w.arg(self.value.as_str().into());
+        if let Some(at) = &self.at {
+            w.child(at.to_expr());
+        }
+        w.finish()
+    }
+}
+
+/// The value of the property with the given name.
+fn find<'a>(properties: &'a [Property], name: &str) -> Option<&'a str> {
+    properties
+        .iter()
+        .find(|p| p.name == name)
+        .map(|p| p.value.as_str())
+}
+
+/// Set a property, adding it if there is none with that name.
+fn set(properties: &mut Vec<Property>, name: &str, value: &str) {
+    match properties.iter_mut().find(|p| p.name == name) {
+        Some(p) => p.value = value.to_owned(),
+        None => properties.push(Property::new(name, value)),
+    }
+}
+
+/// A string with display settings, such as the name of a pin.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Text {
+    pub text: String,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Text {
+    fn from_expr(expr: &Expr, key: &str) -> Option<Text> {
+        let mut r = Reader::new(expr, &[key])?;
+        Some(Text {
+            text: r.arg(text)?,
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    fn to_expr(&self, key: &str) -> Expr {
+        let mut w = Writer::new(key, &self.unknown, &self.spelling);
+        w.arg(self.text.as_str().into());
+        w.finish()
+    }
+}
+
+/// A schematic sheet file, `.kicad_sch`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schematic {
+    pub version: Option<i64>,
+    pub generator: Option<String>,
+    pub uuid: Option<String>,
+    pub paper: Option<String>,
+    pub lib_symbols: Vec<LibSymbol>,
+    pub wires: Vec<Wire>,
+    pub labels: Vec<Label>,
+    pub symbols: Vec<SymbolInstance>,
+    pub sheets: Vec<Sheet>,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Schematic {
+    pub fn from_expr(expr: &Expr) -> Option<Schematic> {
+        let mut r = Reader::new(expr, &["kicad_sch"])?;
+        let version = r.take("version", int_of);
+        let generator = r.take("generator", text_of);
+        let uuid = r.take("uuid", text_of);
+        let paper = r.take("paper", text_of);
+        let lib_symbols = r
+            .take_model("lib_symbols", |l| {
+                l.args().map(LibSymbol::from_expr).collect::<Option<_>>()
+            })
+            .unwrap_or_default();
+        let wires = r.take_all("wire", Wire::from_expr);
+        let labels = r.take_any(&LABEL_HEADINGS, Label::from_expr);
+        let symbols = r.take_all("symbol", SymbolInstance::from_expr);
+        let sheets = r.take_all("sheet", Sheet::from_expr);
+        Some(Schematic {
+            version,
+            generator,
+            uuid,
+            paper,
+            lib_symbols,
+            wires,
+            labels,
+            symbols,
+            sheets,
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new("kicad_sch", &self.unknown, &self.spelling);
+        if let Some(version) = self.version {
+            w.item("version", [num(version as f64)]);
+        }
+        if let Some(generator) = &self.generator {
+            w.item("generator", [generator.as_str().into()]);
+        }
+        if let Some(uuid) = &self.uuid {
+            w.item("uuid", [uuid.as_str().into()]);
+        }
+        if let Some(paper) = &self.paper {
+            w.item("paper", [paper.as_str().into()]);
+        }
+        // a library that could not be read is kept whole as an unknown item
+        if !self
+            .unknown
+            .items()
+            .any(|x| x.head() == Some("lib_symbols"))
+        {
+            w.item(
+                "lib_symbols",
+                self.lib_symbols.iter().map(LibSymbol::to_expr),
+            );
+        }
+        for wire in &self.wires {
+            w.child(wire.to_expr());
+        }
+        for label in &self.labels {
+            w.child(label.to_expr());
+        }
+        for symbol in &self.symbols {
+            w.child(symbol.to_expr());
+        }
+        for sheet in &self.sheets {
+            w.child(sheet.to_expr());
+        }
+        w.finish()
+    }
+
+    /// The library symbol with the given name, as used by `lib_id`.
+    pub fn lib_symbol(&self, name: &str) -> Option<&LibSymbol> {
+        self.lib_symbols.iter().find(|s| s.name == name)
+    }
+}
+
+/// A symbol in a library, or embedded in a schematic, or one unit of a symbol.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LibSymbol {
+    pub name: String,
+    pub extends: Option<String>,
+    pub in_bom: Option<bool>,
+    pub on_board: Option<bool>,
+    pub properties: Vec<Property>,
+    /// The units of a symbol, named `name_unit_style`.
+    pub units: Vec<LibSymbol>,
+    pub pins: Vec<Pin>,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl LibSymbol {
+    pub fn from_expr(expr: &Expr) -> Option<LibSymbol> {
+        let mut r = Reader::new(expr, &["symbol"])?;
+        Some(LibSymbol {
+            name: r.arg(text)?,
+            extends: r.take("extends", text_of),
+            in_bom: r.take("in_bom", bool_of),
+            on_board: r.take("on_board", bool_of),
+            properties: r.take_all("property", Property::from_expr),
+            units: r.take_all("symbol", LibSymbol::from_expr),
+            pins: r.take_all("pin", Pin::from_expr),
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new("symbol", &self.unknown, &self.spelling);
+        w.arg(self.name.as_str().into());
+        if let Some(extends) = &self.extends {
+            w.item("extends", [extends.as_str().into()]);
+        }
+        if let Some(in_bom) = self.in_bom {
+            w.item("in_bom", [yes_no(in_bom)]);
+        }
+        if let Some(on_board) = self.on_board {
+            w.item("on_board", [yes_no(on_board)]);
+        }
+        for property in &self.properties {
+            w.child(property.to_expr());
+        }
+        for unit in &self.units {
+            w.child(unit.to_expr());
+        }
+        for pin in &self.pins {
+            w.child(pin.to_expr());
+        }
+        w.finish()
+    }
+
+    pub fn property(&self, name: &str) -> Option<&str> {
+        find(&self.properties, name)
+    }
+
+    pub fn set_property(&mut self, name: &str, value: &str) {
+        set(&mut self.properties, name, value)
+    }
+
+    /// The pins of the symbol and all its units.
+    pub fn all_pins(&self) -> impl Iterator<Item = &Pin> {
+        self.pins
+            .iter()
+            .chain(self.units.iter().flat_map(|u| u.pins.iter()))
+    }
+}
+
+/// A pin of a library symbol.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pin {
+    pub electrical_type: String,
+    pub graphic_style: String,
+    pub at: Position,
+    pub length: f64,
+    pub name: Text,
+    pub number: Text,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Pin {
+    pub fn from_expr(expr: &Expr) -> Option<Pin> {
+        let mut r = Reader::new(expr, &["pin"])?;
+        Some(Pin {
+            electrical_type: r.arg(symbol)?,
+            graphic_style: r.arg(symbol)?,
+            at: r.take("at", Position::from_expr)?,
+            length: r.take("length", num_of)?,
+            name: r.take_model("name", |n| Text::from_expr(n, "name"))?,
+            number: r.take_model("number", |n| Text::from_expr(n, "number"))?,
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new("pin", &self.unknown, &self.spelling);
+        w.arg(Expr::key(&self.electrical_type));
+        w.arg(Expr::key(&self.graphic_style));
+        w.child(self.at.to_expr());
+        w.item("length", [num(self.length)]);
+        w.child(self.name.to_expr("name"));
+        w.child(self.number.to_expr("number"));
+        w.finish()
+    }
+}
+
+/// A symbol placed in a schematic.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolInstance {
+    /// The name of the embedded library symbol, when it differs from `lib_id`.
+    pub lib_name: Option<String>,
+    pub lib_id: String,
+    pub at: Position,
+    pub mirror: Option<String>,
+    pub unit: Option<i64>,
+    pub in_bom: Option<bool>,
+    pub on_board: Option<bool>,
+    pub dnp: Option<bool>,
+    pub uuid: Option<String>,
+    pub properties: Vec<Property>,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl SymbolInstance {
+    pub fn from_expr(expr: &Expr) -> Option<SymbolInstance> {
+        let mut r = Reader::new(expr, &["symbol"])?;
+        Some(SymbolInstance {
+            lib_name: r.take("lib_name", text_of),
+            lib_id: r.take("lib_id", text_of)?,
+            at: r.take("at", Position::from_expr)?,
+            mirror: r.take("mirror", symbol_of),
+            unit: r.take("unit", int_of),
+            in_bom: r.take("in_bom", bool_of),
+            on_board: r.take("on_board", bool_of),
+            dnp: r.take("dnp", bool_of),
+            uuid: r.take("uuid", text_of),
+            properties: r.take_all("property", Property::from_expr),
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new("symbol", &self.unknown, &self.spelling);
+        if let Some(lib_name) = &self.lib_name {
+            w.item("lib_name", [lib_name.as_str().into()]);
+        }
+        w.item("lib_id", [self.lib_id.as_str().into()]);
+        w.child(self.at.to_expr());
+        if let Some(mirror) = &self.mirror {
+            w.item("mirror", [Expr::key(mirror)]);
+        }
+        if let Some(unit) = self.unit {
+            w.item("unit", [num(unit as f64)]);
+        }
+        for (key, flag) in [
+            ("in_bom", self.in_bom),
+            ("on_board", self.on_board),
+            ("dnp", self.dnp),
+        ] {
+            if let Some(flag) = flag {
+                w.item(key, [yes_no(flag)]);
+            }
+        }
+        if let Some(uuid) = &self.uuid {
+            w.item("uuid", [uuid.as_str().into()]);
+        }
+        for property in &self.properties {
+            w.child(property.to_expr());
+        }
+        w.finish()
+    }
+
+    pub fn property(&self, name: &str) -> Option<&str> {
+        find(&self.properties, name)
+    }
+
+    pub fn set_property(&mut self, name: &str, value: &str) {
+        set(&mut self.properties, name, value)
+    }
+
+    pub fn reference(&self) -> Option<&str> {
+        self.property("Reference")
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        self.property("Value")
+    }
+}
+
+/// A hierarchical sheet placed in a schematic.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sheet {
+    pub at: Position,
+    pub size: (f64, f64),
+    pub uuid: Option<String>,
+    pub properties: Vec<Property>,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Sheet {
+    pub fn from_expr(expr: &Expr) -> Option<Sheet> {
+        let mut r = Reader::new(expr, &["sheet"])?;
+        Some(Sheet {
+            at: r.take("at", Position::from_expr)?,
+            size: r.take("size", xy_of)?,
+            uuid: r.take("uuid", text_of),
+            properties: r.take_all("property", Property::from_expr),
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new("sheet", &self.unknown, &self.spelling);
+        w.child(self.at.to_expr());
+        w.item("size", [num(self.size.0), num(self.size.1)]);
+        if let Some(uuid) = &self.uuid {
+            w.item("uuid", [uuid.as_str().into()]);
+        }
+        for property in &self.properties {
+            w.child(property.to_expr());
+        }
+        w.finish()
+    }
+
+    /// The sheet name, from the property named as in KiCad 8 or earlier.
+    pub fn name(&self) -> Option<&str> {
+        self.property_either("Sheetname", "Sheet name")
+    }
+
+    /// The file holding the sheet's schematic, relative to the project.
+    pub fn file(&self) -> Option<&str> {
+        self.property_either("Sheetfile", "Sheet file")
+    }
+
+    fn property_either(&self, a: &str, b: &str) -> Option<&str> {
+        find(&self.properties, a).or_else(|| find(&self.properties, b))
+    }
+}
+
+/// A wire joining points in a schematic.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Wire {
+    pub pts: Vec<(f64, f64)>,
+    pub uuid: Option<String>,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Wire {
+    pub fn from_expr(expr: &Expr) -> Option<Wire> {
+        let mut r = Reader::new(expr, &["wire"])?;
+        Some(Wire {
+            pts: r.take("pts", pts_of)?,
+            uuid: r.take("uuid", text_of),
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new("wire", &self.unknown, &self.spelling);
+        w.child(pts(&self.pts));
+        if let Some(uuid) = &self.uuid {
+            w.item("uuid", [uuid.as_str().into()]);
+        }
+        w.finish()
+    }
+}
+
+/// The scope of a net label.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LabelKind {
+    #[default]
+    Local,
+    Global,
+    Hierarchical,
+}
+
+const LABEL_HEADINGS: [&str; 3] = ["label", "global_label", "hierarchical_label"];
+
+impl LabelKind {
+    pub fn heading(self) -> &'static str {
+        match self {
+            LabelKind::Local => "label",
+            LabelKind::Global => "global_label",
+            LabelKind::Hierarchical => "hierarchical_label",
+        }
+    }
+}
+
+/// A net label in a schematic.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Label {
+    pub kind: LabelKind,
+    pub text: String,
+    /// The shape of a global or hierarchical label, such as `input`.
+    pub shape: Option<String>,
+    pub at: Position,
+    pub uuid: Option<String>,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Label {
+    pub fn from_expr(expr: &Expr) -> Option<Label> {
+        let mut r = Reader::new(expr, &LABEL_HEADINGS)?;
+        let kind = match expr.head()? {
+            "global_label" => LabelKind::Global,
+            "hierarchical_label" => LabelKind::Hierarchical,
+            _ => LabelKind::Local,
+        };
+        Some(Label {
+            kind,
+            text: r.arg(text)?,
+            shape: r.take("shape", symbol_of),
+            at: r.take("at", Position::from_expr)?,
+            uuid: r.take("uuid", text_of),
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new(self.kind.heading(), &self.unknown, &self.spelling);
+        w.arg(self.text.as_str().into());
+        if let Some(shape) = &self.shape {
+            w.item("shape", [Expr::key(shape)]);
+        }
+        w.child(self.at.to_expr());
+        if let Some(uuid) = &self.uuid {
+            w.item("uuid", [uuid.as_str().into()]);
+        }
+        w.finish()
+    }
+}
+
+/// A printed circuit board file, `.kicad_pcb`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Board {
+    pub version: Option<i64>,
+    pub generator: Option<String>,
+    pub nets: Vec<Net>,
+    pub footprints: Vec<Footprint>,
+    pub tracks: Vec<Track>,
+    pub vias: Vec<Via>,
+    pub zones: Vec<Zone>,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Board {
+    pub fn from_expr(expr: &Expr) -> Option<Board> {
+        let mut r = Reader::new(expr, &["kicad_pcb"])?;
+        let version = r.take("version", int_of);
+        let generator = r.take("generator", text_of);
+        let nets = r.take_all("net", Net::from_expr);
+        let footprints = r.take_all("footprint", Footprint::from_expr);
+        let tracks = r.take_any(&["segment", "arc"], Track::from_expr);
+        let vias = r.take_all("via", Via::from_expr);
+        let zones = r.take_all("zone", Zone::from_expr);
+        Some(Board {
+            version,
+            generator,
+            nets,
+            footprints,
+            tracks,
+            vias,
+            zones,
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new("kicad_pcb", &self.unknown, &self.spelling);
+        if let Some(version) = self.version {
+            w.item("version", [num(version as f64)]);
+        }
+        if let Some(generator) = &self.generator {
+            w.item("generator", [generator.as_str().into()]);
+        }
+        for net in &self.nets {
+            w.child(net.to_expr());
+        }
+        for footprint in &self.footprints {
+            w.child(footprint.to_expr());
+        }
+        for track in &self.tracks {
+            w.child(track.to_expr());
+        }
+        for via in &self.vias {
+            w.child(via.to_expr());
+        }
+        for zone in &self.zones {
+            w.child(zone.to_expr());
+        }
+        w.finish()
+    }
+
+    /// The name of a net by number.
+    pub fn net_name(&self, number: i64) -> Option<&str> {
+        self.nets
+            .iter()
+            .find(|n| n.number == number)
+            .map(|n| n.name.as_str())
+    }
+}
+
+/// A net of a board, or the net of a pad, as `(net 1 "GND")`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Net {
+    pub number: i64,
+    pub name: String,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Net {
+    pub fn from_expr(expr: &Expr) -> Option<Net> {
+        let mut r = Reader::new(expr, &["net"])?;
+        Some(Net {
+            number: r.arg(integer)?,
+            name: r.arg(text)?,
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new("net", &self.unknown, &self.spelling);
+        w.arg(num(self.number as f64));
+        w.arg(self.name.as_str().into());
+        w.finish()
+    }
+}
+
+/// A footprint placed on a board.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Footprint {
+    pub lib_id: String,
+    pub layer: String,
+    pub uuid: Option<String>,
+    pub at: Position,
+    pub properties: Vec<Property>,
+    pub pads: Vec<Pad>,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Footprint {
+    pub fn from_expr(expr: &Expr) -> Option<Footprint> {
+        let mut r = Reader::new(expr, &["footprint"])?;
+        Some(Footprint {
+            lib_id: r.arg(text)?,
+            layer: r.take("layer", text_of)?,
+            uuid: r.take("uuid", text_of),
+            at: r.take("at", Position::from_expr)?,
+            properties: r.take_all("property", Property::from_expr),
+            pads: r.take_all("pad", Pad::from_expr),
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new("footprint", &self.unknown, &self.spelling);
+        w.arg(self.lib_id.as_str().into());
+        w.item("layer", [self.layer.as_str().into()]);
+        if let Some(uuid) = &self.uuid {
+            w.item("uuid", [uuid.as_str().into()]);
+        }
+        w.child(self.at.to_expr());
+        for property in &self.properties {
+            w.child(property.to_expr());
+        }
+        for pad in &self.pads {
+            w.child(pad.to_expr());
+        }
+        w.finish()
+    }
+
+    pub fn property(&self, name: &str) -> Option<&str> {
+        find(&self.properties, name)
+    }
+
+    pub fn set_property(&mut self, name: &str, value: &str) {
+        set(&mut self.properties, name, value)
+    }
+
+    pub fn reference(&self) -> Option<&str> {
+        self.property("Reference")
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        self.property("Value")
+    }
+}
+
+/// A pad of a footprint.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pad {
+    pub number: String,
+    /// `smd`, `thru_hole`, `np_thru_hole` or `connect`.
+    pub pad_type: String,
+    pub shape: String,
+    pub at: Position,
+    pub size: (f64, f64),
+    /// The diameter of a round hole.  Other holes are kept as unknown items.
+    pub drill: Option<f64>,
+    pub layers: Vec<String>,
+    pub net: Option<Net>,
+    pub uuid: Option<String>,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Pad {
+    pub fn from_expr(expr: &Expr) -> Option<Pad> {
+        let mut r = Reader::new(expr, &["pad"])?;
+        Some(Pad {
+            number: r.arg(text)?,
+            pad_type: r.arg(symbol)?,
+            shape: r.arg(symbol)?,
+            at: r.take("at", Position::from_expr)?,
+            size: r.take("size", xy_of)?,
+            drill: r.take("drill", num_of),
+            layers: r.take("layers", texts_of).unwrap_or_default(),
+            net: r.take_model("net", Net::from_expr),
+            uuid: r.take("uuid", text_of),
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new("pad", &self.unknown, &self.spelling);
+        w.arg(self.number.as_str().into());
+        w.arg(Expr::key(&self.pad_type));
+        w.arg(Expr::key(&self.shape));
+        w.child(self.at.to_expr());
+        w.item("size", [num(self.size.0), num(self.size.1)]);
+        if let Some(drill) = self.drill {
+            w.item("drill", [num(drill)]);
+        }
+        w.item("layers", self.layers.iter().map(|l| l.as_str().into()));
+        if let Some(net) = &self.net {
+            w.child(net.to_expr());
+        }
+        if let Some(uuid) = &self.uuid {
+            w.item("uuid", [uuid.as_str().into()]);
+        }
+        w.finish()
+    }
+}
+
+/// A straight or arc track segment on a board.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Track {
+    pub start: (f64, f64),
+    /// The midpoint of an arc.
+    pub mid: Option<(f64, f64)>,
+    pub end: (f64, f64),
+    pub width: f64,
+    pub layer: String,
+    pub net: i64,
+    pub uuid: Option<String>,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Track {
+    pub fn from_expr(expr: &Expr) -> Option<Track> {
+        let mut r = Reader::new(expr, &["segment", "arc"])?;
+        let start = r.take("start", xy_of)?;
+        let mid = r.take("mid", xy_of);
+        if (expr.head() == Some("arc")) != mid.is_some() {
+            return None;
+        }
+        Some(Track {
+            start,
+            mid,
+            end: r.take("end", xy_of)?,
+            width: r.take("width", num_of)?,
+            layer: r.take("layer", text_of)?,
+            net: r.take("net", int_of)?,
+            uuid: r.take("uuid", text_of),
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let head = if self.mid.is_some() { "arc" } else { "segment" };
+        let mut w = Writer::new(head, &self.unknown, &self.spelling);
+        let xy = |(x, y): (f64, f64)| [num(x), num(y)];
+        w.item("start", xy(self.start));
+        if let Some(mid) = self.mid {
+            w.item("mid", xy(mid));
+        }
+        w.item("end", xy(self.end));
+        w.item("width", [num(self.width)]);
+        w.item("layer", [self.layer.as_str().into()]);
+        w.item("net", [num(self.net as f64)]);
+        if let Some(uuid) = &self.uuid {
+            w.item("uuid", [uuid.as_str().into()]);
+        }
+        w.finish()
+    }
+}
+
+/// A via on a board.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Via {
+    pub at: (f64, f64),
+    pub size: f64,
+    pub drill: f64,
+    pub layers: Vec<String>,
+    pub net: i64,
+    pub uuid: Option<String>,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Via {
+    pub fn from_expr(expr: &Expr) -> Option<Via> {
+        let mut r = Reader::new(expr, &["via"])?;
+        Some(Via {
+            at: r.take("at", xy_of)?,
+            size: r.take("size", num_of)?,
+            drill: r.take("drill", num_of)?,
+            layers: r.take("layers", texts_of)?,
+            net: r.take("net", int_of)?,
+            uuid: r.take("uuid", text_of),
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new("via", &self.unknown, &self.spelling);
+        w.item("at", [num(self.at.0), num(self.at.1)]);
+        w.item("size", [num(self.size)]);
+        w.item("drill", [num(self.drill)]);
+        w.item("layers", self.layers.iter().map(|l| l.as_str().into()));
+        w.item("net", [num(self.net as f64)]);
+        if let Some(uuid) = &self.uuid {
+            w.item("uuid", [uuid.as_str().into()]);
+        }
+        w.finish()
+    }
+}
+
+/// A copper zone or keepout area on a board.
+/// The outline and fill are kept as unknown items.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Zone {
+    pub net: i64,
+    pub net_name: String,
+    pub layers: Vec<String>,
+    pub uuid: Option<String>,
+    pub name: Option<String>,
+    pub spelling: Spelling,
+    pub unknown: Unknown,
+}
+
+impl Zone {
+    pub fn from_expr(expr: &Expr) -> Option<Zone> {
+        let mut r = Reader::new(expr, &["zone"])?;
+        Some(Zone {
+            net: r.take("net", int_of)?,
+            net_name: r.take("net_name", text_of)?,
+            layers: r
+                .take("layer", |l| Some(vec![text_of(l)?]))
+                .or_else(|| r.take("layers", texts_of))?,
+            uuid: r.take("uuid", text_of),
+            name: r.take("name", text_of),
+            spelling: r.spelling(),
+            unknown: r.finish(),
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let mut w = Writer::new("zone", &self.unknown, &self.spelling);
+        w.item("net", [num(self.net as f64)]);
+        w.item("net_name", [self.net_name.as_str().into()]);
+        let key = if self.layers.len() == 1 {
+            "layer"
+        } else {
+            "layers"
+        };
+        w.item(key, self.layers.iter().map(|l| l.as_str().into()));
+        if let Some(uuid) = &self.uuid {
+            w.item("uuid", [uuid.as_str().into()]);
+        }
+        if let Some(name) = &self.name {
+            w.item("name", [name.as_str().into()]);
+        }
+        w.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Board, LabelKind, Position, Schematic};
+    use crate::{
+        parse_file::parse_with,
+        sexpr::{format::to_kicad_string, parser::parse_s_expr},
+    };
+
+    const SCHEMATIC: &str = r#"
+        (kicad_sch (version 20231120) (generator "eeschema") (generator_version "8.0")
+            (uuid "1b4e2c8a-0000-4000-8000-000000000001") (paper "A4")
+            (lib_symbols
+                (symbol "Device:R" (pin_numbers hide) (exclude_from_sim no) (in_bom yes) (on_board yes)
+                    (property "Reference" "R" (at 2.032 0 90) (effects (font (size 1.27 1.27))))
+                    (symbol "R_0_1" (rectangle (start -1.016 -2.54) (end 1.016 2.54)))
+                    (symbol "R_1_1"
+                        (pin passive line (at 0 3.81 270) (length 1.27)
+                            (name "~" (effects (font (size 1.27 1.27))))
+                            (number "1" (effects (font (size 1.27 1.27))))))))
+            (junction (at 100 50) (diameter 0) (color 0 0 0 0) (uuid "j1"))
+            (wire (pts (xy 100 50) (xy 120 50)) (stroke (width 0) (type default)) (uuid "w1"))
+            (label "SDA" (at 110 50 0) (effects (font (size 1.27 1.27))) (uuid "l1"))
+            (global_label "VBUS" (shape input) (at 90 50 180) (fields_autoplaced yes) (uuid "l2"))
+            (symbol (lib_id "Device:R") (at 100 60 90) (unit 1) (exclude_from_sim no) (in_bom yes) (on_board yes) (dnp no)
+                (uuid "s1")
+                (property "Reference" "R1" (at 100 58 0))
+                (property "Value" "10k" (at 100 62 0))
+                (pin "1" (uuid "p1"))
+                (instances (project "demo" (path "/1b4e" (reference "R1") (unit 1)))))
+            (sheet (at 150 40) (size 20 10) (stroke (width 0.1524) (type solid)) (uuid "sh1")
+                (property "Sheetname" "Power" (at 150 39 0))
+                (property "Sheetfile" "power.kicad_sch" (at 150 51 0)))
+            (sheet_instances (path "/" (page "1"))))
+    "#;
+
+    const BOARD: &str = r#"
+        (kicad_pcb (version 20240108) (generator "pcbnew") (general (thickness 1.6))
+            (net 0 "") (net 1 "GND")
+            (footprint "Resistor_SMD:R_0603" (layer "F.Cu") (uuid "f1") (at 100 100 90)
+                (property "Reference" "R1" (at 0 -1.43 90) (layer "F.SilkS"))
+                (property "Value" "10k" (at 0 1.43 90) (layer "F.Fab"))
+                (attr smd)
+                (fp_line (start -0.16 -0.51) (end 0.16 -0.51) (layer "F.SilkS"))
+                (pad "1" smd roundrect (at -0.8 0 90) (size 0.8 0.95) (layers "F.Cu" "F.Paste" "F.Mask")
+                    (roundrect_rratio 0.25) (net 1 "GND") (uuid "pd1"))
+                (pad "2" thru_hole oval (at 0.8 0 90) (size 1.2 1.7) (drill oval 0.6 1.1) (layers "*.Cu")))
+            (segment (start 99.2 100) (end 90 100) (width 0.25) (layer "F.Cu") (net 1) (uuid "t1"))
+            (via (at 90 100) (size 0.6) (drill 0.3) (layers "F.Cu" "B.Cu") (net 1) (uuid "v1"))
+            (arc (start 90 100) (mid 88 98) (end 86 100) (width 0.25) (layer "B.Cu") (net 1) (uuid "t2"))
+            (zone (net 1) (net_name "GND") (layer "B.Cu") (uuid "z1") (hatch edge 0.5)
+                (polygon (pts (xy 0 0) (xy 10 0) (xy 10 10)))))
+    "#;
+
+    #[test]
+    fn test_schematic() {
+        let expr = parse_with(SCHEMATIC, parse_s_expr).unwrap();
+        let schematic = Schematic::from_expr(&expr).unwrap();
+        assert_eq!(schematic.version, Some(20231120));
+
+        let r = schematic.lib_symbol("Device:R").unwrap();
+        assert_eq!(r.in_bom, Some(true));
+        let pins: Vec<_> = r.all_pins().map(|p| p.number.text.as_str()).collect();
+        assert_eq!(pins, ["1"]);
+
+        assert_eq!(schematic.wires[0].pts, [(100.0, 50.0), (120.0, 50.0)]);
+        assert_eq!(schematic.labels[1].kind, LabelKind::Global);
+        assert_eq!(schematic.labels[1].shape.as_deref(), Some("input"));
+        assert_eq!(schematic.symbols[0].reference(), Some("R1"));
+        assert_eq!(schematic.symbols[0].dnp, Some(false));
+        assert_eq!(schematic.sheets[0].file(), Some("power.kicad_sch"));
+
+        assert_eq!(schematic.to_expr(), expr);
+    }
+
+    #[test]
+    fn test_board() {
+        let expr = parse_with(BOARD, parse_s_expr).unwrap();
+        let board = Board::from_expr(&expr).unwrap();
+        assert_eq!(board.net_name(1), Some("GND"));
+        assert_eq!(board.tracks.len(), 2);
+        assert_eq!(board.tracks[1].mid, Some((88.0, 98.0)));
+        assert_eq!(board.vias[0].layers, ["F.Cu", "B.Cu"]);
+        assert_eq!(board.zones[0].layers, ["B.Cu"]);
+
+        let footprint = &board.footprints[0];
+        assert_eq!(footprint.value(), Some("10k"));
+        assert_eq!(footprint.pads[0].net.as_ref().unwrap().name, "GND");
+        // an oval hole is not modelled but is kept
+        assert_eq!(footprint.pads[1].drill, None);
+        assert!(!footprint.pads[1].unknown.is_empty());
+
+        assert_eq!(to_kicad_string(&board.to_expr()), to_kicad_string(&expr));
+    }
+
+    /// A KiCad 6 schematic, with bare uuids and `true`, as written by `to_kicad_string`.
+    const KICAD6: &str = "(kicad_sch
+\t(version 20211123)
+\t(generator eeschema)
+\t(uuid 1b4e2c8a-0000-4000-8000-000000000001)
+\t(paper \"A4\")
+\t(lib_symbols)
+\t(wire
+\t\t(pts
+\t\t\t(xy 100.330 50.800) (xy 120.650 50.800)
+\t\t)
+\t\t(stroke
+\t\t\t(width 0)
+\t\t\t(type default)
+\t\t\t(color 0 0 0 0)
+\t\t)
+\t\t(uuid 1b4e2c8a-0000-4000-8000-000000000002)
+\t)
+\t(symbol
+\t\t(lib_id \"Device:R\")
+\t\t(at 1.270 2.540 0)
+\t\t(unit 1)
+\t\t(in_bom true)
+\t\t(on_board yes)
+\t\t(uuid 1b4e2c8a-0000-4000-8000-000000000003)
+\t\t(property \"Reference\" \"R1\"
+\t\t\t(at 1.270 0.000 0)
+\t\t)
+\t)
+)
+";
+
+    #[test]
+    fn test_spelling() {
+        let expr = parse_with(KICAD6, parse_s_expr).unwrap();
+        assert_eq!(to_kicad_string(&expr), KICAD6);
+        let mut schematic = Schematic::from_expr(&expr).unwrap();
+        assert_eq!(to_kicad_string(&schematic.to_expr()), KICAD6);
+
+        let symbol = &mut schematic.symbols[0];
+        symbol.at.x = 2.54;
+        symbol.in_bom = Some(false);
+        let text = to_kicad_string(&schematic.to_expr());
+        assert!(text.contains("(at 2.54 2.540 0)"), "{text}");
+        assert!(text.contains("(in_bom no)"), "{text}");
+        assert!(text.contains("(uuid 1b4e2c8a-0000-4000-8000-000000000003)"));
+    }
+
+    #[test]
+    fn test_edit() {
+        let expr = parse_with(BOARD, parse_s_expr).unwrap();
+        let mut board = Board::from_expr(&expr).unwrap();
+        let footprint = &mut board.footprints[0];
+        footprint.at = Position {
+            x: 50.0,
+            y: 60.0,
+            angle: None,
+        };
+        footprint.set_property("MPN", "RC0603-10K");
+
+        let text = to_kicad_string(&footprint.to_expr());
+        let order: Vec<_> = [
+            "(at 50 60)",
+            "\"MPN\"",
+            "(attr smd)",
+            "(fp_line",
+            "(pad \"1\"",
+        ]
+        .iter()
+        .map(|s| text.find(s).unwrap())
+        .collect();
+        assert!(order.windows(2).all(|w| w[0] < w[1]), "{text}");
+    }
+}
@@ -0,0 +1,587 @@
+//! Connectivity of KiCad schematics.
+//!
+//! Wires, junctions, labels, global labels, power symbols and symbol pins
+//! are joined into nets.  Pin positions come from the `lib_symbols` cache
+//! in the schematic, placed by the rotation and mirroring of each symbol.
+//!
+//! Items connect when they share a point.  A point that lies on a wire
+//! also connects to it.  Labels with the same name connect within a sheet,
+//! global labels and power symbols connect across sheets.
+use super::{analysis::property_value, Expr};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// A location in schematic internal units of 100nm.
+pub type Point = (i64, i64);
+
+/// Convert millimetres to schematic internal units.
+fn to_iu(mm: f64) -> i64 {
+    (mm * 10_000.0).round() as i64
+}
+
+/// A symbol pin connected to a net.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct NetNode {
+    pub reference: String,
+    pub pin: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pin_function: Option<String>,
+    pub pin_type: String,
+}
+
+/// A named net and the symbol pins it connects.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Net {
+    pub name: String,
+    pub nodes: Vec<NetNode>,
+}
+
+/// A symbol that appears in the netlist.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Component {
+    pub reference: String,
+    pub value: String,
+    pub footprint: String,
+    pub lib_id: String,
+}
+
+/// The per-instance reference designator and unit of a placed symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub reference: String,
+    pub unit: i64,
+}
+
+impl Annotation {
+    /// The reference and unit recorded directly in the symbol.
+    pub fn of(symbol: &Expr) -> Option<Annotation> {
+        Some(Annotation {
+            reference: property_value(symbol, "Reference")?.to_owned(),
+            unit: symbol
+                .child("unit")
+                .and_then(Expr::num_arg)
+                .map_or(1, |n| n as i64),
+        })
+    }
+}
+
+/// The sources of a net name, most preferred first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Priority {
+    GlobalLabel,
+    PowerSymbol,
+    LocalLabel,
+    HierarchicalLabel,
+}
+
+/// Things that can be joined into a net.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// A location in a sheet, identified by its path.
+    Point(String, Point),
+    /// A label name in a sheet, identified by its path.
+    Local(String, String),
+    /// A global label or power net name.
+    Global(String),
+}
+
+/// A graph of connections built up one sheet at a time.
+#[derive(Debug, Default)]
+pub struct Connectivity {
+    parent: Vec<usize>,
+    keys: HashMap<Key, usize>,
+    names: Vec<(usize, Priority, String)>,
+    pins: Vec<(usize, NetNode)>,
+    components: BTreeMap<String, Component>,
+}
+
+impl Connectivity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn node(&mut self, key: Key) -> usize {
+        let next = self.parent.len();
+        let node = *self.keys.entry(key).or_insert(next);
+        if node == next {
+            self.parent.push(next);
+        }
+        node
+    }
+
+    fn find(&self, mut node: usize) -> usize {
+        while self.parent[node] != node {
+            node = self.parent[node];
+        }
+        node
+    }
+
+    /// Connect two things.
+    pub fn join(&mut self, a: Key, b: Key) {
+        let a = self.node(a);
+        let b = self.node(b);
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    /// Add the contents of one schematic sheet.
+    /// The `path` identifies the sheet instance and ends with `/`; the root sheet is `/`.
+    /// The `annotate` function gives the reference and unit of each placed symbol.
+    pub fn add_sheet(
+        &mut self,
+        path: &str,
+        schematic: &Expr,
+        annotate: impl Fn(&Expr) -> Option<Annotation>,
+    ) {
+        let point = |p: Point| Key::Point(path.to_owned(), p);
+        let mut points: Vec<Point> = Vec::new();
+        let mut segments: Vec<(Point, Point)> = Vec::new();
+
+        for wire in schematic.children("wire") {
+            let ends: Vec<Point> = wire
+                .child("pts")
+                .into_iter()
+                .flat_map(|pts| pts.children("xy"))
+                .filter_map(xy)
+                .collect();
+            if let [a, b] = ends[..] {
+                self.join(point(a), point(b));
+                points.extend([a, b]);
+                segments.push((a, b));
+            }
+        }
+
+        for junction in schematic.children("junction") {
+            if let Some((p, _)) = at(junction) {
+                self.node(point(p));
+                points.push(p);
+            }
+        }
+
+        for pin in schematic
+            .children("sheet")
+            .flat_map(|sheet| sheet.children("pin"))
+        {
+            if let Some((p, _)) = at(pin) {
+                points.push(p);
+            }
+        }
+
+        let labels = [
+            ("label", Priority::LocalLabel),
+            ("hierarchical_label", Priority::HierarchicalLabel),
+            ("global_label", Priority::GlobalLabel),
+        ];
+        for (kind, priority) in labels {
+            for label in schematic.children(kind) {
+                let (Some(name), Some((p, _))) = (label.str_arg(), at(label)) else {
+                    continue;
+                };
+                let (key, net_name) = if priority == Priority::GlobalLabel {
+                    (Key::Global(name.to_owned()), name.to_owned())
+                } else {
+                    (
+                        Key::Local(path.to_owned(), name.to_owned()),
+                        format!("{path}{name}"),
+                    )
+                };
+                self.join(point(p), key.clone());
+                let node = self.node(key);
+                self.names.push((node, priority, net_name));
+                points.push(p);
+            }
+        }
+
+        let lib_symbols: HashMap<&str, &Expr> = schematic
+            .child("lib_symbols")
+            .into_iter()
+            .flat_map(|libs| libs.children("symbol"))
+            .filter_map(|s| Some((s.str_arg()?, s)))
+            .collect();
+
+        for symbol in schematic.children("symbol") {
+            let Some(lib_id) = symbol.child("lib_id").and_then(Expr::str_arg) else {
+                continue;
+            };
+            let lib_name = symbol.child("lib_name").and_then(Expr::str_arg);
+            let Some(lib_symbol) = lib_symbols.get(lib_name.unwrap_or(lib_id)) else {
+                continue;
+            };
+            let Some(annotation) = annotate(symbol) else {
+                continue;
+            };
+            let value = property_value(symbol, "Value").unwrap_or_default();
+            let is_power = lib_symbol.child("power").is_some();
+
+            for pin in placed_pins(symbol, lib_symbol, annotation.unit) {
+                points.push(pin.at);
+                if is_power {
+                    if pin.pin_type == "power_in" {
+                        let key = Key::Global(value.to_owned());
+                        self.join(point(pin.at), key.clone());
+                        let node = self.node(key);
+                        self.names
+                            .push((node, Priority::PowerSymbol, value.to_owned()));
+                    }
+                } else {
+                    let node = self.node(point(pin.at));
+                    self.pins.push((
+                        node,
+                        NetNode {
+                            reference: annotation.reference.clone(),
+                            pin: pin.number,
+                            pin_function: pin.name,
+                            pin_type: pin.pin_type,
+                        },
+                    ));
+                }
+            }
+
+            if !is_power {
+                self.components
+                    .entry(annotation.reference.clone())
+                    .or_insert_with(|| Component {
+                        reference: annotation.reference.clone(),
+                        value: value.to_owned(),
+                        footprint: property_value(symbol, "Footprint")
+                            .unwrap_or_default()
+                            .to_owned(),
+                        lib_id: lib_id.to_owned(),
+                    });
+            }
+        }
+
+        for (a, b) in segments {
+            for p in points.iter().filter(|p| on_segment(**p, a, b)) {
+                self.join(point(a), point(*p));
+            }
+        }
+    }
+
+    /// Connect the pins of a sheet symbol in the `parent` sheet
+    /// to the hierarchical labels of the same name in the `child` sheet.
+    pub fn add_sheet_pins(&mut self, parent: &str, child: &str, sheet: &Expr) {
+        for pin in sheet.children("pin") {
+            if let (Some(name), Some((p, _))) = (pin.str_arg(), at(pin)) {
+                self.join(
+                    Key::Point(parent.to_owned(), p),
+                    Key::Local(child.to_owned(), name.to_owned()),
+                );
+            }
+        }
+    }
+
+    /// The nets which connect at least one symbol pin, sorted by name.
+    pub fn nets(&self) -> Vec<Net> {
+        let mut members: BTreeMap<usize, Vec<NetNode>> = BTreeMap::new();
+        for (node, pin) in &self.pins {
+            members
+                .entry(self.find(*node))
+                .or_default()
+                .push(pin.clone());
+        }
+
+        let mut names: HashMap<usize, (Priority, &str)> = HashMap::new();
+        for (node, priority, name) in &self.names {
+            let entry = names
+                .entry(self.find(*node))
+                .or_insert((*priority, name.as_str()));
+            if (*priority, name.as_str()) < *entry {
+                *entry = (*priority, name.as_str());
+            }
+        }
+
+        let mut nets: Vec<Net> = members
+            .into_iter()
+            .map(|(root, mut nodes)| {
+                nodes.sort();
+                nodes.dedup();
+                let name = match names.get(&root) {
+                    Some((_, name)) => name.to_string(),
+                    None => default_net_name(&nodes),
+                };
+                Net { name, nodes }
+            })
+            .collect();
+        nets.sort_by(|a, b| a.name.cmp(&b.name));
+        nets
+    }
+
+    /// The symbols with pins in the netlist, by reference.
+    pub fn components(&self) -> impl Iterator<Item = &Component> {
+        self.components.values()
+    }
+}
+
+/// Name a net with no labels after one of its pins, as KiCad does.
+fn default_net_name(nodes: &[NetNode]) -> String {
+    let Some(first) = nodes.first() else {
+        return String::new();
+    };
+    let pin = match &first.pin_function {
+        Some(name) if nodes.len() == 1 => format!("{name}-Pad{}", first.pin),
+        Some(name) => name.clone(),
+        None => format!("Pad{}", first.pin),
+    };
+    let prefix = if nodes.len() == 1 {
+        "unconnected"
+    } else {
+        "Net"
+    };
+    format!("{prefix}-({}-{pin})", first.reference)
+}
+
+/// Find the nets in a single schematic sheet.
+pub fn netlist(schematic: &Expr) -> Option<Vec<Net>> {
+    schematic.head().filter(|h| *h == "kicad_sch")?;
+    let mut graph = Connectivity::new();
+    graph.add_sheet("/", schematic, Annotation::of);
+    Some(graph.nets())
+}
+
+/// A pin of a placed symbol in schematic coordinates.
+#[derive(Debug, Clone)]
+struct PlacedPin {
+    at: Point,
+    number: String,
+    name: Option<String>,
+    pin_type: String,
+}
+
+/// The pins of one unit of a placed symbol.
+fn placed_pins(symbol: &Expr, lib_symbol: &Expr, unit: i64) -> Vec<PlacedPin> {
+    let Some(((x, y), angle)) = at(symbol) else {
+        return Vec::new();
+    };
+    let mirror = symbol.child("mirror").and_then(Expr::symbol_arg);
+    let body_style = symbol
+        .child("body_style")
+        .or(symbol.child("convert"))
+        .and_then(Expr::num_arg)
+        .map_or(1, |n| n as i64);
+
+    let (cos, sin) = match (angle.round() as i64).rem_euclid(360) {
+        90 => (0, 1),
+        180 => (-1, 0),
+        270 => (0, -1),
+        _ => (1, 0),
+    };
+
+    let place = |(px, py): Point| {
+        // library coordinates have y upwards, the schematic has y downwards
+        let (qx, qy) = (px, -py);
+        // rotate counterclockwise on screen then mirror
+        let (mut rx, mut ry) = (qx * cos + qy * sin, -qx * sin + qy * cos);
+        match mirror {
+            Some("x") => ry = -ry,
+            Some("y") => rx = -rx,
+            _ => (),
+        }
+        (x + rx, y + ry)
+    };
+
+    let selected = |sub: &&Expr| {
+        let Some(name) = sub.str_arg() else {
+            return false;
+        };
+        let mut parts = name.rsplitn(3, '_');
+        let style = parts.next().and_then(|s| s.parse::<i64>().ok());
+        let sub_unit = parts.next().and_then(|s| s.parse::<i64>().ok());
+        matches!(sub_unit, Some(u) if u == 0 || u == unit)
+            && matches!(style, Some(s) if s == 0 || s == body_style)
+    };
+
+    lib_symbol
+        .children("symbol")
+        .filter(selected)
+        .flat_map(|sub| sub.children("pin"))
+        .filter_map(|pin| {
+            let (p, _) = at(pin)?;
+            let name = pin
+                .child("name")
+                .and_then(Expr::str_arg)
+                .filter(|n| !n.is_empty() && *n != "~")
+                .map(str::to_owned);
+            Some(PlacedPin {
+                at: place(p),
+                number: pin.child("number").and_then(Expr::str_arg)?.to_owned(),
+                name,
+                pin_type: pin.args().next()?.as_atom()?.as_symbol()?.to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Is `p` on the line segment from `a` to `b`?
+fn on_segment(p: Point, a: Point, b: Point) -> bool {
+    let cross =
+        (b.0 - a.0) as i128 * (p.1 - a.1) as i128 - (b.1 - a.1) as i128 * (p.0 - a.0) as i128;
+    cross == 0
+        && a.0.min(b.0) <= p.0
+        && p.0 <= a.0.max(b.0)
+        && a.1.min(b.1) <= p.1
+        && p.1 <= a.1.max(b.1)
+}
+
+fn nums(expr: &Expr) -> Vec<f64> {
+    expr.args().filter_map(|x| x.as_atom()?.as_num()).collect()
+}
+
+fn xy(expr: &Expr) -> Option<Point> {
+    match nums(expr)[..] {
+        [x, y, ..] => Some((to_iu(x), to_iu(y))),
+        _ => None,
+    }
+}
+
+/// The position and angle in an `(at x y angle)` child.
+fn at(expr: &Expr) -> Option<(Point, f64)> {
+    let at = expr.child("at")?;
+    Some((xy(at)?, nums(at).get(2).copied().unwrap_or(0.0)))
+}
+
+/// A KiCad netlist (`.net`) S-expression for the nets and components of a graph.
+pub fn netlist_expr(graph: &Connectivity) -> Expr {
+    let item = |key: &str, value: &str| Expr::list([Expr::key(key), value.into()]);
+
+    let components = graph.components().map(|c| {
+        let (lib, part) = c.lib_id.split_once(':').unwrap_or(("", &c.lib_id));
+        Expr::list([
+            Expr::key("comp"),
+            item("ref", &c.reference),
+            item("value", &c.value),
+            item("footprint", &c.footprint),
+            Expr::list([Expr::key("libsource"), item("lib", lib), item("part", part)]),
+        ])
+    });
+
+    let nets = graph.nets().into_iter().enumerate().map(|(i, net)| {
+        let nodes = net.nodes.into_iter().map(|n| {
+            let mut node = vec![
+                Expr::key("node"),
+                item("ref", &n.reference),
+                item("pin", &n.pin),
+            ];
+            if let Some(f) = &n.pin_function {
+                node.push(item("pinfunction", f));
+            }
+            node.push(item("pintype", &n.pin_type));
+            Expr::list(node)
+        });
+        let code = (i + 1).to_string();
+        Expr::list(
+            [
+                Expr::key("net"),
+                item("code", &code),
+                item("name", &net.name),
+            ]
+            .into_iter()
+            .chain(nodes),
+        )
+    });
+
+    Expr::list([
+        Expr::key("export"),
+        item("version", "E"),
+        Expr::list([Expr::key("components")].into_iter().chain(components)),
+        Expr::list([Expr::key("nets")].into_iter().chain(nets)),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::{netlist, placed_pins, Net, NetNode};
+    use crate::{parse_file::parse_with, sexpr::parser::parse_s_expr, sexpr::Expr};
+
+    fn schematic() -> Expr {
+        let s = r##"
+            (kicad_sch
+                (version 20250114)
+                (generator "eeschema")
+                (lib_symbols
+                    (symbol "Device:R"
+                        (property "Reference" "R")
+                        (symbol "R_0_1"
+                            (rectangle (start -1.016 -2.54) (end 1.016 2.54))
+                        )
+                        (symbol "R_1_1"
+                            (pin passive line (at 0 3.81 270) (length 1.27)
+                                (name "~") (number "1"))
+                            (pin passive line (at 0 -3.81 90) (length 1.27)
+                                (name "~") (number "2"))
+                        )
+                    )
+                    (symbol "power:GND"
+                        (power)
+                        (property "Reference" "#PWR")
+                        (symbol "GND_0_1"
+                            (pin power_in line (at 0 0 270) (length 0)
+                                (name "GND") (number "1"))
+                        )
+                    )
+                )
+                (wire (pts (xy 100 103.81) (xy 106.19 103.81)))
+                (wire (pts (xy 106.19 103.81) (xy 106.19 100)))
+                (label "SIG" (at 103 103.81 0))
+                (symbol (lib_id "Device:R") (at 100 100 0) (unit 1)
+                    (property "Reference" "R1") (property "Value" "10k"))
+                (symbol (lib_id "Device:R") (at 110 100 90) (unit 1)
+                    (property "Reference" "R2") (property "Value" "4k7"))
+                (symbol (lib_id "power:GND") (at 113.81 100 0) (unit 1)
+                    (property "Reference" "#PWR01") (property "Value" "GND"))
+            )
+        "##;
+        parse_with(s, parse_s_expr).unwrap()
+    }
+
+    fn node(reference: &str, pin: &str) -> NetNode {
+        NetNode {
+            reference: reference.to_owned(),
+            pin: pin.to_owned(),
+            pin_function: None,
+            pin_type: "passive".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_netlist() {
+        let nets = netlist(&schematic()).unwrap();
+        assert_eq!(
+            nets,
+            vec![
+                Net {
+                    name: "/SIG".to_owned(),
+                    nodes: vec![node("R1", "2"), node("R2", "1")]
+                },
+                Net {
+                    name: "GND".to_owned(),
+                    nodes: vec![node("R2", "2")]
+                },
+                Net {
+                    name: "unconnected-(R1-Pad1)".to_owned(),
+                    nodes: vec![node("R1", "1")]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mirrored_pins() {
+        let sch = schematic();
+        let lib = sch.child("lib_symbols").unwrap().child("symbol").unwrap();
+        let symbol = parse_with("(symbol (at 10 20 90) (mirror y))", parse_s_expr).unwrap();
+        let pins: Vec<_> = placed_pins(&symbol, lib, 1)
+            .into_iter()
+            .map(|p| (p.number, p.at))
+            .collect();
+        assert_eq!(
+            pins,
+            vec![
+                ("1".to_owned(), (138_100, 200_000)),
+                ("2".to_owned(), (61_900, 200_000))
+            ]
+        );
+    }
+}
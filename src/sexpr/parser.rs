@@ -1,26 +1,33 @@
 //! In this example we build an [S-expression](https://en.wikipedia.org/wiki/S-expression)
 //! parser and tiny [lisp](https://en.wikipedia.org/wiki/Lisp_(programming_language)) interpreter.
 //! Lisp is a simple type of language made up of Atoms and Lists, forming easily parsable trees.
-use super::{Atom, Expr};
+use super::{borrowed, Atom, Expr};
+use crate::parse_file::{parse_error, Result};
+use crate::span::{Cursor, Span};
 use crate::strings;
 use nom::{
     branch::alt,
-    bytes::complete::{is_not, tag},
-    character::complete::{char, hex_digit1, multispace0, satisfy},
-    combinator::{all_consuming, cut, map, map_opt, map_parser, map_res, recognize, rest},
+    bytes::complete::{is_not, tag, take_while1},
+    character::complete::{char, hex_digit1, multispace0},
+    combinator::{all_consuming, consumed, cut, map, map_opt, map_res, recognize, rest},
     error::{context, VerboseError},
-    multi::{many0, many1, many1_count},
+    multi::many1_count,
     number::complete::double,
     sequence::{delimited, preceded, terminated},
-    AsChar, IResult, Parser,
+    AsChar, IResult, Needed, Parser,
 };
 use uuid::Uuid;
 
+/// The scalar parsers classify a token already separated from its neighbours.
+/// They report failure without detail, which costs nothing,
+/// since a token which is nothing else is a symbol.
+type Classified<'a> = IResult<&'a str, borrowed::Atom<'a>, ()>;
+
 /// Reserved words true and false.
-fn parse_bool(i: &str) -> IResult<&str, Atom, VerboseError<&str>> {
+fn parse_bool(i: &str) -> Classified<'_> {
     alt((
-        map(tag("true"), |_| Atom::Bool(true)),
-        map(tag("false"), |_| Atom::Bool(false)),
+        map(tag("true"), |_| borrowed::Atom::Bool(true)),
+        map(tag("false"), |_| borrowed::Atom::Bool(false)),
     ))
     .parse(i)
 }
@@ -28,50 +35,68 @@ fn parse_bool(i: &str) -> IResult<&str, Atom, VerboseError<&str>> {
 /// A symbol.  Any sequence excluding white space and brackets could be a symbol.
 /// More specific atoms such as quoted strings or numbers should be matched
 /// before symbols.
-fn parse_symbol(i: &str) -> IResult<&str, Atom, VerboseError<&str>> {
-    let mut parser = map(rest, |sym_str: &str| Atom::Symbol(sym_str.to_string()));
+fn parse_symbol(i: &str) -> Classified<'_> {
+    let mut parser = map(rest, borrowed::Atom::Symbol);
     parser.parse(i)
 }
 
 /// A float.
-fn parse_num(i: &str) -> IResult<&str, Atom, VerboseError<&str>> {
-    map(double, Atom::Num).parse(i)
+/// Most symbols are rejected by their first character without trying the float parser.
+fn parse_num(i: &str) -> Classified<'_> {
+    if !i.starts_with(|c: char| c.is_ascii_digit() || "+-.iInN".contains(c)) {
+        return Err(nom::Err::Error(()));
+    }
+    map(consumed(double), |(text, value)| {
+        borrowed::Atom::Num(value, text)
+    })
+    .parse(i)
 }
 
 /// A hex number starting with 0x.
-fn parse_bits(i: &str) -> IResult<&str, Atom, VerboseError<&str>> {
-    let digits = many1(satisfy(|x| x.is_hex_digit() || x == '_'));
-    let hex = map_opt(digits, |s| {
-        u64::from_str_radix(&s.into_iter().filter(|x| *x != '_').collect::<String>(), 16).ok()
+fn parse_bits(i: &str) -> Classified<'_> {
+    let digits = take_while1(|x: char| x.is_hex_digit() || x == '_');
+    let hex = map_opt(digits, |s: &str| {
+        u64::from_str_radix(&s.replace('_', ""), 16).ok()
     });
-    map(preceded(tag("0x"), hex), Atom::Bits).parse(i)
+    map(consumed(preceded(tag("0x"), hex)), |(text, value)| {
+        borrowed::Atom::Bits(value, text)
+    })
+    .parse(i)
 }
 
 /// Standard UUID syntax.
-fn parse_uuid(i: &str) -> IResult<&str, Atom, VerboseError<&str>> {
+fn parse_uuid(i: &str) -> Classified<'_> {
     let segment = char('-').and(hex_digit1);
     map_res(
         recognize(hex_digit1.and(many1_count(segment))),
-        |num_str: &str| Uuid::try_parse(num_str).map(Atom::Uuid),
+        |num_str: &str| Uuid::try_parse(num_str).map(borrowed::Atom::Uuid),
     )
     .parse(i)
 }
 
-/// A scalar is a symbol or one of several types of number
-/// These are matched in most specific to least specific order.
+/// Classify a token as one of several types of number, a boolean or a symbol.
+/// These are tried in most specific to least specific order
+/// and each must match the whole token.
+fn classify(token: &str) -> borrowed::Atom<'_> {
+    let result = all_consuming(
+        parse_uuid
+            .or(parse_bits)
+            .or(parse_num)
+            .or(parse_bool)
+            .or(parse_symbol),
+    )
+    .parse(token);
+    match result {
+        Ok((_, atom)) => atom,
+        Err(_) => borrowed::Atom::Symbol(token),
+    }
+}
+
+/// A scalar is a symbol or one of several types of number.
 /// All the text up to a bracket or whitespace will be matched.
-fn parse_scalar(i: &str) -> IResult<&str, Atom, VerboseError<&str>> {
-    let mut parser = map_parser(
-        is_not(" \t\r\n)("),
-        all_consuming(
-            parse_uuid
-                .or(parse_bits)
-                .or(parse_num)
-                .or(parse_bool)
-                .or(parse_symbol),
-        ),
-    );
-    parser.parse(i)
+/// The result borrows from the input and is shared with the borrowed parser.
+pub(crate) fn parse_scalar(i: &str) -> IResult<&str, borrowed::Atom<'_>, VerboseError<&str>> {
+    map(is_not(" \t\r\n)("), classify).parse(i)
 }
 
 /// Quoted string.
@@ -81,41 +106,109 @@ fn parse_string(i: &str) -> IResult<&str, Atom, VerboseError<&str>> {
 
 /// An atom is a quoted string or a scalar.
 fn parse_atom(i: &str) -> IResult<&str, Atom, VerboseError<&str>> {
-    parse_string.or(parse_scalar).parse(i)
+    let mut scalar = map(parse_scalar, |atom| atom.to_atom());
+    if i.starts_with('"') {
+        parse_string.or(scalar).parse(i)
+    } else {
+        scalar.parse(i)
+    }
+}
+
+/// A provisional span recording the length of the remaining input.
+/// It is converted to a true location by `locate` once the parse is complete.
+fn pending(i: &str) -> Option<Span> {
+    Some(Span {
+        offset: i.len(),
+        line: 0,
+        column: 0,
+    })
+}
+
+/// Replace the provisional spans in a parsed expression with locations in `text`.
+fn locate(expr: &mut Expr, text: &str) {
+    locate_from(expr, text, &mut Cursor::new(text))
+}
+
+/// As `locate`, continuing from a cursor which has not passed the expression.
+fn locate_from(expr: &mut Expr, text: &str, cursor: &mut Cursor) {
+    fn relocate(span: &mut Option<Span>, text: &str, cursor: &mut Cursor) {
+        if let Some(s) = span {
+            *s = cursor.advance(text.len() - s.offset);
+        }
+    }
+
+    fn walk(expr: &mut Expr, text: &str, cursor: &mut Cursor) {
+        match expr {
+            Expr::Constant(_, span) => relocate(span, text, cursor),
+            Expr::List(exprs, span) => {
+                relocate(span, text, cursor);
+                for expr in exprs.iter_mut() {
+                    walk(expr, text, cursor)
+                }
+            }
+        }
+    }
+
+    walk(expr, text, cursor)
 }
 
 /// An atom is a constant expression
 fn parse_constant(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
-    map(parse_atom, Expr::Constant).parse(i)
+    let span = pending(i);
+    map(parse_atom, move |atom| Expr::Constant(atom, span)).parse(i)
 }
 
 /// A list is zero or more expressions in brackets.
 fn parse_list(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
+    let span = pending(i);
     map(
         delimited(
             char('('),
             parse_bare_list,
             context("closing paren", cut(char(')'))),
         ),
-        Expr::list,
+        move |exprs| Expr::list(exprs).with_span(span),
     )
     .parse(i)
 }
 
 /// An expression is either a list or a constant
 fn parse_expr(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
-    parse_list.or(parse_constant).parse(i)
+    if i.is_empty() {
+        // reported as an unexpected end of input
+        Err(nom::Err::Incomplete(Needed::Unknown))
+    } else if i.starts_with('(') {
+        parse_list(i)
+    } else {
+        parse_constant(i)
+    }
 }
 
 /// An unbracketed list of zero or more expressions.
+/// A closing bracket ends the list without the cost of a failed parse.
 fn parse_bare_list(i: &str) -> IResult<&str, Vec<Expr>, VerboseError<&str>> {
-    preceded(multispace0, many0(terminated(parse_expr, multispace0))).parse(i)
+    let mut elems = Vec::new();
+    let (mut i, _) = multispace0(i)?;
+    while !i.starts_with(')') {
+        let (rest, elem) = parse_expr(i)?;
+        elems.push(elem);
+        (i, _) = multispace0(rest)?;
+    }
+    Ok((i, elems))
 }
 
 /// The parser accepts a single expression,
 /// usually a a bracketed list.
+/// Each node of the result records its location in the input.
 pub fn parse_s_expr(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
-    all_consuming(delimited(multispace0, parse_expr, multispace0)).parse(i)
+    map(
+        all_consuming(delimited(multispace0, parse_expr, multispace0)),
+        |mut expr| {
+            locate(&mut expr, i);
+            expr
+        },
+    )
+    .parse(i)
 }
 
 /// Not Working!
@@ -125,6 +218,9 @@ pub fn parse_s_expr(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
 /// Empty input (or whitespace) returns and empty Expr::List.
 pub fn parse_s_exprs(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
     map(all_consuming(parse_bare_list), |mut xs| {
+        for x in xs.iter_mut() {
+            locate(x, i)
+        }
         if xs.len() == 1 {
             xs.remove(0)
         } else {
@@ -133,3 +229,154 @@ pub fn parse_s_exprs(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
     })
     .parse(i)
 }
+
+/// The items of a file's outer list, such as the `footprint`s of a `(kicad_pcb ...)`,
+/// parsed one at a time so that the whole tree is never held at once.
+/// Each item is located in the whole text.  After a parse error the iterator ends.
+pub struct TopLevel<'a> {
+    name: String,
+    text: &'a str,
+    rest: &'a str,
+    head: Expr,
+    cursor: Cursor<'a>,
+    finished: bool,
+}
+
+/// Begin parsing the items of `text`, which came from the source called `name`,
+/// by reading the opening bracket and the head of its outer list.
+pub fn parse_top_level<'a>(name: &str, text: &'a str) -> Result<TopLevel<'a>> {
+    let mut opening = delimited(
+        multispace0.and(char('(')).and(multispace0),
+        parse_constant,
+        multispace0,
+    );
+    let (rest, mut head) = opening
+        .parse(text)
+        .map_err(|err| parse_error(name, text, err))?;
+    let mut cursor = Cursor::new(text);
+    locate_from(&mut head, text, &mut cursor);
+    Ok(TopLevel {
+        name: name.to_owned(),
+        text,
+        rest,
+        head,
+        cursor,
+        finished: false,
+    })
+}
+
+impl TopLevel<'_> {
+    /// The head of the outer list, usually a symbol such as `kicad_pcb`.
+    pub fn head(&self) -> &Expr {
+        &self.head
+    }
+
+    fn step(&mut self) -> Result<Option<Expr>> {
+        let located = |err| parse_error(&self.name, self.text, err);
+        if let Some(after) = self.rest.strip_prefix(')') {
+            all_consuming(multispace0).parse(after).map_err(located)?;
+            return Ok(None);
+        }
+        let (rest, mut item) = terminated(parse_expr, multispace0)
+            .parse(self.rest)
+            .map_err(located)?;
+        locate_from(&mut item, self.text, &mut self.cursor);
+        self.rest = rest;
+        Ok(Some(item))
+    }
+}
+
+impl Iterator for TopLevel<'_> {
+    type Item = Result<Expr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let result = self.step();
+        self.finished = !matches!(result, Ok(Some(_)));
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_s_expr, parse_top_level};
+    use crate::parse_file::parse_with;
+    use crate::sexpr::{format::to_kicad_string, Atom, Expr};
+
+    #[test]
+    fn test_spans() {
+        let text = "(kicad_sch\n  (version 20250114)\n  (paper \"A4\"))";
+        let expr: Expr = parse_with(text, parse_s_expr).unwrap();
+        let root = expr.span().unwrap();
+        assert_eq!((root.offset, root.line, root.column), (0, 1, 1));
+
+        let paper = &expr.as_list().unwrap()[2];
+        let span = paper.span().unwrap();
+        assert_eq!((span.line, span.column), (3, 3));
+        assert_eq!(&text[span.offset..span.offset + 6], "(paper");
+
+        let a4 = paper.as_list().unwrap()[1].span().unwrap();
+        assert_eq!((a4.line, a4.column), (3, 10));
+    }
+
+    #[test]
+    fn test_error_location() {
+        let text = "(kicad_sch\n  (version 20250114\n";
+        let err = parse_with(text, parse_s_expr).unwrap_err().to_string();
+        assert!(err.starts_with("<input>:3:1: parse error: unexpected end of input"));
+
+        let text = "(kicad_sch\n  (version 20250114))\n)";
+        let err = parse_with(text, parse_s_expr).unwrap_err().to_string();
+        assert!(err.starts_with("<input>:3:1: parse error: unexpected input"));
+        assert!(err.ends_with("3 | )\n  | ^"));
+    }
+
+    #[test]
+    fn test_numeric_text() {
+        let text = "(a 1.270 0.0 1e-3 -0 0x00010fc_ffffffff)";
+        let expr: Expr = parse_with(text, parse_s_expr).unwrap();
+        assert_eq!(to_kicad_string(&expr), format!("{text}\n"));
+
+        let elems = expr.as_list().unwrap();
+        assert_eq!(elems[1], Expr::from(1.27));
+        assert_eq!(
+            elems[5].as_atom().unwrap().as_bits(),
+            Some(0x10fc_ffff_ffff)
+        );
+
+        let mut edited = elems[1].as_atom().unwrap().clone();
+        if let Atom::Num(n) = &mut edited {
+            n.set_value(2.5)
+        }
+        assert_eq!(edited.to_string(), "2.5");
+    }
+    #[test]
+    fn test_top_level() {
+        let text =
+            "(kicad_pcb (version 20240108)\n  (footprint \"R\" (at 1 2))\n  (footprint \"C\")\n)\n";
+        let items = parse_top_level("<input>", text).unwrap();
+        assert_eq!(items.head(), &Expr::key("kicad_pcb"));
+
+        let whole: Expr = parse_with(text, parse_s_expr).unwrap();
+        let items: Vec<Expr> = items.map(Result::unwrap).collect();
+        assert_eq!(items.len(), 3);
+        for (item, expected) in items.iter().zip(whole.args()) {
+            assert_eq!(item, expected);
+            assert_eq!(item.span(), expected.span());
+        }
+
+        let text = "(kicad_pcb (version 1) (footprint \"R\"";
+        let results: Vec<_> = parse_top_level("<input>", text).unwrap().collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        let err = results[1].as_ref().unwrap_err().to_string();
+        assert!(err.starts_with("<input>:1:38: parse error: unexpected end of input"));
+
+        let text = "(kicad_pcb (version 1)) x";
+        let err = parse_top_level("<input>", text).unwrap().nth(1).unwrap();
+        assert!(err.unwrap_err().to_string().contains("unexpected input"));
+        assert!(parse_top_level("<input>", "kicad_pcb").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! A textual pattern language for matching S-expressions at run time.
+//!
+//! A pattern is written as an S-expression in which some symbols are special:
+//!
+//! |Pattern|Matches|
+//! |---|---|
+//! |`_`| any one expression|
+//! |`_str`, `_num`, `_sym`| any one string, number or symbol|
+//! |`$name`| any one expression, captured as `name`|
+//! |`_*` or `..*`| any number of expressions within a list, including none|
+//! |`(p1 p2 ..)`| a list whose elements match `p1`, `p2` and so on|
+//! |anything else| an equal atom, so `property` matches the symbol and `"MPN"` the string|
+//!
+//! For example `(symbol ..* (property "MPN" $mpn _*) ..*)` matches a symbol
+//! with an MPN property and captures its value.
+//!
+//! As a `Simplifier` a pattern produces a list of `(name value)` captures
+//! in the order they appear in the pattern, or the matched expression
+//! if the pattern has no captures.  The captures are also added to the
+//! `Bindings` when matching with `Simplifier::bind`.
+use super::{
+    parser::parse_s_expr,
+    simplifier::{Bindings, Simplifier},
+    Atom, Expr,
+};
+use crate::parse_file::{parse_named, Result};
+
+/// A compiled pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Any,
+    AnyStr,
+    AnyNum,
+    AnySymbol,
+    Rest,
+    Capture(String),
+    Atom(Atom),
+    List(Vec<Pattern>),
+}
+
+/// Compile the text of a pattern.
+pub fn compile(source: &str) -> Result<Pattern> {
+    let expr = parse_named("<pattern>", source.trim(), parse_s_expr)?;
+    let pattern = Pattern::from_expr(&expr)?;
+    if pattern == Pattern::Rest {
+        Err(expr.error("a repeat can only appear within a list"))?
+    }
+    Ok(pattern)
+}
+
+impl Pattern {
+    fn from_expr(expr: &Expr) -> Result<Pattern> {
+        let pattern = match expr {
+            Expr::List(elems, _) => Pattern::List(
+                elems
+                    .iter()
+                    .map(Pattern::from_expr)
+                    .collect::<Result<_>>()?,
+            ),
+            Expr::Constant(Atom::Symbol(s), _) => match s.as_str() {
+                "_" => Pattern::Any,
+                "_str" => Pattern::AnyStr,
+                "_num" => Pattern::AnyNum,
+                "_sym" => Pattern::AnySymbol,
+                "_*" | "..*" => Pattern::Rest,
+                "$" => Err(expr.error("a capture needs a name"))?,
+                _ => match s.strip_prefix('$') {
+                    Some(name) => Pattern::Capture(name.to_owned()),
+                    None => Pattern::Atom(Atom::Symbol(s.clone())),
+                },
+            },
+            Expr::Constant(atom, _) => Pattern::Atom(atom.clone()),
+        };
+        Ok(pattern)
+    }
+
+    /// Match the pattern against an expression, adding any captures to `bindings`.
+    pub fn is_match(&self, subject: &Expr, bindings: &mut Bindings) -> bool {
+        match self {
+            Pattern::Any => true,
+            Pattern::AnyStr => subject.as_atom().and_then(Atom::as_string).is_some(),
+            Pattern::AnyNum => subject.as_atom().and_then(Atom::as_num).is_some(),
+            Pattern::AnySymbol => subject.as_atom().and_then(Atom::as_symbol).is_some(),
+            Pattern::Rest => false,
+            Pattern::Capture(name) => {
+                bindings.insert(name, subject.clone());
+                true
+            }
+            Pattern::Atom(atom) => subject.as_atom() == Some(atom),
+            Pattern::List(patterns) => match subject.as_list() {
+                Some(elems) => {
+                    let elems: Vec<&Expr> = elems.iter().collect();
+                    match_sequence(patterns, &elems, bindings)
+                }
+                None => false,
+            },
+        }
+    }
+
+    /// Does the pattern capture anything?
+    pub fn has_captures(&self) -> bool {
+        match self {
+            Pattern::Capture(_) => true,
+            Pattern::List(patterns) => patterns.iter().any(Pattern::has_captures),
+            _ => false,
+        }
+    }
+}
+
+/// Match a sequence of patterns against list elements, backtracking over repeats.
+/// Captures from failed attempts are removed.
+fn match_sequence(patterns: &[Pattern], elems: &[&Expr], bindings: &mut Bindings) -> bool {
+    let attempt = |b: &mut Bindings| {
+        let matched = match patterns.split_first() {
+            None => elems.is_empty(),
+            Some((Pattern::Rest, rest)) => {
+                (0..=elems.len()).any(|skip| match_sequence(rest, &elems[skip..], b))
+            }
+            Some((first, rest)) => match elems.split_first() {
+                Some((elem, others)) => first.is_match(elem, b) && match_sequence(rest, others, b),
+                None => false,
+            },
+        };
+        matched.then(Expr::empty)
+    };
+    bindings.attempt(attempt).is_some()
+}
+
+impl Simplifier for Pattern {
+    fn simplify(&self, subject: &Expr) -> Option<Expr> {
+        self.bind(subject, &mut Bindings::default())
+    }
+
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        let mut captures = Bindings::default();
+        if !self.is_match(subject, &mut captures) {
+            bindings.reject(subject, || self.expected());
+            return None;
+        }
+        let result = if self.has_captures() {
+            Expr::list(
+                captures
+                    .iter()
+                    .map(|(name, value)| Expr::list([Expr::key(name), value.clone()])),
+            )
+        } else {
+            subject.clone()
+        };
+        for (name, value) in captures.iter() {
+            bindings.insert(name, value.clone());
+        }
+        Some(result)
+    }
+
+    fn expected(&self) -> String {
+        "a match for the pattern".to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::compile;
+    use crate::{
+        parse_file::parse_with,
+        sexpr::{parser::parse_s_expr, simplifier::Simplifier, Expr},
+    };
+
+    fn symbol() -> Expr {
+        let s = r#"
+            (symbol (lib_id "Device:R") (at 100 100 0) (unit 1)
+                (property "Reference" "R1" (at 0 0 0))
+                (property "Value" "10k" (at 0 0 0))
+                (property "MPN" "RC0603-10K" (at 0 0 0) (effects (hide yes))))
+        "#;
+        parse_with(s, parse_s_expr).unwrap()
+    }
+
+    #[test]
+    fn test_captures() {
+        let pattern = compile(
+            r#"(symbol ..* (property "Reference" $ref _*) ..* (property "MPN" $mpn _*) ..*)"#,
+        )
+        .unwrap();
+        let result = pattern.simplify(&symbol()).unwrap();
+        let expected = parse_with(r#"((ref "R1") (mpn "RC0603-10K"))"#, parse_s_expr).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_match() {
+        let matches = |p: &str| compile(p).unwrap().simplify(&symbol()).is_some();
+        assert!(matches("(symbol _*)"));
+        assert!(matches("(symbol (lib_id _str) (at _num _num 0) _*)"));
+        assert!(!matches("(symbol (lib_id _num) _*)"));
+        assert!(!matches(r#"(symbol ..* (property "Footprint" _*) ..*)"#));
+        assert!(!matches("(symbol)"));
+        assert!(!matches("(footprint _*)"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(compile("_*").is_err());
+        assert!(compile("(a $ b)").is_err());
+        assert!(compile("(a (b)").is_err());
+    }
+}
@@ -0,0 +1,329 @@
+//! Check that symbol pin numbers match the pad numbers of their footprints.
+//!
+//! Each symbol's `Footprint` property names a library and footprint, for example
+//! `Package_TO_SOT_SMD:SOT-23`.  The library is found through the footprint
+//! library tables and the footprint read from `<name>.kicad_mod` within it.
+//! Pins are taken from every unit of the symbol in its normal body style.
+//! Unnumbered pads, such as mounting holes, are ignored.
+use super::{
+    analysis::property_value,
+    libtable::{project_vars, LibTable},
+    netlist::Annotation,
+    parser::parse_s_expr,
+    project::Project,
+    Expr,
+};
+use crate::parse_file::parse_file;
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap, HashSet},
+    path::PathBuf,
+};
+
+/// The result of checking one symbol against its footprint.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PinReport {
+    /// The reference of a placed symbol or the name of a library symbol.
+    pub reference: String,
+    pub footprint: String,
+    pub pin_count: usize,
+    pub pad_count: usize,
+    /// Pin numbers with no pad of the same number.
+    pub missing_pads: Vec<String>,
+    /// Pad numbers with no pin of the same number.
+    pub unused_pads: Vec<String>,
+    /// Why the footprint could not be checked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl PinReport {
+    /// Does the symbol disagree with its footprint?
+    pub fn is_problem(&self) -> bool {
+        self.error.is_some() || !self.missing_pads.is_empty() || !self.unused_pads.is_empty()
+    }
+}
+
+/// Finds footprints through footprint library tables, reading each file once.
+pub struct FootprintResolver {
+    tables: Vec<LibTable>,
+    project_dir: PathBuf,
+    cache: RefCell<HashMap<String, Result<BTreeSet<String>, String>>>,
+}
+
+impl FootprintResolver {
+    /// Search the given tables in order.
+    /// `${KIPRJMOD}` in library uris refers to `project_dir`.
+    pub fn new(tables: Vec<LibTable>, project_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            tables,
+            project_dir: project_dir.into(),
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The file for a footprint given as `library:name`.
+    pub fn path(&self, footprint: &str) -> Result<PathBuf, String> {
+        let (library, name) = footprint
+            .split_once(':')
+            .ok_or_else(|| format!("footprint {footprint} does not name a library"))?;
+        let lib = self
+            .tables
+            .iter()
+            .flat_map(|t| t.libs.iter())
+            .find(|l| l.name == library && !l.disabled)
+            .ok_or_else(|| format!("library {library} is not in the footprint library tables"))?;
+        let dir = lib.expanded_uri(project_vars(&self.project_dir));
+        Ok(PathBuf::from(dir).join(format!("{name}.kicad_mod")))
+    }
+
+    /// The pad numbers of a footprint given as `library:name`.
+    pub fn pads(&self, footprint: &str) -> Result<BTreeSet<String>, String> {
+        if let Some(pads) = self.cache.borrow().get(footprint) {
+            return pads.clone();
+        }
+        let pads = self.path(footprint).and_then(|path| {
+            let expr = parse_file(&path.to_string_lossy(), parse_s_expr)
+                .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+            Ok(footprint_pads(&expr))
+        });
+        self.cache
+            .borrow_mut()
+            .insert(footprint.to_owned(), pads.clone());
+        pads
+    }
+}
+
+/// The numbers of the pads in a footprint, ignoring unnumbered pads.
+pub fn footprint_pads(footprint: &Expr) -> BTreeSet<String> {
+    footprint
+        .children("pad")
+        .filter_map(|pad| {
+            let atom = pad.args().next()?.as_atom()?;
+            let number = atom
+                .as_string()
+                .map_or_else(|| atom.to_string(), str::to_owned);
+            (!number.is_empty()).then_some(number)
+        })
+        .collect()
+}
+
+/// The pin numbers of a library symbol in all units of its normal body style.
+/// A derived symbol takes its pins from the symbol it `extends` in `library`.
+pub fn symbol_pins(lib_symbol: &Expr, library: &[&Expr]) -> BTreeSet<String> {
+    let base = lib_symbol
+        .child("extends")
+        .and_then(Expr::str_arg)
+        .and_then(|parent| library.iter().find(|s| s.str_arg() == Some(parent)))
+        .copied()
+        .unwrap_or(lib_symbol);
+
+    let normal_style = |sub: &&Expr| {
+        sub.str_arg()
+            .and_then(|name| name.rsplit('_').next())
+            .is_some_and(|style| style == "0" || style == "1")
+    };
+
+    base.children("symbol")
+        .filter(normal_style)
+        .flat_map(|sub| sub.children("pin"))
+        .filter_map(|pin| Some(pin.child("number")?.str_arg()?.to_owned()))
+        .collect()
+}
+
+/// Compare the pins of a symbol with the pads of its footprint.
+pub fn check(
+    reference: &str,
+    footprint: &str,
+    pins: &BTreeSet<String>,
+    resolver: &FootprintResolver,
+) -> PinReport {
+    let mut report = PinReport {
+        reference: reference.to_owned(),
+        footprint: footprint.to_owned(),
+        pin_count: pins.len(),
+        pad_count: 0,
+        missing_pads: Vec::new(),
+        unused_pads: Vec::new(),
+        error: None,
+    };
+    match resolver.pads(footprint) {
+        Ok(pads) => {
+            report.pad_count = pads.len();
+            report.missing_pads = pins.difference(&pads).cloned().collect();
+            report.unused_pads = pads.difference(pins).cloned().collect();
+        }
+        Err(e) => report.error = Some(e),
+    }
+    report
+}
+
+/// Check the symbols placed in a schematic sheet, once per reference.
+/// References are given by `annotate`.
+pub fn check_schematic(
+    schematic: &Expr,
+    annotate: impl Fn(&Expr) -> Option<Annotation>,
+    resolver: &FootprintResolver,
+    seen: &mut HashSet<String>,
+) -> Vec<PinReport> {
+    let library: Vec<&Expr> = schematic
+        .child("lib_symbols")
+        .into_iter()
+        .flat_map(|libs| libs.children("symbol"))
+        .collect();
+
+    let mut reports = Vec::new();
+    for symbol in schematic.children("symbol") {
+        let Some(footprint) = property_value(symbol, "Footprint").filter(|f| !f.is_empty()) else {
+            continue;
+        };
+        let on_board = symbol.child("on_board").and_then(Expr::symbol_arg) != Some("no");
+        let Some(annotation) = annotate(symbol).filter(|_| on_board) else {
+            continue;
+        };
+        if !seen.insert(annotation.reference.clone()) {
+            continue;
+        }
+        let lib_id = symbol.child("lib_id").and_then(Expr::str_arg);
+        let lib_name = symbol.child("lib_name").and_then(Expr::str_arg).or(lib_id);
+        let Some(lib_symbol) = library.iter().find(|s| s.str_arg() == lib_name) else {
+            continue;
+        };
+        let pins = symbol_pins(lib_symbol, &library);
+        reports.push(check(&annotation.reference, footprint, &pins, resolver));
+    }
+    reports
+}
+
+/// Check every placed symbol in a project.
+pub fn check_project(project: &Project, resolver: &FootprintResolver) -> Vec<PinReport> {
+    let mut seen = HashSet::new();
+    project
+        .sheets
+        .iter()
+        .flat_map(|sheet| {
+            check_schematic(
+                project.schematic(sheet),
+                |s| project.annotate(sheet, s),
+                resolver,
+                &mut seen,
+            )
+        })
+        .collect()
+}
+
+/// Check the symbols in a symbol library which name a footprint.
+pub fn check_library(symlib: &Expr, resolver: &FootprintResolver) -> Vec<PinReport> {
+    let library: Vec<&Expr> = symlib.children("symbol").collect();
+    library
+        .iter()
+        .filter_map(|symbol| {
+            let name = symbol.str_arg()?;
+            let footprint = property_value(symbol, "Footprint").filter(|f| !f.is_empty())?;
+            let pins = symbol_pins(symbol, &library);
+            Some(check(name, footprint, &pins, resolver))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_library, check_schematic, FootprintResolver};
+    use crate::{
+        parse_file::parse_with,
+        sexpr::{libtable::LibTable, netlist::Annotation, parser::parse_s_expr},
+    };
+    use std::{collections::HashSet, fs};
+
+    const LIBRARY: &str = r#"
+        (kicad_symbol_lib
+            (symbol "D_Schottky"
+                (property "Footprint" "Test:SOD-123")
+                (symbol "D_Schottky_1_1"
+                    (pin passive line (at -3.81 0 0) (length 2.54) (name "K") (number "1"))
+                    (pin passive line (at 3.81 0 180) (length 2.54) (name "A") (number "2"))
+                )
+            )
+            (symbol "BAT54" (extends "D_Schottky")
+                (property "Footprint" "Test:SOD-123"))
+            (symbol "Q_NPN"
+                (property "Footprint" "Test:SOT-23")
+                (symbol "Q_NPN_1_1"
+                    (pin input line (at -5.08 0 0) (length 2.54) (number "1"))
+                    (pin passive line (at 2.54 5.08 270) (length 2.54) (number "2"))
+                    (pin passive line (at 2.54 -5.08 90) (length 2.54) (number "3"))
+                )
+                (symbol "Q_NPN_1_2"
+                    (pin input line (at -5.08 0 0) (length 2.54) (number "4"))
+                )
+            )
+            (symbol "Missing" (property "Footprint" "Other:X")
+                (symbol "Missing_1_1"
+                    (pin input line (at 0 0 0) (length 2.54) (number "1"))))
+        )
+    "#;
+
+    fn resolver(dir: &std::path::Path) -> FootprintResolver {
+        let table = r#"(fp_lib_table (version 7)
+            (lib (name "Test")(type "KiCad")(uri "${KIPRJMOD}/Test.pretty")(options "")(descr "")))"#;
+        let table = LibTable::from_expr(&parse_with(table, parse_s_expr).unwrap()).unwrap();
+        FootprintResolver::new(vec![table], dir)
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = std::env::temp_dir().join(format!("kiops-pincheck-{}", std::process::id()));
+        let pretty = dir.join("Test.pretty");
+        fs::create_dir_all(&pretty).unwrap();
+        fs::write(
+            pretty.join("SOD-123.kicad_mod"),
+            r#"(footprint "SOD-123" (pad "1" smd rect (at -1.6 0)) (pad "2" smd rect (at 1.6 0)))"#,
+        )
+        .unwrap();
+        fs::write(
+            pretty.join("SOT-23.kicad_mod"),
+            r#"(footprint "SOT-23" (pad "A" smd rect (at 0 0)) (pad "K" smd rect (at 0 0))
+                (pad "3" smd rect (at 0 0)) (pad "" np_thru_hole circle (at 0 0)))"#,
+        )
+        .unwrap();
+        let resolver = resolver(&dir);
+
+        let library = parse_with(LIBRARY, parse_s_expr).unwrap();
+        let reports = check_library(&library, &resolver);
+
+        let schematic = format!(
+            r#"(kicad_sch (lib_symbols {})
+                (symbol (lib_id "Device:Q_NPN") (lib_name "Q_NPN") (unit 1)
+                    (property "Reference" "Q1") (property "Footprint" "Test:SOT-23"))
+                (symbol (lib_id "Device:Q_NPN") (lib_name "Q_NPN") (unit 1)
+                    (property "Reference" "Q1") (property "Footprint" "Test:SOT-23")))"#,
+            &LIBRARY.trim()["(kicad_symbol_lib".len()..LIBRARY.trim().len() - 1]
+        );
+        let schematic = parse_with(&schematic, parse_s_expr).unwrap();
+        let placed = check_schematic(&schematic, Annotation::of, &resolver, &mut HashSet::new());
+        fs::remove_dir_all(&dir).unwrap();
+
+        let problems: Vec<_> = reports
+            .iter()
+            .map(|r| (r.reference.as_str(), r.is_problem()))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                ("D_Schottky", false),
+                ("BAT54", false),
+                ("Q_NPN", true),
+                ("Missing", true)
+            ]
+        );
+        assert_eq!(reports[2].missing_pads, ["1", "2"]);
+        assert_eq!(reports[2].unused_pads, ["A", "K"]);
+        assert_eq!((reports[2].pin_count, reports[2].pad_count), (3, 3));
+        assert!(reports[3].error.as_ref().unwrap().contains("Other"));
+
+        assert_eq!(placed.len(), 1);
+        assert_eq!(placed[0].reference, "Q1");
+        assert_eq!(placed[0].missing_pads, ["1", "2"]);
+    }
+}
@@ -0,0 +1,310 @@
+//! Footprint position (pick and place) files from a PCB.
+//!
+//! The output follows `kicad-cli pcb export pos`.  Positions are measured
+//! from the page origin or the auxiliary (drill and place file) origin with
+//! the Y axis pointing up, and rotations are normalised to `0..360` degrees.
+//! Bottom side footprints come first, then each side is in natural order of reference.
+use super::{analysis::property_value, bom::natural_cmp, Expr};
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Board coordinates are held in nanometres, as KiCad does.
+const IU_PER_MM: f64 = 1e6;
+
+/// A side of the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Top,
+    Bottom,
+}
+
+impl Side {
+    fn name(self) -> &'static str {
+        match self {
+            Side::Top => "top",
+            Side::Bottom => "bottom",
+        }
+    }
+}
+
+/// Units for positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Units {
+    #[default]
+    Millimetres,
+    Inches,
+}
+
+/// Which footprints to include and how to measure them.
+#[derive(Debug, Clone, Default)]
+pub struct PosOptions {
+    /// Only this side, or both if `None`.
+    pub side: Option<Side>,
+    /// Measure from the auxiliary origin instead of the page origin.
+    pub aux_origin: bool,
+    /// Negate X on the bottom side, as seen from below.
+    pub negate_bottom_x: bool,
+    pub units: Units,
+    pub smd_only: bool,
+    pub exclude_through_hole: bool,
+    pub exclude_dnp: bool,
+}
+
+/// The position of one footprint.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Placement {
+    pub reference: String,
+    pub value: String,
+    pub package: String,
+    pub x: f64,
+    pub y: f64,
+    pub rotation: f64,
+    pub side: Side,
+}
+
+/// The placements of the footprints in a PCB.
+pub fn placements(pcb: &Expr, options: &PosOptions) -> Option<Vec<Placement>> {
+    pcb.head().filter(|h| *h == "kicad_pcb")?;
+
+    let origin = pcb
+        .child("setup")
+        .and_then(|s| s.child("aux_axis_origin"))
+        .filter(|_| options.aux_origin)
+        .map_or((0, 0), |o| {
+            let n = nums(o);
+            (to_iu(n.first()), to_iu(n.get(1)))
+        });
+    let scale = match options.units {
+        Units::Millimetres => 1.0 / IU_PER_MM,
+        Units::Inches => 1.0 / IU_PER_MM / 25.4,
+    };
+
+    let mut result: Vec<Placement> = pcb
+        .children("footprint")
+        .chain(pcb.children("module"))
+        .filter_map(|footprint| {
+            let attributes: Vec<&str> = footprint
+                .child("attr")
+                .into_iter()
+                .flat_map(Expr::args)
+                .filter_map(|a| a.as_atom()?.as_symbol())
+                .collect();
+            let has = |a: &str| attributes.contains(&a);
+            let through_hole = footprint.children("pad").any(|p| {
+                p.args().nth(1).and_then(|t| t.as_atom()?.as_symbol()) == Some("thru_hole")
+            });
+
+            if has("exclude_from_pos_files")
+                || options.smd_only && !has("smd")
+                || options.exclude_through_hole && through_hole
+                || options.exclude_dnp && has("dnp")
+            {
+                return None;
+            }
+
+            let side = match footprint.child("layer").and_then(Expr::str_arg)? {
+                "B.Cu" => Side::Bottom,
+                _ => Side::Top,
+            };
+            if options.side.is_some_and(|s| s != side) {
+                return None;
+            }
+
+            let at = nums(footprint.child("at")?);
+            let mut x = to_iu(at.first()) - origin.0;
+            let y = to_iu(at.get(1)) - origin.1;
+            if side == Side::Bottom && options.negate_bottom_x {
+                x = -x;
+            }
+
+            let lib_id = footprint.str_arg().unwrap_or_default();
+            Some(Placement {
+                reference: text(footprint, "reference")?.to_owned(),
+                value: text(footprint, "value").unwrap_or_default().to_owned(),
+                package: lib_id.split_once(':').map_or(lib_id, |(_, n)| n).to_owned(),
+                x: x as f64 * scale,
+                // the Y axis points up in the position file
+                y: -y as f64 * scale,
+                rotation: normalise(at.get(2).copied().unwrap_or(0.0)),
+                side,
+            })
+        })
+        .collect();
+
+    result.sort_by(|a, b| {
+        (b.side == Side::Bottom)
+            .cmp(&(a.side == Side::Bottom))
+            .then_with(|| natural_cmp(&a.reference, &b.reference))
+    });
+    Some(result)
+}
+
+/// A footprint's reference or value from its property (KiCad 8 on) or `fp_text`.
+fn text<'a>(footprint: &'a Expr, kind: &str) -> Option<&'a str> {
+    let mut name = kind.to_owned();
+    name[..1].make_ascii_uppercase();
+    property_value(footprint, &name).or_else(|| {
+        footprint.children("fp_text").find_map(|t| {
+            let mut args = t.args();
+            (args.next()?.as_atom()?.as_symbol()? == kind).then_some(())?;
+            args.next()?.as_atom()?.as_string()
+        })
+    })
+}
+
+fn nums(expr: &Expr) -> Vec<f64> {
+    expr.args().filter_map(|x| x.as_atom()?.as_num()).collect()
+}
+
+fn to_iu(mm: Option<&f64>) -> i64 {
+    (mm.copied().unwrap_or(0.0) * IU_PER_MM).round() as i64
+}
+
+/// An angle in degrees in the range `0..360`.
+fn normalise(degrees: f64) -> f64 {
+    degrees.rem_euclid(360.0) + 0.0
+}
+
+/// The placements in kicad-cli's CSV format.
+pub fn to_csv(placements: &[Placement]) -> String {
+    let mut csv = String::from("Ref,Val,Package,PosX,PosY,Rot,Side\n");
+    let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+    for p in placements {
+        writeln!(
+            csv,
+            "{},{},{},{:.6},{:.6},{:.6},{}",
+            quote(&p.reference),
+            quote(&p.value),
+            quote(&p.package),
+            p.x,
+            p.y,
+            p.rotation,
+            p.side.name()
+        )
+        .unwrap();
+    }
+    csv
+}
+
+/// The placements in kicad-cli's fixed column ASCII format.
+pub fn to_ascii(placements: &[Placement], options: &PosOptions) -> String {
+    let fields: Vec<[String; 3]> = placements
+        .iter()
+        .map(|p| [&p.reference, &p.value, &p.package].map(|s| s.replace(' ', "_")))
+        .collect();
+    let width = |i: usize, min: usize| {
+        fields
+            .iter()
+            .map(|f| f[i].chars().count())
+            .fold(min, usize::max)
+    };
+    let (r, v, k) = (width(0, 8), width(1, 8), width(2, 16));
+
+    let mut ascii = String::from("### Footprint positions - created by kiops ###\n");
+    ascii.push_str(match options.units {
+        Units::Millimetres => "## Unit = mm, Angle = deg.\n",
+        Units::Inches => "## Unit = inches, Angle = deg.\n",
+    });
+    let side = options.side.map_or("All", Side::name);
+    writeln!(ascii, "## Side : {side}").unwrap();
+    writeln!(
+        ascii,
+        "{:<r$}  {:<v$}  {:<k$}  {:>9}  {:>9}  {:>8}  Side",
+        "# Ref", "Val", "Package", "PosX", "PosY", "Rot"
+    )
+    .unwrap();
+    for (p, [reference, value, package]) in placements.iter().zip(&fields) {
+        writeln!(
+            ascii,
+            "{reference:<r$}  {value:<v$}  {package:<k$}  {:9.4}  {:9.4}  {:8.4}  {}",
+            p.x,
+            p.y,
+            p.rotation,
+            p.side.name()
+        )
+        .unwrap();
+    }
+    ascii.push_str("## End\n");
+    ascii
+}
+
+#[cfg(test)]
+mod test {
+    use super::{placements, to_ascii, to_csv, PosOptions, Side};
+    use crate::{parse_file::parse_with, sexpr::parser::parse_s_expr, sexpr::Expr};
+
+    fn pcb() -> Expr {
+        let s = r#"
+            (kicad_pcb
+                (version 20240108)
+                (setup (aux_axis_origin 50 150))
+                (footprint "Resistor_SMD:R_0603_1608Metric" (layer "F.Cu") (at 100 100 -90)
+                    (property "Reference" "R10") (property "Value" "10k") (attr smd)
+                    (pad "1" smd roundrect (at -0.8 0) (size 0.8 0.95) (layers "F.Cu")))
+                (footprint "Resistor_SMD:R_0603_1608Metric" (layer "F.Cu") (at 110.5 90)
+                    (property "Reference" "R2") (property "Value" "4k7") (attr smd))
+                (footprint "Capacitor_SMD:C_0603_1608Metric" (layer "B.Cu") (at 60 140 180)
+                    (fp_text reference "C1" (at 0 0)) (fp_text value "100n" (at 0 0))
+                    (attr smd dnp))
+                (footprint "Connector:Pin Header" (layer "F.Cu") (at 70 120 450)
+                    (property "Reference" "J1") (property "Value" "Conn 01x02")
+                    (attr through_hole)
+                    (pad "1" thru_hole rect (at 0 0) (size 1.7 1.7) (drill 1) (layers "*.Cu")))
+                (footprint "Fiducial:Fiducial_1mm" (layer "F.Cu") (at 80 80)
+                    (property "Reference" "FID1") (attr smd exclude_from_pos_files))
+            )
+        "#;
+        parse_with(s, parse_s_expr).unwrap()
+    }
+
+    #[test]
+    fn test_placements() {
+        let options = PosOptions {
+            aux_origin: true,
+            negate_bottom_x: true,
+            ..Default::default()
+        };
+        let all = placements(&pcb(), &options).unwrap();
+        let references: Vec<_> = all.iter().map(|p| p.reference.as_str()).collect();
+        assert_eq!(references, ["C1", "J1", "R2", "R10"]);
+        assert_eq!(
+            (all[0].x, all[0].y, all[0].side),
+            (-10.0, 10.0, Side::Bottom)
+        );
+        assert_eq!((all[1].rotation, all[3].rotation), (90.0, 270.0));
+
+        let options = PosOptions {
+            smd_only: true,
+            exclude_dnp: true,
+            ..Default::default()
+        };
+        let smd = placements(&pcb(), &options).unwrap();
+        assert_eq!(
+            to_csv(&smd),
+            "Ref,Val,Package,PosX,PosY,Rot,Side\n\
+             \"R2\",\"4k7\",\"R_0603_1608Metric\",110.500000,-90.000000,0.000000,top\n\
+             \"R10\",\"10k\",\"R_0603_1608Metric\",100.000000,-100.000000,270.000000,top\n"
+        );
+    }
+
+    #[test]
+    fn test_ascii() {
+        let options = PosOptions {
+            side: Some(Side::Top),
+            exclude_through_hole: true,
+            ..Default::default()
+        };
+        let top = placements(&pcb(), &options).unwrap();
+        assert_eq!(
+            to_ascii(&top, &options),
+            "### Footprint positions - created by kiops ###\n\
+             ## Unit = mm, Angle = deg.\n\
+             ## Side : top\n\
+             # Ref     Val       Package                 PosX       PosY       Rot  Side\n\
+             R2        4k7       R_0603_1608Metric   110.5000   -90.0000    0.0000  top\n\
+             R10       10k       R_0603_1608Metric   100.0000  -100.0000  270.0000  top\n\
+             ## End\n"
+        );
+    }
+}
@@ -0,0 +1,395 @@
+//! A KiCad project as a hierarchy of schematic sheets.
+//!
+//! Loading starts at the root schematic named after the `.kicad_pro` file
+//! and follows `sheet` entries recursively.  A sheet file used more than once
+//! is parsed once but appears once per instance.  Each placed symbol takes
+//! its reference and unit from the `instances` entry for its sheet instance.
+use super::{
+    analysis::property_value,
+    netlist::{Annotation, Connectivity},
+    parser::parse_s_expr,
+    Expr,
+};
+use crate::parse_file::{parse_file, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Sheets nested deeper than this are assumed to be recursive.
+const MAX_DEPTH: usize = 32;
+
+/// One instance of a schematic sheet.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sheet {
+    /// The sheet uuids from the root, as used in `instances` entries, e.g. `/<root>/<sheet>`.
+    pub path: String,
+    /// The sheet names from the root, ending with `/`, e.g. `/Power/`.
+    pub name: String,
+    pub file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<String>,
+    #[serde(skip)]
+    parent: Option<usize>,
+    #[serde(skip)]
+    symbol: Option<Expr>,
+}
+
+/// One placed symbol in one sheet instance.
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolInstance {
+    pub sheet: String,
+    #[serde(rename = "Reference")]
+    pub reference: String,
+    pub unit: i64,
+    pub lib_id: String,
+    pub in_bom: bool,
+    pub on_board: bool,
+    pub dnp: bool,
+    pub power: bool,
+    #[serde(flatten)]
+    pub properties: BTreeMap<String, String>,
+}
+
+/// A schematic hierarchy with each file parsed once.
+#[derive(Debug)]
+pub struct Project {
+    pub name: String,
+    pub sheets: Vec<Sheet>,
+    files: HashMap<PathBuf, Expr>,
+    legacy_instances: HashMap<String, Annotation>,
+}
+
+impl Project {
+    /// Load the project given its `.kicad_pro` or root `.kicad_sch` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Project> {
+        let root = path.as_ref().with_extension("kicad_sch");
+        let name = root
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut project = Project {
+            name,
+            sheets: Vec::new(),
+            files: HashMap::new(),
+            legacy_instances: HashMap::new(),
+        };
+
+        let schematic = project.read(&root)?;
+        let uuid = schematic
+            .child("uuid")
+            .and_then(uuid_arg)
+            .unwrap_or_default();
+        let page = schematic
+            .child("sheet_instances")
+            .and_then(|s| s.child("path"))
+            .and_then(|p| p.child("page"))
+            .and_then(Expr::str_arg)
+            .map(str::to_owned);
+        project.legacy_instances = legacy_instances(&schematic);
+
+        project.sheets.push(Sheet {
+            path: format!("/{uuid}"),
+            name: "/".to_owned(),
+            file: root,
+            page,
+            parent: None,
+            symbol: None,
+        });
+        project.visit(0, 0)?;
+        Ok(project)
+    }
+
+    /// Parse a file, or return the copy already parsed.
+    fn read(&mut self, file: &Path) -> Result<Expr> {
+        if let Some(expr) = self.files.get(file) {
+            return Ok(expr.clone());
+        }
+        let expr = parse_file(&file.to_string_lossy(), parse_s_expr)?;
+        self.files.insert(file.to_owned(), expr.clone());
+        Ok(expr)
+    }
+
+    /// Add the sheets used by the sheet instance at `index`, recursively.
+    fn visit(&mut self, index: usize, depth: usize) -> Result<()> {
+        if depth > MAX_DEPTH {
+            Err(format!(
+                "sheets nested too deeply at {}",
+                self.sheets[index].name
+            ))?
+        }
+        let parent = self.sheets[index].clone();
+        let schematic = self.read(&parent.file)?;
+        let dir = parent.file.parent().unwrap_or(Path::new(""));
+
+        for symbol in schematic.children("sheet") {
+            let Some(file) =
+                property_value(symbol, "Sheetfile").or(property_value(symbol, "Sheet file"))
+            else {
+                continue;
+            };
+            let sheet_name = property_value(symbol, "Sheetname")
+                .or(property_value(symbol, "Sheet name"))
+                .unwrap_or_default();
+            let uuid = symbol.child("uuid").and_then(uuid_arg).unwrap_or_default();
+            let page = instance_paths(symbol)
+                .find(|p| p.str_arg() == Some(&parent.path))
+                .and_then(|p| p.child("page"))
+                .and_then(Expr::str_arg)
+                .map(str::to_owned);
+
+            self.sheets.push(Sheet {
+                path: format!("{}/{uuid}", parent.path),
+                name: format!("{}{sheet_name}/", parent.name),
+                file: dir.join(file),
+                page,
+                parent: Some(index),
+                symbol: Some(symbol.clone()),
+            });
+            self.visit(self.sheets.len() - 1, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// The parsed schematic for a sheet instance.
+    pub fn schematic(&self, sheet: &Sheet) -> &Expr {
+        &self.files[&sheet.file]
+    }
+
+    /// Each schematic file of the project once, in sheet order.
+    pub fn files(&self) -> Vec<(&Path, &Expr)> {
+        let mut files: Vec<(&Path, &Expr)> = Vec::new();
+        for sheet in &self.sheets {
+            if !files.iter().any(|(file, _)| *file == sheet.file) {
+                files.push((&sheet.file, self.schematic(sheet)));
+            }
+        }
+        files
+    }
+
+    /// The reference and unit of a symbol placed in the given sheet instance.
+    pub fn annotate(&self, sheet: &Sheet, symbol: &Expr) -> Option<Annotation> {
+        let instance = instance_paths(symbol).find(|p| p.str_arg() == Some(&sheet.path));
+        if let Some(instance) = instance {
+            return Some(Annotation {
+                reference: instance.child("reference")?.str_arg()?.to_owned(),
+                unit: instance
+                    .child("unit")
+                    .and_then(Expr::num_arg)
+                    .map_or(1, |n| n as i64),
+            });
+        }
+
+        // KiCad 6 lists instances in the root sheet, with paths that omit the root uuid
+        let uuid = symbol.child("uuid").and_then(uuid_arg)?;
+        let within = sheet
+            .path
+            .trim_start_matches('/')
+            .split_once('/')
+            .map_or("", |(_, rest)| rest);
+        let key = if within.is_empty() {
+            format!("/{uuid}")
+        } else {
+            format!("/{within}/{uuid}")
+        };
+        if let Some(annotation) = self.legacy_instances.get(&key) {
+            return Some(annotation.clone());
+        }
+
+        Annotation::of(symbol)
+    }
+
+    /// Every placed symbol in every sheet instance.
+    pub fn symbols(&self) -> Vec<SymbolInstance> {
+        let mut result = Vec::new();
+        for sheet in &self.sheets {
+            let schematic = self.schematic(sheet);
+            let power_symbols: Vec<&str> = schematic
+                .child("lib_symbols")
+                .into_iter()
+                .flat_map(|libs| libs.children("symbol"))
+                .filter(|s| s.child("power").is_some())
+                .filter_map(Expr::str_arg)
+                .collect();
+
+            for symbol in schematic.children("symbol") {
+                let Some(annotation) = self.annotate(sheet, symbol) else {
+                    continue;
+                };
+                let lib_id = symbol
+                    .child("lib_id")
+                    .and_then(Expr::str_arg)
+                    .unwrap_or_default();
+                let lib_name = symbol.child("lib_name").and_then(Expr::str_arg);
+                let mut properties: BTreeMap<String, String> = symbol
+                    .children("property")
+                    .filter_map(|p| {
+                        let mut args = p.args();
+                        let key = args.next()?.as_atom()?.as_string()?;
+                        let value = args.next()?.as_atom()?.as_string()?;
+                        Some((key.to_owned(), value.to_owned()))
+                    })
+                    .collect();
+                properties.remove("Reference");
+
+                result.push(SymbolInstance {
+                    sheet: sheet.name.clone(),
+                    reference: annotation.reference,
+                    unit: annotation.unit,
+                    lib_id: lib_id.to_owned(),
+                    in_bom: flag(symbol, "in_bom", true),
+                    on_board: flag(symbol, "on_board", true),
+                    dnp: flag(symbol, "dnp", false),
+                    power: power_symbols.contains(&lib_name.unwrap_or(lib_id))
+                        || lib_id.starts_with("power:"),
+                    properties,
+                });
+            }
+        }
+        result
+    }
+
+    /// The connections of the whole design, across sheets.
+    pub fn connectivity(&self) -> Connectivity {
+        let mut graph = Connectivity::new();
+        for sheet in &self.sheets {
+            graph.add_sheet(&sheet.name, self.schematic(sheet), |s| {
+                self.annotate(sheet, s)
+            });
+            if let (Some(parent), Some(symbol)) = (sheet.parent, &sheet.symbol) {
+                graph.add_sheet_pins(&self.sheets[parent].name, &sheet.name, symbol);
+            }
+        }
+        graph
+    }
+}
+
+/// The `(path ..)` entries under `(instances (project ..))`.
+fn instance_paths(item: &Expr) -> impl Iterator<Item = &Expr> {
+    item.children("instances")
+        .flat_map(|i| i.children("project"))
+        .flat_map(|p| p.children("path"))
+}
+
+/// KiCad 6 `symbol_instances` in the root sheet, keyed by path.
+fn legacy_instances(root: &Expr) -> HashMap<String, Annotation> {
+    root.children("symbol_instances")
+        .flat_map(|i| i.children("path"))
+        .filter_map(|p| {
+            let annotation = Annotation {
+                reference: p.child("reference")?.str_arg()?.to_owned(),
+                unit: p
+                    .child("unit")
+                    .and_then(Expr::num_arg)
+                    .map_or(1, |n| n as i64),
+            };
+            Some((p.str_arg()?.to_owned(), annotation))
+        })
+        .collect()
+}
+
+/// A uuid written either quoted or bare.
+fn uuid_arg(expr: &Expr) -> Option<String> {
+    let atom = expr.args().next()?.as_atom()?;
+    atom.as_string()
+        .map(str::to_owned)
+        .or(atom.as_uuid().map(|u| u.to_string()))
+}
+
+/// A `yes`/`no` flag such as `(in_bom yes)`.
+fn flag(item: &Expr, key: &str, default: bool) -> bool {
+    item.child(key)
+        .and_then(Expr::symbol_arg)
+        .map_or(default, |v| v == "yes")
+}
+
+#[cfg(test)]
+mod test {
+    use super::Project;
+    use std::fs;
+
+    const ROOT: &str = r#"
+        (kicad_sch
+            (version 20250114)
+            (uuid "aaaaaaaa-0000-0000-0000-000000000000")
+            (global_label "VIN" (at 50 50 0))
+            (global_label "VIN" (at 50 80 0))
+            (sheet (at 40 40) (size 10 20)
+                (uuid "bbbbbbbb-0000-0000-0000-000000000000")
+                (property "Sheetname" "A")
+                (property "Sheetfile" "child.kicad_sch")
+                (pin "IN" input (at 50 50 0))
+                (instances (project "demo"
+                    (path "/aaaaaaaa-0000-0000-0000-000000000000" (page "2"))))
+            )
+            (sheet (at 40 70) (size 10 20)
+                (uuid "cccccccc-0000-0000-0000-000000000000")
+                (property "Sheetname" "B")
+                (property "Sheetfile" "child.kicad_sch")
+                (pin "IN" input (at 50 80 0))
+                (instances (project "demo"
+                    (path "/aaaaaaaa-0000-0000-0000-000000000000" (page "3"))))
+            )
+            (sheet_instances (path "/" (page "1")))
+        )
+    "#;
+
+    const CHILD: &str = r#"
+        (kicad_sch
+            (version 20250114)
+            (uuid "dddddddd-0000-0000-0000-000000000000")
+            (lib_symbols
+                (symbol "Device:R"
+                    (symbol "R_1_1"
+                        (pin passive line (at 0 3.81 270) (length 1.27)
+                            (name "~") (number "1"))
+                        (pin passive line (at 0 -3.81 90) (length 1.27)
+                            (name "~") (number "2"))
+                    )
+                )
+            )
+            (hierarchical_label "IN" (at 100 96.19 0))
+            (symbol (lib_id "Device:R") (at 100 100 0) (unit 1) (dnp no)
+                (uuid "eeeeeeee-0000-0000-0000-000000000000")
+                (property "Reference" "R?") (property "Value" "10k")
+                (instances (project "demo"
+                    (path "/aaaaaaaa-0000-0000-0000-000000000000/bbbbbbbb-0000-0000-0000-000000000000"
+                        (reference "R1") (unit 1))
+                    (path "/aaaaaaaa-0000-0000-0000-000000000000/cccccccc-0000-0000-0000-000000000000"
+                        (reference "R2") (unit 1))))
+            )
+        )
+    "#;
+
+    #[test]
+    fn test_project() {
+        let dir = std::env::temp_dir().join(format!("kiops-project-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("demo.kicad_sch"), ROOT).unwrap();
+        fs::write(dir.join("child.kicad_sch"), CHILD).unwrap();
+        let project = Project::load(dir.join("demo.kicad_pro"));
+        fs::remove_dir_all(&dir).unwrap();
+        let project = project.unwrap();
+
+        let names: Vec<_> = project.sheets.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["/", "/A/", "/B/"]);
+        let pages: Vec<_> = project.sheets.iter().map(|s| s.page.as_deref()).collect();
+        assert_eq!(pages, [Some("1"), Some("2"), Some("3")]);
+
+        let symbols = project.symbols();
+        let references: Vec<_> = symbols.iter().map(|s| s.reference.as_str()).collect();
+        assert_eq!(references, ["R1", "R2"]);
+        assert_eq!(symbols[1].sheet, "/B/");
+        assert_eq!(symbols[1].properties["Value"], "10k");
+        assert!(!symbols[1].dnp && symbols[1].in_bom && !symbols[1].power);
+
+        let nets = project.connectivity().nets();
+        let vin = nets.iter().find(|n| n.name == "VIN").unwrap();
+        let pins: Vec<_> = vin
+            .nodes
+            .iter()
+            .map(|n| (n.reference.as_str(), n.pin.as_str()))
+            .collect();
+        assert_eq!(pins, [("R1", "1"), ("R2", "1")]);
+    }
+}
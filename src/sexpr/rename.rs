@@ -0,0 +1,515 @@
+//! Renaming libraries and symbols and rewriting every reference to them.
+//!
+//! A rule is written `from=to` in one of three forms:
+//!
+//! |Rule|Effect|
+//! |---|---|
+//! |`Vendor=cuprous`| library `Vendor` becomes `cuprous`, `Vendor:X` becomes `cuprous:X`|
+//! |`Vendor:*=cuprous:V_*`| as above, and each symbol name is prefixed, `Vendor:X` becomes `cuprous:V_X`|
+//! |`Vendor:X=cuprous:Y`| the one symbol `Vendor:X` becomes `cuprous:Y`|
+//!
+//! The first rule that matches a name applies.  Symbol rules rewrite the
+//! `lib_id` of placed symbols, the `lib_symbols` cache of a schematic and the
+//! symbols of a library file.  Footprint rules rewrite `Footprint` properties
+//! and the footprints placed on a board.  A rule that moves a whole library
+//! also renames its entry in the library table.
+use super::{
+    symlib::{self, name_in, parent_in},
+    Expr,
+};
+use crate::{parse_file::Result, span::Span};
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// One renaming rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    /// Every item of library `from` moves to `to`, its name prefixed with `prefix`.
+    Library {
+        from: String,
+        to: String,
+        prefix: String,
+    },
+    /// The single item `from` becomes `to`, both written `Library:Name`.
+    Item { from: String, to: String },
+}
+
+impl Rule {
+    /// The new name for a `Library:Name` if this rule matches it.
+    pub fn apply(&self, id: &str) -> Option<String> {
+        match self {
+            Rule::Library { from, to, prefix } => {
+                let (library, name) = id.split_once(':')?;
+                (library == from).then(|| format!("{to}:{prefix}{name}"))
+            }
+            Rule::Item { from, to } => (id == from).then(|| to.clone()),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Rule, String> {
+        let invalid = || format!("invalid rename rule {text:?}");
+        let (from, to) = text.split_once('=').ok_or_else(invalid)?;
+        let rule = match (from.split_once(':'), to.split_once(':')) {
+            (None, None) => Rule::Library {
+                from: from.to_owned(),
+                to: to.to_owned(),
+                prefix: String::new(),
+            },
+            (Some((from, "*")), Some((to, prefix))) => Rule::Library {
+                from: from.to_owned(),
+                to: to.to_owned(),
+                prefix: prefix.strip_suffix('*').ok_or_else(invalid)?.to_owned(),
+            },
+            (Some(_), Some(_)) if !from.contains('*') && !to.contains('*') => Rule::Item {
+                from: from.to_owned(),
+                to: to.to_owned(),
+            },
+            _ => Err(invalid())?,
+        };
+        let (Rule::Library { from, to, .. } | Rule::Item { from, to }) = &rule;
+        if from.is_empty() || to.is_empty() {
+            Err(invalid())?
+        }
+        Ok(rule)
+    }
+}
+
+/// An ordered list of rules for one kind of library.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rules(pub Vec<Rule>);
+
+impl Rules {
+    /// The new name for a `Library:Name`, if the first matching rule changes it.
+    pub fn apply(&self, id: &str) -> Option<String> {
+        self.0
+            .iter()
+            .find_map(|rule| rule.apply(id))
+            .filter(|new| new != id)
+    }
+}
+
+/// The rules for symbol libraries and for footprint libraries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Renames {
+    pub symbols: Rules,
+    pub footprints: Rules,
+}
+
+/// One rewritten name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub span: Option<Span>,
+    /// Where the name was found: `lib_id`, `lib_name`, `lib_symbols`,
+    /// `symbol`, `extends`, `Footprint`, `footprint`, or the `name` or
+    /// `uri` of a library table entry.
+    pub what: &'static str,
+    pub from: String,
+    pub to: String,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(span) = self.span {
+            write!(f, "{span}: ")?;
+        }
+        write!(f, "{} {:?} -> {:?}", self.what, self.from, self.to)
+    }
+}
+
+/// Rewrite the `lib_id`s, the `lib_symbols` cache and the `Footprint`
+/// properties of a schematic.
+pub fn rename_schematic(schematic: Expr, renames: &Renames) -> (Expr, Vec<Change>) {
+    let mut changes = Vec::new();
+    let output = rewrite(schematic, renames, &mut changes);
+    (output, changes)
+}
+
+/// Rewrite the footprints placed on a board and their `Footprint` properties.
+pub fn rename_board(board: Expr, renames: &Renames) -> (Expr, Vec<Change>) {
+    let mut changes = Vec::new();
+    let output = rewrite(board, renames, &mut changes);
+    (output, changes)
+}
+
+/// Give a library in a `sym-lib-table` or `fp-lib-table` the nickname a
+/// library rule moves it to, unless the table already has that nickname.
+/// A library whose file is named for its nickname has its uri changed to
+/// match, leaving the file itself to be renamed by the caller.
+pub fn rename_table(table: Expr, renames: &Renames) -> (Expr, Vec<Change>) {
+    let (rules, extension) = match table.head() {
+        Some("sym_lib_table") => (&renames.symbols, "kicad_sym"),
+        Some("fp_lib_table") => (&renames.footprints, "pretty"),
+        _ => return (table, Vec::new()),
+    };
+    let name_of = |lib: &Expr| Some(text_of(lib.child("name")?.args().next()?)?.to_owned());
+    let mut names: Vec<String> = table.children("lib").filter_map(name_of).collect();
+    let mut changes = Vec::new();
+    let span = table.span();
+    let mut output = table.into_deque().unwrap_or_default();
+    for lib in output.iter_mut().filter(|e| e.head() == Some("lib")) {
+        let Some(name) = name_of(lib) else {
+            continue;
+        };
+        let new = rules.0.iter().find_map(|rule| match rule {
+            Rule::Library { from, to, .. } if *from == name && !names.contains(to) => Some(to),
+            _ => None,
+        });
+        let Some(new) = new else {
+            continue;
+        };
+        names.push(new.clone());
+        let old_file = format!("{name}.{extension}");
+        let new_uri = lib
+            .child("uri")
+            .and_then(|uri| text_of(uri.args().next()?))
+            .and_then(|uri| uri.strip_suffix(&old_file))
+            .filter(|dir| dir.is_empty() || dir.ends_with(['/', '\\']))
+            .map(|dir| format!("{dir}{new}.{extension}"));
+        let Expr::List(items, _) = lib else {
+            continue;
+        };
+        for item in items.iter_mut() {
+            match (item.head(), &new_uri) {
+                (Some("name"), _) => set_value(item, "name", new, &mut changes),
+                (Some("uri"), Some(uri)) => set_value(item, "uri", uri, &mut changes),
+                _ => {}
+            }
+        }
+    }
+    (Expr::List(output, span), changes)
+}
+
+/// Rename the symbols of the library known by `nickname`, along with
+/// their units, the `extends` of derived symbols and their `Footprint`
+/// properties.  Moving a symbol to another library does not change
+/// its name within this file unless a prefix or new name is given.
+pub fn rename_symlib(
+    library: Expr,
+    nickname: &str,
+    renames: &Renames,
+) -> Result<(Expr, Vec<Change>)> {
+    let short = |name: &str| -> Option<String> {
+        let new = renames.symbols.apply(&format!("{nickname}:{name}"))?;
+        let (_, new) = new.split_once(':')?;
+        (new != name).then(|| new.to_owned())
+    };
+
+    let mut changes = Vec::new();
+    let mut names = BTreeSet::new();
+    let span = library.span();
+    let elems = library.into_deque().unwrap_or_default();
+    let mut output = Vec::new();
+    for elem in elems {
+        if elem.head() != Some("symbol") {
+            output.push(elem);
+            continue;
+        }
+        let mut symbol = elem;
+        let name = name_in(&symbol).unwrap_or_default();
+        let new_name = short(&name);
+        if let Some(new) = &new_name {
+            changes.push(change(&symbol, 1, "symbol", &name, new));
+            symbol = symlib::rename(symbol, &name, new);
+        }
+        if let Some(new) = parent_in(&symbol).and_then(|parent| {
+            let new = short(&parent)?;
+            changes.push(Change {
+                span: symbol
+                    .child("extends")
+                    .and_then(|e| e.args().next()?.span()),
+                what: "extends",
+                from: parent,
+                to: new.clone(),
+            });
+            Some(new)
+        }) {
+            symbol = symlib::extend(symbol, &new);
+        }
+        let final_name = new_name.unwrap_or(name);
+        if !names.insert(final_name.clone()) {
+            Err(format!(
+                "{nickname}: renaming gives two symbols named {final_name:?}"
+            ))?
+        }
+        output.push(rewrite(symbol, renames, &mut changes));
+    }
+    Ok((Expr::list(output).with_span(span), changes))
+}
+
+/// Rewrite names throughout an expression.
+fn rewrite(expr: Expr, renames: &Renames, changes: &mut Vec<Change>) -> Expr {
+    let Expr::List(elems, span) = expr else {
+        return expr;
+    };
+    let head = elems.front().and_then(|h| h.as_atom()?.as_symbol());
+    let footprint = head == Some("property") && elems.get(1).and_then(str_of) == Some("Footprint");
+    let elems = match head {
+        Some("lib_id") => replace(elems, 1, &renames.symbols, "lib_id", changes),
+        Some("lib_name") => replace(elems, 1, &renames.symbols, "lib_name", changes),
+        Some("footprint") => replace(elems, 1, &renames.footprints, "footprint", changes)
+            .into_iter()
+            .map(|elem| rewrite(elem, renames, changes))
+            .collect(),
+        _ if footprint => replace(elems, 2, &renames.footprints, "Footprint", changes),
+        Some("lib_symbols") => elems
+            .into_iter()
+            .map(|elem| {
+                let elem = if elem.head() == Some("symbol") {
+                    rename_cached(elem, &renames.symbols, changes)
+                } else {
+                    elem
+                };
+                rewrite(elem, renames, changes)
+            })
+            .collect(),
+        _ => elems
+            .into_iter()
+            .map(|elem| rewrite(elem, renames, changes))
+            .collect(),
+    };
+    Expr::List(elems, span)
+}
+
+/// Rename a symbol in the `lib_symbols` cache, which is named by its
+/// `lib_id` while its units are named for the symbol alone.
+fn rename_cached(symbol: Expr, rules: &Rules, changes: &mut Vec<Change>) -> Expr {
+    let Some(id) = name_in(&symbol) else {
+        return symbol;
+    };
+    let Some(new) = rules.apply(&id) else {
+        return symbol;
+    };
+    changes.push(change(&symbol, 1, "lib_symbols", &id, &new));
+    let item = |id: &str| id.split_once(':').map_or(id, |(_, item)| item).to_owned();
+    let symbol = symlib::rename(symbol, &item(&id), &item(&new));
+    symlib::rename(symbol, &id, &new)
+}
+
+/// Replace the string at `index` if a rule renames it.
+fn replace(
+    mut elems: VecDeque<Expr>,
+    index: usize,
+    rules: &Rules,
+    what: &'static str,
+    changes: &mut Vec<Change>,
+) -> VecDeque<Expr> {
+    if let Some(elem) = elems.get_mut(index) {
+        if let Some(new) = str_of(elem).and_then(|id| rules.apply(id)) {
+            let from = str_of(elem).unwrap_or_default().to_owned();
+            changes.push(Change {
+                span: elem.span(),
+                what,
+                from,
+                to: new.clone(),
+            });
+            *elem = Expr::Constant(new.into(), elem.span());
+        }
+    }
+    elems
+}
+
+/// Replace the value of a `(key value)` item.
+fn set_value(item: &mut Expr, what: &'static str, to: &str, changes: &mut Vec<Change>) {
+    let Expr::List(elems, _) = item else {
+        return;
+    };
+    if let Some(elem) = elems.get_mut(1) {
+        changes.push(Change {
+            span: elem.span(),
+            what,
+            from: text_of(elem).unwrap_or_default().to_owned(),
+            to: to.to_owned(),
+        });
+        *elem = Expr::Constant(to.into(), elem.span());
+    }
+}
+
+fn change(item: &Expr, index: usize, what: &'static str, from: &str, to: &str) -> Change {
+    Change {
+        span: item
+            .as_list()
+            .and_then(|elems| elems.get(index))
+            .and_then(Expr::span),
+        what,
+        from: from.to_owned(),
+        to: to.to_owned(),
+    }
+}
+
+fn str_of(expr: &Expr) -> Option<&str> {
+    expr.as_atom()?.as_string()
+}
+
+/// A string, or a symbol as older library tables write their names.
+fn text_of(expr: &Expr) -> Option<&str> {
+    let atom = expr.as_atom()?;
+    atom.as_string().or_else(|| atom.as_symbol())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        rename_board, rename_schematic, rename_symlib, rename_table, Renames, Rule, Rules,
+    };
+    use crate::{
+        parse_file::parse_with,
+        sexpr::{parser::parse_s_expr, Expr},
+    };
+
+    fn parse(s: &str) -> Expr {
+        parse_with(s, parse_s_expr).unwrap()
+    }
+
+    fn renames(symbols: &[&str], footprints: &[&str]) -> Renames {
+        let rules = |rules: &[&str]| Rules(rules.iter().map(|r| r.parse().unwrap()).collect());
+        Renames {
+            symbols: rules(symbols),
+            footprints: rules(footprints),
+        }
+    }
+
+    #[test]
+    fn test_rules() {
+        let rule = |s: &str| s.parse::<Rule>();
+        assert_eq!(rule("A=B").unwrap().apply("A:x").unwrap(), "B:x");
+        assert_eq!(rule("A:*=B:p_*").unwrap().apply("A:x").unwrap(), "B:p_x");
+        assert_eq!(rule("A:x=B:y").unwrap().apply("A:x").unwrap(), "B:y");
+        assert_eq!(rule("A:x=B:y").unwrap().apply("A:z"), None);
+        assert!(rule("A").is_err());
+        assert!(rule("A:*=B").is_err());
+        assert!(rule("A:*=B:p").is_err());
+        assert!(rule("=B").is_err());
+
+        let rules = renames(&["A:x=A:y", "A=B"], &[]).symbols;
+        assert_eq!(rules.apply("A:x").unwrap(), "A:y");
+        assert_eq!(rules.apply("A:z").unwrap(), "B:z");
+        assert_eq!(rules.apply("C:z"), None);
+    }
+
+    #[test]
+    fn test_schematic() {
+        let schematic = parse(
+            r#"(kicad_sch
+                (lib_symbols
+                    (symbol "Vendor:U" (property "Footprint" "VFP:QFN")
+                        (symbol "U_0_1" (rectangle)) (symbol "U_1_1" (pin))))
+                (symbol (lib_id "Vendor:U") (property "Footprint" "VFP:QFN"))
+                (symbol (lib_id "Device:R") (property "Footprint" "Other:R")))"#,
+        );
+        let renames = renames(&["Vendor:*=cuprous:V_*"], &["VFP=cuprous"]);
+        let (output, changes) = rename_schematic(schematic, &renames);
+        let expected = parse(
+            r#"(kicad_sch
+                (lib_symbols
+                    (symbol "cuprous:V_U" (property "Footprint" "cuprous:QFN")
+                        (symbol "V_U_0_1" (rectangle)) (symbol "V_U_1_1" (pin))))
+                (symbol (lib_id "cuprous:V_U") (property "Footprint" "cuprous:QFN"))
+                (symbol (lib_id "Device:R") (property "Footprint" "Other:R")))"#,
+        );
+        assert_eq!(output, expected);
+        let report: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            report,
+            [
+                r#"3:29: lib_symbols "Vendor:U" -> "cuprous:V_U""#,
+                r#"3:62: Footprint "VFP:QFN" -> "cuprous:QFN""#,
+                r#"5:33: lib_id "Vendor:U" -> "cuprous:V_U""#,
+                r#"5:67: Footprint "VFP:QFN" -> "cuprous:QFN""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_symlib() {
+        let library = parse(
+            r#"(kicad_symbol_lib (version 20231120)
+                (symbol "U" (symbol "U_0_1" (rectangle)))
+                (symbol "U2" (extends "U")))"#,
+        );
+        let (output, changes) = rename_symlib(
+            library.clone(),
+            "Vendor",
+            &renames(&["Vendor:U=cuprous:X"], &[]),
+        )
+        .unwrap();
+        let expected = parse(
+            r#"(kicad_symbol_lib (version 20231120)
+                (symbol "X" (symbol "X_0_1" (rectangle)))
+                (symbol "U2" (extends "X")))"#,
+        );
+        assert_eq!(output, expected);
+        assert_eq!(changes.len(), 2);
+
+        let moved = rename_symlib(
+            library.clone(),
+            "Vendor",
+            &renames(&["Vendor=cuprous"], &[]),
+        );
+        assert!(moved.unwrap().1.is_empty());
+
+        let clash = rename_symlib(library, "Vendor", &renames(&["Vendor:U=cuprous:U2"], &[]));
+        assert!(clash.is_err());
+    }
+
+    #[test]
+    fn test_board() {
+        let board = parse(
+            r#"(kicad_pcb
+                (footprint "VFP:QFN" (property "Footprint" "VFP:QFN") (pad "1"))
+                (footprint "Other:R"))"#,
+        );
+        let (output, changes) = rename_board(board, &renames(&[], &["VFP=cuprous"]));
+        let expected = parse(
+            r#"(kicad_pcb
+                (footprint "cuprous:QFN" (property "Footprint" "cuprous:QFN") (pad "1"))
+                (footprint "Other:R"))"#,
+        );
+        assert_eq!(output, expected);
+        let report: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            report,
+            [
+                r#"2:28: footprint "VFP:QFN" -> "cuprous:QFN""#,
+                r#"2:60: Footprint "VFP:QFN" -> "cuprous:QFN""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let table = parse(
+            r#"(sym_lib_table (version 7)
+                (lib (name "Vendor")(type "KiCad")(uri "${KIPRJMOD}/Vendor.kicad_sym"))
+                (lib (name Parts)(type "KiCad")(uri "${KIPRJMOD}/parts.kicad_sym"))
+                (lib (name "Old")(type "KiCad")(uri "Old.kicad_sym"))
+                (lib (name "cuprous")(type "KiCad")(uri "cuprous.kicad_sym")))"#,
+        );
+        let renames = renames(&["Vendor=vendor", "Parts:*=p:P_*", "Old=cuprous"], &[]);
+        let (output, changes) = rename_table(table, &renames);
+        let expected = parse(
+            r#"(sym_lib_table (version 7)
+                (lib (name "vendor")(type "KiCad")(uri "${KIPRJMOD}/vendor.kicad_sym"))
+                (lib (name "p")(type "KiCad")(uri "${KIPRJMOD}/parts.kicad_sym"))
+                (lib (name "Old")(type "KiCad")(uri "Old.kicad_sym"))
+                (lib (name "cuprous")(type "KiCad")(uri "cuprous.kicad_sym")))"#,
+        );
+        assert_eq!(output, expected);
+        let report: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            report,
+            [
+                r#"2:28: name "Vendor" -> "vendor""#,
+                r#"2:56: uri "${KIPRJMOD}/Vendor.kicad_sym" -> "${KIPRJMOD}/vendor.kicad_sym""#,
+                r#"3:28: name "Parts" -> "p""#,
+            ]
+        );
+
+        let footprints = rename_table(parse("(fp_lib_table (lib (name \"Vendor\")))"), &renames);
+        assert!(footprints.1.is_empty());
+    }
+}
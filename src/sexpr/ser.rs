@@ -0,0 +1,479 @@
+//! Serialize Rust values as S-expressions following KiCad's conventions.
+//!
+//! This is the reverse of `de`.  A struct becomes a list headed by its name,
+//! so a top level struct is usually renamed, as in `#[serde(rename = "footprint")]`.
+//! Within it:
+//!
+//! - a field whose name begins with `_` is written as a positional argument,
+//! - `None` and empty `Vec` fields are left out,
+//! - a `bool` field becomes `(key yes)` or `(key no)`,
+//! - a number or string field becomes `(key value)` and a tuple `(key a b c)`,
+//! - a struct field becomes `(key ...)` holding its own fields,
+//! - a `Vec` of numbers, strings or unit enums becomes one `(key a b c)`,
+//!   and any other `Vec` a `(key ...)` for each element.
+//!
+//! Unit enum variants are written as symbols and other variants as lists
+//! headed by the variant name.  Map entries are written as fields.
+use super::{de::Error, Atom, Expr, Numeric};
+use serde::ser::{self, Serialize};
+use std::fmt::Display;
+
+/// Serialize a value as an S-expression.
+pub fn to_expr<T: Serialize + ?Sized>(value: &T) -> Result<Expr, Error> {
+    Ok(value.serialize(Serializer)?.into_expr().unwrap_or_default())
+}
+
+/// A serialized value before it is placed in its parent.
+enum Node {
+    None,
+    Atom(Expr),
+    Seq(Vec<Node>),
+    Struct(&'static str, Vec<Expr>),
+    Variant(Expr),
+}
+
+impl Node {
+    fn into_expr(self) -> Option<Expr> {
+        match self {
+            Node::None => None,
+            Node::Atom(x) | Node::Variant(x) => Some(x),
+            Node::Seq(xs) => Some(Expr::list(xs.into_iter().filter_map(Node::into_expr))),
+            Node::Struct("", fields) => Some(Expr::list(fields)),
+            Node::Struct(name, fields) => Some(headed(name, fields)),
+        }
+    }
+
+    /// The arguments following the key when this is the value of a field.
+    fn into_args(self) -> Vec<Expr> {
+        match self {
+            Node::None => Vec::new(),
+            Node::Atom(x) | Node::Variant(x) => vec![x],
+            Node::Seq(xs) => xs.into_iter().filter_map(Node::into_expr).collect(),
+            Node::Struct(_, fields) => fields,
+        }
+    }
+}
+
+fn headed(key: &str, args: Vec<Expr>) -> Expr {
+    Expr::list([Expr::key(key)].into_iter().chain(args))
+}
+
+/// An integer, spelled exactly even where `value` cannot hold it.
+fn integer(value: f64, exact: impl Display) -> Node {
+    let n = Numeric::with_text(value, &exact.to_string());
+    Node::Atom(Expr::Constant(Atom::Num(n), None))
+}
+
+/// Add a field to the contents of a struct.
+fn place(fields: &mut Vec<Expr>, key: &str, value: Node) {
+    if key.starts_with('_') {
+        fields.extend(value.into_expr());
+        return;
+    }
+    match value {
+        Node::None => (),
+        Node::Seq(xs) if xs.iter().all(|x| matches!(x, Node::Atom(_))) => {
+            if !xs.is_empty() {
+                fields.push(headed(key, Node::Seq(xs).into_args()));
+            }
+        }
+        Node::Seq(xs) => {
+            for x in xs {
+                if !matches!(x, Node::None) {
+                    fields.push(headed(key, x.into_args()));
+                }
+            }
+        }
+        value => fields.push(headed(key, value.into_args())),
+    }
+}
+
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Node;
+    type Error = Error;
+    type SerializeSeq = Seq;
+    type SerializeTuple = Seq;
+    type SerializeTupleStruct = Seq;
+    type SerializeTupleVariant = Seq;
+    type SerializeMap = Map;
+    type SerializeStruct = Struct;
+    type SerializeStructVariant = Struct;
+
+    fn serialize_bool(self, v: bool) -> Result<Node, Error> {
+        Ok(Node::Atom(Expr::key(if v { "yes" } else { "no" })))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Node, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Node, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Node, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Node, Error> {
+        Ok(integer(v as f64, v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Node, Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Node, Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Node, Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Node, Error> {
+        Ok(integer(v as f64, v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Node, Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Node, Error> {
+        Ok(Node::Atom(v.into()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Node, Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Node, Error> {
+        Ok(Node::Atom(v.into()))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Node, Error> {
+        Err(ser::Error::custom(
+            "bytes cannot be written as an S-expression",
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Node, Error> {
+        Ok(Node::None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Node, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Node, Error> {
+        Ok(Node::None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Node, Error> {
+        Ok(Node::None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Node, Error> {
+        Ok(Node::Atom(Expr::key(variant)))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Node, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Node, Error> {
+        let value = value.serialize(self)?;
+        Ok(Node::Variant(headed(variant, value.into_args())))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Seq, Error> {
+        Ok(Seq {
+            variant: None,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Seq, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Seq, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Seq, Error> {
+        Ok(Seq {
+            variant: Some(variant),
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Map, Error> {
+        Ok(Map {
+            fields: Vec::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, _len: usize) -> Result<Struct, Error> {
+        Ok(Struct {
+            name,
+            variant: false,
+            fields: Vec::new(),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Struct, Error> {
+        Ok(Struct {
+            name: variant,
+            variant: true,
+            fields: Vec::new(),
+        })
+    }
+}
+
+struct Seq {
+    variant: Option<&'static str>,
+    items: Vec<Node>,
+}
+
+impl Seq {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Node, Error> {
+        Ok(match self.variant {
+            Some(variant) => Node::Variant(headed(variant, Node::Seq(self.items).into_args())),
+            None => Node::Seq(self.items),
+        })
+    }
+}
+
+impl ser::SerializeSeq for Seq {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for Seq {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for Seq {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for Seq {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        self.finish()
+    }
+}
+
+struct Map {
+    fields: Vec<Expr>,
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for Map {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        let key = match key.serialize(Serializer)? {
+            Node::Atom(Expr::Constant(Atom::Str(s) | Atom::Symbol(s), _)) => s,
+            Node::Atom(Expr::Constant(atom, _)) => atom.to_string(),
+            _ => Err(ser::Error::custom("a map key must be a string"))?,
+        };
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ser::Error::custom("a map value must follow its key"))?;
+        place(&mut self.fields, &key, value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        Ok(Node::Struct("", self.fields))
+    }
+}
+
+struct Struct {
+    name: &'static str,
+    variant: bool,
+    fields: Vec<Expr>,
+}
+
+impl ser::SerializeStruct for Struct {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        place(&mut self.fields, key, value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        Ok(if self.variant {
+            Node::Variant(headed(self.name, self.fields))
+        } else {
+            Node::Struct(self.name, self.fields)
+        })
+    }
+}
+
+impl ser::SerializeStructVariant for Struct {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        ser::SerializeStruct::end(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::to_expr;
+    use crate::sexpr::{de::from_expr, format::to_kicad_string};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum PadType {
+        Smd,
+        ThruHole,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename = "pad")]
+    struct Pad {
+        #[serde(rename = "_number")]
+        number: String,
+        #[serde(rename = "_type")]
+        pad_type: PadType,
+        at: (f64, f64),
+        layers: Vec<String>,
+        drill: Option<f64>,
+        locked: bool,
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let pad = Pad {
+            number: "1".to_owned(),
+            pad_type: PadType::ThruHole,
+            at: (-0.8, 0.0),
+            layers: vec!["*.Cu".to_owned(), "*.Mask".to_owned()],
+            drill: None,
+            locked: true,
+        };
+        let expr = to_expr(&pad).unwrap();
+        assert_eq!(
+            to_kicad_string(&expr),
+            "(pad \"1\" thru_hole\n\t(at -0.8 0)\n\t(layers \"*.Cu\" \"*.Mask\")\n\t(locked yes)\n)\n"
+        );
+        assert_eq!(from_expr::<Pad>(&expr).unwrap(), pad);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename = "counts")]
+    struct Counts {
+        big: u64,
+        small: i64,
+        byte: u8,
+    }
+
+    #[test]
+    fn test_integers() {
+        let counts = Counts {
+            big: u64::MAX,
+            small: i64::MIN,
+            byte: 7,
+        };
+        let expr = to_expr(&counts).unwrap();
+        assert_eq!(
+            to_kicad_string(&expr),
+            "(counts\n\t(big 18446744073709551615)\n\t(small -9223372036854775808)\n\t(byte 7)\n)\n"
+        );
+        assert_eq!(from_expr::<Counts>(&expr).unwrap(), counts);
+    }
+}
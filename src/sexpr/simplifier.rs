@@ -1,10 +1,68 @@
-use super::{Atom, Expr};
+use super::{de, json::expr_to_json_value, Atom, Expr};
+use crate::span::Span;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{borrow::Cow, fmt::Display};
 
 pub use super::Atom::*;
 
 pub trait Simplifier: Clone {
     fn simplify(&self, subject: &Expr) -> Option<Expr>;
 
+    /// Simplify as `simplify` does while adding the expressions
+    /// captured by `Capture` to `bindings`.
+    /// Combinators pass the bindings through to the simplifiers they contain.
+    /// Simplifiers which do not contain others record a rejection when explaining.
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        let result = self.simplify(subject);
+        if result.is_none() {
+            bindings.reject(subject, || self.expected());
+        }
+        result
+    }
+
+    /// Bind the elements of the list `subject` from `start` on, as if they
+    /// were a list of their own.  `Cons` matches the tail of a list this way
+    /// rather than copying it at every step.  The default copies the elements,
+    /// so combinators which match lists override it.
+    fn bind_from(&self, subject: &Expr, start: usize, bindings: &mut Bindings) -> Option<Expr> {
+        self.bind(&rest(subject, start), bindings)
+    }
+
+    /// What the simplifier expects, to explain a rejection.
+    fn expected(&self) -> String {
+        format!("a match for `{}`", std::any::type_name::<Self>())
+    }
+
+    /// Simplify the subject or explain which simplifiers rejected which expressions.
+    fn explain(&self, subject: &Expr) -> Result<Expr, Explanation> {
+        let mut bindings = Bindings::explaining();
+        self.bind(subject, &mut bindings)
+            .ok_or_else(|| Explanation(bindings.trace.map(|t| t.rejections).unwrap_or_default()))
+    }
+
+    /// Simplify the subject or describe the failure,
+    /// located at the first expression a simplifier rejected.
+    fn simplify_or(&self, subject: &Expr, message: &str) -> Result<Expr, String> {
+        self.simplify(subject)
+            .ok_or_else(|| match self.explain(subject) {
+                Err(explanation) => explanation.error(message),
+                Ok(_) => message.to_owned(),
+            })
+    }
+
+    /// Match the subject returning the simplified result and the captures.
+    fn matches(&self, subject: &Expr) -> Option<(Expr, Bindings)> {
+        let mut bindings = Bindings::default();
+        let result = self.bind(subject, &mut bindings)?;
+        Some((result, bindings))
+    }
+
+    /// Match the subject returning only the captures.
+    fn capture(&self, subject: &Expr) -> Option<Bindings> {
+        Some(self.matches(subject)?.1)
+    }
+
     fn or(self, other: impl Simplifier) -> impl Simplifier
     where
         Self: Sized,
@@ -37,6 +95,10 @@ impl Simplifier for Atom {
             None
         }
     }
+
+    fn expected(&self) -> String {
+        format!("`{self}`")
+    }
 }
 
 impl Simplifier for &'static str {
@@ -47,6 +109,10 @@ impl Simplifier for &'static str {
             None
         }
     }
+
+    fn expected(&self) -> String {
+        format!("symbol `{self}`")
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -60,6 +126,10 @@ impl Simplifier for AnyNum {
             None
         }
     }
+
+    fn expected(&self) -> String {
+        "a number".to_owned()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -73,6 +143,30 @@ impl Simplifier for AnyStr {
             None
         }
     }
+
+    fn expected(&self) -> String {
+        "a string".to_owned()
+    }
+}
+
+/// The elements of the list `subject` from `start` on.
+fn rest(subject: &Expr, start: usize) -> Cow<'_, Expr> {
+    match subject {
+        Expr::List(elems, _) if start > 0 => Cow::Owned(Expr::List(
+            elems.iter().skip(start).cloned().collect(),
+            span_from(subject, start),
+        )),
+        _ => Cow::Borrowed(subject),
+    }
+}
+
+/// The span of the list `subject` from `start` on, which is that of its
+/// first element.  An empty tail has no span.
+fn span_from(subject: &Expr, start: usize) -> Option<Span> {
+    match subject {
+        Expr::List(elems, _) if start > 0 => elems.get(start).and_then(Expr::span),
+        _ => subject.span(),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -95,6 +189,277 @@ impl Simplifier for Nothing {
             None
         }
     }
+
+    fn bind_from(&self, subject: &Expr, start: usize, bindings: &mut Bindings) -> Option<Expr> {
+        match subject.as_list() {
+            Some(elems) if elems.len() <= start => {
+                Some(Expr::empty().with_span(span_from(subject, start)))
+            }
+            _ => {
+                bindings.reject_from(subject, start, || self.expected());
+                None
+            }
+        }
+    }
+
+    fn expected(&self) -> String {
+        "an empty list".to_owned()
+    }
+}
+
+/// The named expressions captured while matching, in the order they were captured.
+/// A name captured more than once has several values.
+/// When explaining a match the bindings also trace why simplifiers failed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bindings {
+    captures: Vec<(String, Expr)>,
+    trace: Option<Trace>,
+}
+
+/// The combinators entered so far and the rejections recorded within them.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Trace {
+    path: Vec<String>,
+    rejections: Vec<Rejection>,
+}
+
+impl Bindings {
+    /// Bindings that record rejections.
+    pub fn explaining() -> Self {
+        Bindings {
+            captures: Vec::new(),
+            trace: Some(Trace::default()),
+        }
+    }
+
+    /// Add a capture.
+    pub fn insert(&mut self, name: &str, value: Expr) {
+        self.captures.push((name.to_owned(), value));
+    }
+
+    /// The first value captured with the given name.
+    pub fn get(&self, name: &str) -> Option<&Expr> {
+        self.captures
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v)
+    }
+
+    /// Every value captured with the given name.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Expr> {
+        self.captures
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .map(|(_, v)| v)
+    }
+
+    /// The first string captured with the given name.
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.get(name)?.as_atom()?.as_string()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Expr)> {
+        self.captures.iter().map(|(n, v)| (n.as_str(), v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.captures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.captures.is_empty()
+    }
+
+    /// Run a match, dropping anything it captured if it fails.
+    pub fn attempt(&mut self, f: impl FnOnce(&mut Bindings) -> Option<Expr>) -> Option<Expr> {
+        let mark = self.captures.len();
+        let result = f(self);
+        if result.is_none() {
+            self.captures.truncate(mark);
+        }
+        result
+    }
+
+    /// Record that the subject was not what a simplifier expected.
+    /// Nothing is recorded unless explaining.
+    pub fn reject(&mut self, subject: &Expr, expected: impl FnOnce() -> String) {
+        self.record(subject.span(), expected, || describe(subject));
+    }
+
+    /// Record that the elements of the list `subject` from `start` on were
+    /// not what a simplifier expected.  The rejection is located at the first
+    /// of them, or at the list if none are left.
+    pub fn reject_from(&mut self, subject: &Expr, start: usize, expected: impl FnOnce() -> String) {
+        let Some(elems) = subject.as_list().filter(|_| start > 0) else {
+            return self.reject(subject, expected);
+        };
+        match elems.get(start) {
+            Some(first) => self.record(first.span(), expected, || match elems.len() - start - 1 {
+                0 => describe(first),
+                more => format!("{} and {more} more", describe(first)),
+            }),
+            None => self.record(subject.span(), expected, || {
+                "the end of the list".to_owned()
+            }),
+        }
+    }
+
+    fn record(
+        &mut self,
+        span: Option<Span>,
+        expected: impl FnOnce() -> String,
+        found: impl FnOnce() -> String,
+    ) {
+        if let Some(trace) = &mut self.trace {
+            let rejection = Rejection {
+                path: trace.path.join(" / "),
+                expected: expected(),
+                found: found(),
+                span,
+            };
+            trace.rejections.push(rejection);
+        }
+    }
+
+    /// Run a match within a labelled step of a combinator.
+    /// The label is added to the path of rejections recorded by the match,
+    /// which are forgotten if the match succeeds.
+    pub fn within(
+        &mut self,
+        label: impl Display,
+        f: impl FnOnce(&mut Bindings) -> Option<Expr>,
+    ) -> Option<Expr> {
+        let Some(trace) = &mut self.trace else {
+            return f(self);
+        };
+        trace.path.push(label.to_string());
+        let mark = trace.rejections.len();
+        let result = f(self);
+        if let Some(trace) = &mut self.trace {
+            trace.path.pop();
+            if result.is_some() {
+                trace.rejections.truncate(mark);
+            }
+        }
+        result
+    }
+
+    /// Run a match whose failure is expected, forgetting any rejections it records.
+    pub fn quietly(&mut self, f: impl FnOnce(&mut Bindings) -> Option<Expr>) -> Option<Expr> {
+        let mark = self.rejections();
+        let result = f(self);
+        self.forget(mark);
+        result
+    }
+
+    fn rejections(&self) -> usize {
+        self.trace.as_ref().map_or(0, |t| t.rejections.len())
+    }
+
+    fn forget(&mut self, mark: usize) {
+        if let Some(trace) = &mut self.trace {
+            trace.rejections.truncate(mark);
+        }
+    }
+
+    /// The bindings as a JSON object for display.  A name captured once
+    /// maps to its value and a name captured several times to an array.
+    pub fn to_json(&self) -> Value {
+        let mut obj = Map::new();
+        for (name, _) in &self.captures {
+            if obj.contains_key(name) {
+                continue;
+            }
+            let mut values: Vec<Value> = self
+                .get_all(name)
+                .map(|v| expr_to_json_value(v.clone()))
+                .collect();
+            let value = match values.len() {
+                1 => values.pop().unwrap(),
+                _ => Value::Array(values),
+            };
+            obj.insert(name.clone(), value);
+        }
+        Value::Object(obj)
+    }
+
+    /// Deserialize the bindings into a struct with fields named for the captures.
+    /// A `Vec` field takes every value captured with its name, however many there are.
+    pub fn deserialize<'a, T: Deserialize<'a>>(&'a self) -> Result<T, de::Error> {
+        let named: Vec<(&str, &Expr)> = self.iter().collect();
+        de::from_named(&named)
+    }
+}
+
+/// An expression which a simplifier did not accept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejection {
+    /// The combinator steps leading to the simplifier, such as `Cons head`.
+    pub path: String,
+    pub expected: String,
+    pub found: String,
+    pub span: Option<Span>,
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(span) = self.span {
+            write!(f, "{span}: ")?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "expected {}, found {}", self.expected, self.found)
+    }
+}
+
+/// Why a simplifier failed: the rejections that caused it, innermost last.
+#[derive(Clone, PartialEq)]
+pub struct Explanation(pub Vec<Rejection>);
+
+impl Explanation {
+    /// Describe the failure, prefixed by the location of the first rejection if known.
+    pub fn error(&self, message: &str) -> String {
+        match self.0.iter().find_map(|r| r.span) {
+            Some(span) => format!("{span}: {message}"),
+            None => message.to_owned(),
+        }
+    }
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("no match");
+        }
+        for (i, rejection) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{rejection}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Explanation {}
+
+/// A brief description of an expression for a rejection.
+fn describe(subject: &Expr) -> String {
+    match subject {
+        Expr::Constant(atom, _) => format!("`{atom}`"),
+        Expr::List(elems, _) => match elems.front() {
+            None => "`()`".to_owned(),
+            Some(Expr::Constant(Symbol(head), _)) => format!("`({head} ...)`"),
+            Some(_) => "a list".to_owned(),
+        },
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -106,20 +471,28 @@ where
     B: Simplifier,
 {
     fn simplify(&self, subject: &Expr) -> Option<Expr> {
-        let elems = subject.as_list()?;
-        let elem = elems.front()?;
-        let head = self.0.simplify(elem)?;
-        let mut elems = elems.clone();
-        elems.pop_front();
-        let tail = self.1.simplify(&Expr::List(elems))?;
+        self.bind(subject, &mut Bindings::default())
+    }
+
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        self.bind_from(subject, 0, bindings)
+    }
+
+    fn bind_from(&self, subject: &Expr, start: usize, bindings: &mut Bindings) -> Option<Expr> {
+        let Some(elem) = subject.as_list().and_then(|elems| elems.get(start)) else {
+            bindings.reject_from(subject, start, || "a non-empty list".to_owned());
+            return None;
+        };
+        let head = bindings.within("Cons head", |b| self.0.bind(elem, b))?;
+        let tail = bindings.within("Cons tail", |b| self.1.bind_from(subject, start + 1, b))?;
         let l = if !head.is_empty() {
             let mut result = tail.into_deque()?;
             result.push_front(head);
-            Expr::List(result)
+            Expr::List(result, None)
         } else {
             tail
         };
-        Some(l)
+        Some(l.with_span(span_from(subject, start)))
     }
 }
 
@@ -131,7 +504,23 @@ where
     A: Simplifier,
 {
     fn simplify(&self, subject: &Expr) -> Option<Expr> {
-        self.0.simplify(subject.as_list()?.front()?)
+        self.bind(subject, &mut Bindings::default())
+    }
+
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        let Some(elem) = subject.as_list().and_then(|elems| elems.front()) else {
+            bindings.reject(subject, || "a non-empty list".to_owned());
+            return None;
+        };
+        bindings.within("Head", |b| self.0.bind(elem, b))
+    }
+
+    fn bind_from(&self, subject: &Expr, start: usize, bindings: &mut Bindings) -> Option<Expr> {
+        let Some(elem) = subject.as_list().and_then(|elems| elems.get(start)) else {
+            bindings.reject_from(subject, start, || "a non-empty list".to_owned());
+            return None;
+        };
+        bindings.within("Head", |b| self.0.bind(elem, b))
     }
 }
 
@@ -144,9 +533,22 @@ where
     B: Simplifier,
 {
     fn simplify(&self, subject: &Expr) -> Option<Expr> {
-        self.0
-            .simplify(subject)
-            .or_else(|| self.1.simplify(subject))
+        self.bind(subject, &mut Bindings::default())
+    }
+
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        self.bind_from(subject, 0, bindings)
+    }
+
+    fn bind_from(&self, subject: &Expr, start: usize, bindings: &mut Bindings) -> Option<Expr> {
+        let mark = bindings.rejections();
+        let result = bindings
+            .attempt(|b| b.within("Or first", |b| self.0.bind_from(subject, start, b)))
+            .or_else(|| bindings.within("Or second", |b| self.1.bind_from(subject, start, b)));
+        if result.is_some() {
+            bindings.forget(mark);
+        }
+        result
     }
 }
 #[derive(Debug, Clone)]
@@ -158,7 +560,16 @@ where
     B: Simplifier,
 {
     fn simplify(&self, subject: &Expr) -> Option<Expr> {
-        self.0.simplify(subject).and_then(|x| self.1.simplify(&x))
+        self.bind(subject, &mut Bindings::default())
+    }
+
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        self.bind_from(subject, 0, bindings)
+    }
+
+    fn bind_from(&self, subject: &Expr, start: usize, bindings: &mut Bindings) -> Option<Expr> {
+        let x = bindings.within("And first", |b| self.0.bind_from(subject, start, b))?;
+        bindings.within("And then", |b| self.1.bind(&x, b))
     }
 }
 
@@ -170,9 +581,27 @@ where
     A: Simplifier,
 {
     fn simplify(&self, subject: &Expr) -> Option<Expr> {
-        Some(Expr::list(
-            subject.as_list()?.iter().filter_map(|x| self.0.simplify(x)),
-        ))
+        self.bind(subject, &mut Bindings::default())
+    }
+
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        self.bind_from(subject, 0, bindings)
+    }
+
+    fn bind_from(&self, subject: &Expr, start: usize, bindings: &mut Bindings) -> Option<Expr> {
+        let Some(elems) = subject.as_list() else {
+            bindings.reject(subject, || "a list".to_owned());
+            return None;
+        };
+        Some(
+            Expr::list(
+                elems
+                    .iter()
+                    .skip(start)
+                    .filter_map(|x| bindings.quietly(|b| b.attempt(|b| self.0.bind(x, b)))),
+            )
+            .with_span(span_from(subject, start)),
+        )
     }
 }
 
@@ -184,7 +613,28 @@ where
     A: Simplifier,
 {
     fn simplify(&self, subject: &Expr) -> Option<Expr> {
-        subject.as_list()?.iter().find_map(|x| self.0.simplify(x))
+        self.bind(subject, &mut Bindings::default())
+    }
+
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        self.bind_from(subject, 0, bindings)
+    }
+
+    fn bind_from(&self, subject: &Expr, start: usize, bindings: &mut Bindings) -> Option<Expr> {
+        let Some(elems) = subject.as_list() else {
+            bindings.reject(subject, || "a list".to_owned());
+            return None;
+        };
+        let result = elems
+            .iter()
+            .skip(start)
+            .find_map(|x| bindings.quietly(|b| b.attempt(|b| self.0.bind(x, b))));
+        if result.is_none() {
+            bindings.reject_from(subject, start, || {
+                "a list with a matching element".to_owned()
+            });
+        }
+        result
     }
 }
 
@@ -196,7 +646,18 @@ where
     A: Simplifier,
 {
     fn simplify(&self, subject: &Expr) -> Option<Expr> {
-        self.0.simplify(subject).is_some().then_some(Expr::empty())
+        self.bind(subject, &mut Bindings::default())
+    }
+
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        self.bind_from(subject, 0, bindings)
+    }
+
+    fn bind_from(&self, subject: &Expr, start: usize, bindings: &mut Bindings) -> Option<Expr> {
+        bindings
+            .within("Discard", |b| self.0.bind_from(subject, start, b))
+            .is_some()
+            .then_some(Expr::empty())
     }
 }
 
@@ -208,10 +669,18 @@ where
     A: Simplifier,
 {
     fn simplify(&self, subject: &Expr) -> Option<Expr> {
-        self.0
-            .simplify(subject)
+        self.bind(subject, &mut Bindings::default())
+    }
+
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        self.bind_from(subject, 0, bindings)
+    }
+
+    fn bind_from(&self, subject: &Expr, start: usize, bindings: &mut Bindings) -> Option<Expr> {
+        bindings
+            .within("Ensure", |b| self.0.bind_from(subject, start, b))
             .is_some()
-            .then_some(subject.clone())
+            .then(|| rest(subject, start).into_owned())
     }
 }
 
@@ -228,6 +697,10 @@ where
             .is_none()
             .then_some(subject.clone())
     }
+
+    fn expected(&self) -> String {
+        format!("not {}", self.0.expected())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -238,3 +711,325 @@ impl Simplifier for LabelAs {
         Some(Expr::list([Expr::key(self.0), subject.clone()]))
     }
 }
+
+/// Match with `A` and, if that succeeds, bind the simplified result to a name.
+/// The result is passed on unchanged.
+#[derive(Debug, Clone)]
+pub struct Capture<A>(pub &'static str, pub A);
+
+impl<A> Simplifier for Capture<A>
+where
+    A: Simplifier,
+{
+    fn simplify(&self, subject: &Expr) -> Option<Expr> {
+        self.1.simplify(subject)
+    }
+
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        self.bind_from(subject, 0, bindings)
+    }
+
+    fn bind_from(&self, subject: &Expr, start: usize, bindings: &mut Bindings) -> Option<Expr> {
+        let name = self.0;
+        let result = bindings.within(format_args!("Capture `{name}`"), |b| {
+            self.1.bind_from(subject, start, b)
+        })?;
+        bindings.insert(self.0, result.clone());
+        Some(result)
+    }
+}
+
+/// Match every expression nested at any depth within the subject with `A`,
+/// in document order, producing a `List` of the successes.
+/// The children of a matching expression are searched too.
+#[derive(Debug, Clone)]
+pub struct Descendants<A>(pub A);
+
+impl<A> Simplifier for Descendants<A>
+where
+    A: Simplifier,
+{
+    fn simplify(&self, subject: &Expr) -> Option<Expr> {
+        self.bind(subject, &mut Bindings::default())
+    }
+
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        fn visit(
+            simplifier: &impl Simplifier,
+            expr: &Expr,
+            found: &mut Vec<Expr>,
+            bindings: &mut Bindings,
+        ) {
+            for x in expr.as_list().into_iter().flatten() {
+                found.extend(bindings.quietly(|b| b.attempt(|b| simplifier.bind(x, b))));
+                visit(simplifier, x, found, bindings);
+            }
+        }
+        let mut found = Vec::new();
+        visit(&self.0, subject, &mut found, bindings);
+        Some(Expr::list(found))
+    }
+}
+
+/// Match the expressions nested at any depth within the subject with `A`,
+/// in document order, extracting the first success.
+#[derive(Debug, Clone)]
+pub struct FindDeep<A>(pub A);
+
+impl<A> Simplifier for FindDeep<A>
+where
+    A: Simplifier,
+{
+    fn simplify(&self, subject: &Expr) -> Option<Expr> {
+        self.bind(subject, &mut Bindings::default())
+    }
+
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        fn search(
+            simplifier: &impl Simplifier,
+            expr: &Expr,
+            bindings: &mut Bindings,
+        ) -> Option<Expr> {
+            expr.as_list()?.iter().find_map(|x| {
+                bindings
+                    .attempt(|b| simplifier.bind(x, b))
+                    .or_else(|| search(simplifier, x, bindings))
+            })
+        }
+        let result = bindings.quietly(|b| search(&self.0, subject, b));
+        if result.is_none() {
+            bindings.reject(subject, || "a matching descendant".to_owned());
+        }
+        result
+    }
+}
+
+/// Reproduce the subject replacing each expression matched by `A`, at any depth,
+/// with the simplified result.  A replacement is not searched again.
+/// This always succeeds.
+#[derive(Debug, Clone)]
+pub struct Rewrite<A>(pub A);
+
+impl<A> Simplifier for Rewrite<A>
+where
+    A: Simplifier,
+{
+    fn simplify(&self, subject: &Expr) -> Option<Expr> {
+        self.bind(subject, &mut Bindings::default())
+    }
+
+    fn bind(&self, subject: &Expr, bindings: &mut Bindings) -> Option<Expr> {
+        bindings
+            .quietly(|b| b.attempt(|b| self.0.bind(subject, b)))
+            .or_else(|| match subject {
+                Expr::List(elems, span) => Some(Expr::List(
+                    elems
+                        .iter()
+                        .filter_map(|x| self.bind(x, bindings))
+                        .collect(),
+                    *span,
+                )),
+                _ => Some(subject.clone()),
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        AnyNum, AnyStr, Anything, Capture, Cons, Descendants, Discard, Ensure, Find, FindDeep,
+        Nothing, Rewrite, Simplifier,
+    };
+    use crate::{
+        parse_file::parse_with,
+        sexpr::{format::to_kicad_string, parser::parse_s_expr, Atom, Expr},
+    };
+    use serde::Deserialize;
+
+    fn pcb() -> Expr {
+        let s = r#"
+            (kicad_pcb
+                (footprint "R_0603" (property "MPN" "RC0603-10K")
+                    (fp_text user "x" (effects (font (size 1.27 1.27)))))
+                (footprint "C_0603" (property "MPN" "CL10B104")
+                    (fp_text user "y" (effects (font (size 1.27 1.27) (thickness 0.15)))))
+                (gr_text "z" (effects (font (size 1.5 1.5))))
+            )
+        "#;
+        parse_with(s, parse_s_expr).unwrap()
+    }
+
+    #[test]
+    fn test_descendants() {
+        let mpn = Cons("property", Cons(Atom::from("MPN"), Cons(AnyStr, Nothing)));
+        let found = Descendants(mpn.clone()).simplify(&pcb()).unwrap();
+        assert_eq!(found.as_list().unwrap().len(), 2);
+
+        let first = FindDeep(mpn).simplify(&pcb()).unwrap();
+        assert_eq!(first.args().nth(1), Some(&Expr::from("RC0603-10K")));
+        assert!(FindDeep("no_such_thing").simplify(&pcb()).is_none());
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let size = Atom::from(1.27);
+        let new_size = parse_with("(size 1 1)", parse_s_expr).unwrap();
+        let resize = Rewrite(
+            Cons("size", Cons(size.clone(), Cons(size, Nothing)))
+                .and(move |_: &Expr| Some(new_size.clone())),
+        );
+        let result = to_kicad_string(&resize.simplify(&pcb()).unwrap());
+        assert_eq!(result.matches("(size 1 1)").count(), 2);
+        assert_eq!(result.matches("(size 1.5 1.5)").count(), 1);
+    }
+
+    #[test]
+    fn test_capture() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Part {
+            footprint: String,
+            mpn: Vec<String>,
+        }
+
+        let footprint = Cons("footprint", Cons(Capture("footprint", AnyStr), Anything));
+        let mpn = Cons(
+            Discard("property"),
+            Cons(
+                Discard(Atom::from("MPN")),
+                Cons(Capture("mpn", AnyStr), Anything),
+            ),
+        );
+        let parts = Cons(
+            Discard("kicad_pcb"),
+            Ensure(Find(footprint)).and(Descendants(mpn)),
+        );
+        let (_, bindings) = parts.matches(&pcb()).unwrap();
+        assert_eq!(bindings.get_str("footprint"), Some("R_0603"));
+        assert_eq!(bindings.len(), 3);
+        assert_eq!(
+            bindings.deserialize::<Part>().unwrap(),
+            Part {
+                footprint: "R_0603".to_owned(),
+                mpn: vec!["RC0603-10K".to_owned(), "CL10B104".to_owned()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_capture_once_or_never() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Part {
+            value: String,
+            mpn: Vec<String>,
+            datasheet: Option<String>,
+        }
+
+        let property = |name: &str, capture: &'static str| {
+            Cons(
+                Discard("property"),
+                Cons(
+                    Discard(Atom::from(name)),
+                    Cons(Capture(capture, AnyStr), Anything),
+                ),
+            )
+        };
+        let part = Ensure(Descendants(property("Value", "value")))
+            .and(Descendants(property("MPN", "mpn")))
+            .and(Descendants(property("Datasheet", "datasheet")));
+        let one = parse_with(
+            r#"(footprint "R_0603" (property "Value" "10k") (property "MPN" "RC0603-10K"))"#,
+            parse_s_expr,
+        )
+        .unwrap();
+        assert_eq!(
+            part.capture(&one).unwrap().deserialize::<Part>().unwrap(),
+            Part {
+                value: "10k".to_owned(),
+                mpn: vec!["RC0603-10K".to_owned()],
+                datasheet: None,
+            }
+        );
+
+        let none = parse_with(
+            r#"(footprint "R_0603" (property "Value" "10k"))"#,
+            parse_s_expr,
+        )
+        .unwrap();
+        let bindings = part.capture(&none).unwrap();
+        assert_eq!(
+            bindings.deserialize::<Part>().unwrap().mpn,
+            Vec::<String>::new()
+        );
+
+        let missing = parse_with(r#"(footprint "R_0603")"#, parse_s_expr).unwrap();
+        let bindings = part.capture(&missing).unwrap();
+        assert!(bindings.deserialize::<Part>().is_err());
+    }
+
+    #[test]
+    fn test_failed_capture() {
+        let size = Cons(
+            "size",
+            Cons(Capture("x", AnyNum), Cons(Atom::from(2.0), Nothing)),
+        );
+        let sizes = Descendants(size);
+        let bindings = sizes.capture(&pcb()).unwrap();
+        assert!(bindings.is_empty());
+    }
+    #[test]
+    fn test_explain() {
+        let explanation = Cons("kicad_sch", Anything).explain(&pcb()).unwrap_err();
+        assert_eq!(
+            explanation.to_string(),
+            "2:14: Cons head: expected symbol `kicad_sch`, found `kicad_pcb`"
+        );
+
+        let footprint = Cons("footprint", Cons(AnyNum, Anything));
+        let explanation = Cons("kicad_pcb", Find(footprint.clone()))
+            .explain(&pcb())
+            .unwrap_err();
+        assert_eq!(
+            explanation.to_string(),
+            "3:17: Cons tail: expected a list with a matching element, \
+             found `(footprint ...)` and 2 more"
+        );
+
+        let either = Cons("kicad_pcb", Find(footprint.or(Cons("gr_text", Anything))));
+        assert!(either.explain(&pcb()).is_ok());
+        assert!(Cons(AnyStr.or("kicad_pcb"), Anything)
+            .explain(&pcb())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_simplify_or() {
+        let schematic = Cons("kicad_sch", Anything);
+        assert_eq!(
+            schematic.simplify_or(&pcb(), "expected a schematic"),
+            Err("2:14: expected a schematic".to_owned())
+        );
+        assert!(Cons("kicad_pcb", Anything)
+            .simplify_or(&pcb(), "expected a board")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_explain_tail() {
+        let pair = Cons(Discard("a"), Cons(AnyNum, Nothing));
+        let long = parse_with("(a\n  1 2)", parse_s_expr).unwrap();
+        assert_eq!(
+            pair.explain(&long).unwrap_err().to_string(),
+            "2:5: Cons tail / Cons tail: expected an empty list, found `2`"
+        );
+        assert_eq!(
+            pair.simplify_or(&long, "expected a pair"),
+            Err("2:5: expected a pair".to_owned())
+        );
+
+        let short = parse_with("(a)", parse_s_expr).unwrap();
+        assert_eq!(
+            pair.explain(&short).unwrap_err().to_string(),
+            "1:1: Cons tail: expected a non-empty list, found the end of the list"
+        );
+    }
+}
@@ -1,37 +1,377 @@
+use crate::parse_file::Result;
 use crate::sexpr::simplifier::{Anything, Cons, Discard, Filter, Find, Head, Simplifier};
-use crate::sexpr::Expr;
-use std::collections::BTreeMap;
+use crate::sexpr::{Atom, Expr};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
 use std::iter::once;
 
 const HEADING: &str = "kicad_symbol_lib";
 const GENERATOR: &str = "generator";
 const VERSION: &str = "version";
 const SYMBOL: &str = "symbol";
+const EXTENDS: &str = "extends";
+const PROPERTY: &str = "property";
 
-pub fn merge(input1: Expr, input2: Expr) -> Option<Expr> {
-    let version = attr_in(VERSION, &input1)?;
-    let generator = attr_in(GENERATOR, &input1)?;
-    if version != attr_in(VERSION, &input2)? || generator != attr_in(GENERATOR, &input2)? {
-        None?
+/// How a merge resolves items that share a name but differ in definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Keep the definition from the earliest library.
+    FirstWins,
+    /// Keep the definition from the latest library.
+    LastWins,
+    /// Refuse to merge, reporting every conflict.
+    FailOnConflict,
+    /// Keep every definition, renaming all but the first with a numeric suffix.
+    KeepBoth,
+}
+
+/// An item defined differently in two or more of the merged libraries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// What the item is, such as `symbol` or `footprint`.
+    pub kind: &'static str,
+    pub name: String,
+    /// The libraries containing each distinct definition, in order.
+    pub sources: Vec<String>,
+    /// The `(source, name)` of each definition kept in the output.
+    pub kept: Vec<(String, String)>,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:?} differs in {}",
+            self.kind,
+            self.name,
+            self.sources.join(", ")
+        )?;
+        if !self.kept.is_empty() {
+            let kept: Vec<String> = self
+                .kept
+                .iter()
+                .map(|(source, name)| format!("{name:?} from {source}"))
+                .collect();
+            write!(f, ", kept {}", kept.join(" and "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of `merge`: a library and the conflicts resolved to produce it.
+#[derive(Debug, Clone)]
+pub struct Merged {
+    pub library: Expr,
+    pub conflicts: Vec<Conflict>,
+    /// The `(child, parent)` names of derived symbols whose parent is missing.
+    pub orphans: Vec<(String, String)>,
+}
+
+/// Merge any number of named symbol libraries into one.
+///
+/// Symbols are compared structurally, so a symbol repeated verbatim in
+/// several libraries is kept once and not reported.  Differing symbols of
+/// the same name are resolved by `policy`.  The output takes the version
+/// and generator of the newest input library.
+///
+/// A derived symbol follows its parent when the parent is renamed by
+/// `Policy::KeepBoth`, and is reported as an orphan if no parent is found.
+pub fn merge(inputs: &[(String, Expr)], policy: Policy) -> Result<Merged> {
+    let mut newest: Option<(f64, Expr, Expr)> = None;
+    let mut definitions: BTreeMap<String, Vec<(&str, Expr)>> = BTreeMap::new();
+    for (source, input) in inputs {
+        let not_symlib = || format!("{source}: not a symbol library");
+        let version = attr_in(VERSION, input).ok_or_else(not_symlib)?;
+        let generator = attr_in(GENERATOR, input).ok_or_else(not_symlib)?;
+        let number = version.as_atom().and_then(Atom::as_num).unwrap_or(0.0);
+        if newest.as_ref().is_none_or(|(n, _, _)| number > *n) {
+            newest = Some((number, version, generator));
+        }
+        for symbol in symbols_in(input).ok_or_else(not_symlib)? {
+            let Some(name) = name_in(&symbol) else {
+                continue;
+            };
+            let defs = definitions.entry(name).or_default();
+            if !defs.iter().any(|(_, other)| *other == symbol) {
+                defs.push((source, symbol));
+            }
+        }
+    }
+    let (_, version, generator) = newest.ok_or("no symbol libraries to merge")?;
+
+    let Resolution {
+        items: symbols,
+        renamed,
+        conflicts,
+    } = resolve("symbol", definitions, policy, rename)?;
+
+    let mut orphans = Vec::new();
+    let mut resolved = BTreeMap::new();
+    for (name, (source, symbol)) in &symbols {
+        let mut symbol = symbol.clone();
+        if let Some(parent) = parent_in(&symbol) {
+            let parent = match renamed.get(&(*source, parent.clone())) {
+                Some(renamed) => {
+                    symbol = extend(symbol, renamed);
+                    renamed.clone()
+                }
+                None => parent,
+            };
+            if !symbols.contains_key(&parent) {
+                orphans.push((name.clone(), parent));
+            }
+        }
+        resolved.insert(name.clone(), symbol);
+    }
+    let library = symlib(version, generator, ordered(&resolved).into_iter());
+    Ok(Merged {
+        library,
+        conflicts,
+        orphans,
+    })
+}
+
+/// The definitions kept when merging, and how they were chosen.
+pub(crate) struct Resolution<'s, T> {
+    /// The definition kept for each name, with its source.
+    pub items: BTreeMap<String, (&'s str, T)>,
+    /// The new name of each `(source, name)` renamed by `Policy::KeepBoth`.
+    pub renamed: BTreeMap<(&'s str, String), String>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Resolve the distinct definitions found for each name by `policy`.
+/// `kind` describes the items in conflicts and `rename(item, from, to)` renames one.
+pub(crate) fn resolve<'s, T>(
+    kind: &'static str,
+    definitions: BTreeMap<String, Vec<(&'s str, T)>>,
+    policy: Policy,
+    rename: impl Fn(T, &str, &str) -> T,
+) -> Result<Resolution<'s, T>> {
+    let mut taken: BTreeSet<String> = definitions.keys().cloned().collect();
+    let mut items = BTreeMap::new();
+    let mut renamed = BTreeMap::new();
+    let mut conflicts = Vec::new();
+    for (name, mut defs) in definitions {
+        if defs.len() == 1 {
+            items.insert(name, defs.remove(0));
+            continue;
+        }
+        let sources = defs.iter().map(|(source, _)| source.to_string()).collect();
+        let mut kept = Vec::new();
+        match policy {
+            Policy::FirstWins | Policy::LastWins => {
+                let (source, item) = if policy == Policy::FirstWins {
+                    defs.remove(0)
+                } else {
+                    defs.pop().unwrap()
+                };
+                kept.push((source.to_owned(), name.clone()));
+                items.insert(name.clone(), (source, item));
+            }
+            Policy::FailOnConflict => (),
+            Policy::KeepBoth => {
+                for (index, (source, item)) in defs.into_iter().enumerate() {
+                    let new_name = if index == 0 {
+                        name.clone()
+                    } else {
+                        let new_name = (2..)
+                            .map(|n| format!("{name}_{n}"))
+                            .find(|candidate| !taken.contains(candidate))
+                            .unwrap();
+                        taken.insert(new_name.clone());
+                        renamed.insert((source, name.clone()), new_name.clone());
+                        new_name
+                    };
+                    kept.push((source.to_owned(), new_name.clone()));
+                    let item = rename(item, &name, &new_name);
+                    items.insert(new_name, (source, item));
+                }
+            }
+        }
+        conflicts.push(Conflict {
+            kind,
+            name,
+            sources,
+            kept,
+        });
+    }
+
+    if policy == Policy::FailOnConflict && !conflicts.is_empty() {
+        let report: Vec<String> = conflicts.iter().map(Conflict::to_string).collect();
+        Err(report.join("\n"))?
+    }
+    Ok(Resolution {
+        items,
+        renamed,
+        conflicts,
+    })
+}
+
+/// What `split` does with a derived symbol, one that `extends` a parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Derived {
+    /// Write the symbol's ancestors into its library ahead of it.
+    Bundle,
+    /// Write a standalone symbol combining the symbol with its ancestors.
+    Flatten,
+}
+
+/// The name of the symbol a derived symbol extends.
+pub fn parent_in(symbol: &Expr) -> Option<String> {
+    Some(symbol.child(EXTENDS)?.str_arg()?.to_owned())
+}
+
+/// Point a derived symbol at a different parent.
+pub(crate) fn extend(symbol: Expr, parent: &str) -> Expr {
+    let span = symbol.span();
+    let Some(elems) = symbol.clone().into_deque() else {
+        return symbol;
+    };
+    let elems = elems.into_iter().map(|elem| {
+        if elem.head() == Some(EXTENDS) {
+            list(EXTENDS, once(Expr::Constant(parent.into(), None))).with_span(elem.span())
+        } else {
+            elem
+        }
+    });
+    Expr::list(elems).with_span(span)
+}
+
+/// A symbol preceded by its ancestors, most distant first, as far as they are found.
+pub fn lineage(symbols: &BTreeMap<String, Expr>, name: &str) -> Vec<Expr> {
+    let mut line: Vec<Expr> = Vec::new();
+    let mut next = Some(name.to_owned());
+    while let Some(name) = next {
+        let Some(symbol) = symbols.get(&name) else {
+            break;
+        };
+        if line.iter().any(|s| name_in(s).as_ref() == Some(&name)) {
+            break;
+        }
+        next = parent_in(symbol);
+        line.push(symbol.clone());
+    }
+    line.reverse();
+    line
+}
+
+/// Symbols in name order except that each follows its ancestors, which KiCad requires.
+pub fn ordered(symbols: &BTreeMap<String, Expr>) -> Vec<Expr> {
+    let mut done = BTreeSet::new();
+    let mut output = Vec::new();
+    for name in symbols.keys() {
+        for symbol in lineage(symbols, name) {
+            if done.insert(name_in(&symbol)) {
+                output.push(symbol);
+            }
+        }
+    }
+    output
+}
+
+/// A derived symbol made standalone by applying it over its ancestors.
+///
+/// The symbol keeps the graphics and pins of its ancestors, renamed for the
+/// symbol, while its own properties and attributes replace theirs.
+pub fn flatten(symbols: &BTreeMap<String, Expr>, name: &str) -> Option<Expr> {
+    let mut line = lineage(symbols, name).into_iter();
+    let base = line.next()?;
+    Some(line.fold(base, inherit))
+}
+
+fn inherit(parent: Expr, child: Expr) -> Expr {
+    let span = child.span();
+    let (Some(from), Some(to)) = (name_in(&parent), name_in(&child)) else {
+        return child;
+    };
+    let overrides: Vec<Expr> = child
+        .into_deque()
+        .unwrap_or_default()
+        .into_iter()
+        .skip(2)
+        .filter(|elem| elem.head() != Some(EXTENDS))
+        .collect();
+    let mut used = vec![false; overrides.len()];
+    let mut elems = Vec::new();
+    let parent = rename(parent, &from, &to).into_deque().unwrap_or_default();
+    for elem in parent {
+        if elem.head() == Some(SYMBOL) {
+            push_unused(&mut elems, &overrides, &mut used);
+        }
+        match overrides.iter().position(|o| same_slot(o, &elem)) {
+            Some(index) => {
+                used[index] = true;
+                elems.push(overrides[index].clone());
+            }
+            None => elems.push(elem),
+        }
     }
-    let s1 = symbols_in(&input1)?;
-    let s2 = symbols_in(&input2)?;
-    let symbols = unique(s1.chain(s2));
-    let output = symlib(version, generator, symbols);
-    Some(output)
+    push_unused(&mut elems, &overrides, &mut used);
+    Expr::list(elems).with_span(span)
+}
+
+fn push_unused(elems: &mut Vec<Expr>, overrides: &[Expr], used: &mut [bool]) {
+    for (elem, used) in overrides.iter().zip(used) {
+        if !*used {
+            *used = true;
+            elems.push(elem.clone());
+        }
+    }
+}
+
+/// Does a child's element replace a parent's?  Properties are matched by
+/// name, units never, and other attributes by their key.
+fn same_slot(child: &Expr, parent: &Expr) -> bool {
+    match (child.head(), parent.head()) {
+        (Some(PROPERTY), Some(PROPERTY)) => child.str_arg() == parent.str_arg(),
+        (Some(SYMBOL), _) | (_, Some(SYMBOL)) => false,
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Rename a symbol along with its units, which KiCad names `{name}_{unit}_{style}`.
+pub fn rename(symbol: Expr, from: &str, to: &str) -> Expr {
+    let span = symbol.span();
+    let Some(elems) = symbol.clone().into_deque() else {
+        return symbol;
+    };
+    let elems = elems.into_iter().enumerate().map(|(index, elem)| {
+        if index == 1 {
+            let suffix = elem
+                .as_atom()
+                .and_then(Atom::as_string)
+                .and_then(|name| name.strip_prefix(from))
+                .filter(|suffix| suffix.is_empty() || suffix.starts_with('_'));
+            match suffix {
+                Some(suffix) => Expr::Constant(format!("{to}{suffix}").into(), elem.span()),
+                None => elem,
+            }
+        } else if elem.head() == Some(SYMBOL) {
+            rename(elem, from, to)
+        } else {
+            elem
+        }
+    });
+    Expr::list(elems).with_span(span)
 }
 
-pub fn split(input: Expr) -> Option<Vec<(String, Expr)>> {
+pub fn split(input: Expr, derived: Derived) -> Option<Vec<(String, Expr)>> {
     let symbols = group(symbols_in(&input)?);
     let version = attr_in(VERSION, &input)?;
     let generator = attr_in(GENERATOR, &input)?;
     Some(
         symbols
-            .into_iter()
-            .map(|(name, symbol)| {
+            .keys()
+            .map(|name| {
+                let content = match derived {
+                    Derived::Bundle => lineage(&symbols, name),
+                    Derived::Flatten => flatten(&symbols, name).into_iter().collect(),
+                };
                 (
-                    name,
-                    symlib(version.clone(), generator.clone(), once(symbol)),
+                    name.clone(),
+                    symlib(version.clone(), generator.clone(), content.into_iter()),
                 )
             })
             .collect(),
@@ -39,7 +379,7 @@ pub fn split(input: Expr) -> Option<Vec<(String, Expr)>> {
 }
 
 pub fn unique(symbols: impl Iterator<Item = Expr>) -> impl Iterator<Item = Expr> {
-    group(symbols).into_iter().map(|(_, v)| v)
+    group(symbols).into_values()
 }
 
 pub fn group(symbols: impl Iterator<Item = Expr>) -> BTreeMap<String, Expr> {
@@ -75,3 +415,146 @@ pub fn symlib(version: Expr, generator: Expr, symbols: impl Iterator<Item = Expr
             .chain(symbols),
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::{group, merge, name_in, parent_in, split, symbols_in, Derived, Policy};
+    use crate::{
+        parse_file::parse_with,
+        sexpr::{parser::parse_s_expr, Expr},
+    };
+
+    fn library(version: u32, symbols: &str) -> Expr {
+        let s = format!(r#"(kicad_symbol_lib (version {version}) (generator "test") {symbols})"#);
+        parse_with(&s, parse_s_expr).unwrap()
+    }
+
+    fn inputs() -> Vec<(String, Expr)> {
+        let r1 = r#"(symbol "R" (property "Value" "R") (symbol "R_0_1" (rectangle)))"#;
+        let r2 = r#"(symbol "R" (property "Value" "R_Small") (symbol "R_0_1" (polyline)))"#;
+        vec![
+            (
+                "a".to_owned(),
+                library(20211014, &format!("{r1} (symbol \"C\")")),
+            ),
+            (
+                "b".to_owned(),
+                library(20211014, &format!("{r1} (symbol \"L\")")),
+            ),
+            ("c".to_owned(), library(20231120, r2)),
+        ]
+    }
+
+    fn names(library: &Expr) -> Vec<String> {
+        symbols_in(library)
+            .unwrap()
+            .filter_map(|s| name_in(&s))
+            .collect()
+    }
+
+    fn value_of(library: &Expr, name: &str) -> String {
+        let symbol = symbols_in(library)
+            .unwrap()
+            .find(|s| name_in(s).as_deref() == Some(name))
+            .unwrap();
+        let property = symbol.child("property").unwrap();
+        let value = property.args().nth(1).unwrap().to_string();
+        value
+    }
+
+    #[test]
+    fn test_policies() {
+        let first = merge(&inputs(), Policy::FirstWins).unwrap();
+        assert_eq!(names(&first.library), ["C", "L", "R"]);
+        assert_eq!(value_of(&first.library, "R"), r#""R""#);
+        assert_eq!(first.conflicts.len(), 1);
+        assert_eq!(first.conflicts[0].sources, ["a", "c"]);
+        assert_eq!(
+            first.conflicts[0].to_string(),
+            r#"symbol "R" differs in a, c, kept "R" from a"#
+        );
+
+        let last = merge(&inputs(), Policy::LastWins).unwrap();
+        assert_eq!(value_of(&last.library, "R"), r#""R_Small""#);
+
+        let fail = merge(&inputs(), Policy::FailOnConflict).unwrap_err();
+        assert!(fail.to_string().contains(r#"symbol "R" differs in a, c"#));
+    }
+
+    #[test]
+    fn test_keep_both() {
+        let both = merge(&inputs(), Policy::KeepBoth).unwrap();
+        assert_eq!(names(&both.library), ["C", "L", "R", "R_2"]);
+        assert_eq!(value_of(&both.library, "R_2"), r#""R_Small""#);
+        let renamed = symbols_in(&both.library).unwrap().last().unwrap();
+        assert_eq!(
+            name_in(renamed.child("symbol").unwrap()).unwrap(),
+            "R_2_0_1"
+        );
+    }
+
+    #[test]
+    fn test_identical_and_versions() {
+        let merged = merge(&inputs()[..2], Policy::FailOnConflict).unwrap();
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(names(&merged.library), ["C", "L", "R"]);
+
+        let merged = merge(&inputs(), Policy::FirstWins).unwrap();
+        let version = merged.library.child("version").unwrap();
+        assert_eq!(version.num_arg(), Some(20231120.0));
+    }
+
+    fn derived() -> Expr {
+        library(
+            20231120,
+            r#"
+            (symbol "NE555" (property "Reference" "U") (property "Value" "NE555")
+                (in_bom yes) (symbol "NE555_1_1" (pin input line (number "2"))))
+            (symbol "ICM7555" (extends "NE555") (property "Value" "ICM7555")
+                (property "MPN" "ICM7555IPAZ"))
+            (symbol "LMC555" (extends "ICM7555") (property "Value" "LMC555"))
+            "#,
+        )
+    }
+
+    #[test]
+    fn test_split_derived() {
+        let bundled = split(derived(), Derived::Bundle).unwrap();
+        let (name, lmc555) = &bundled[1];
+        assert_eq!(name, "LMC555");
+        assert_eq!(names(lmc555), ["NE555", "ICM7555", "LMC555"]);
+
+        let flat = split(derived(), Derived::Flatten).unwrap();
+        let (_, lmc555) = &flat[1];
+        let expected = r#"
+            (symbol "LMC555" (property "Reference" "U") (property "Value" "LMC555")
+                (in_bom yes) (property "MPN" "ICM7555IPAZ")
+                (symbol "LMC555_1_1" (pin input line (number "2"))))
+        "#;
+        let expected = parse_with(expected, parse_s_expr).unwrap();
+        assert_eq!(lmc555.child("symbol").unwrap(), &expected);
+    }
+
+    #[test]
+    fn test_merge_derived() {
+        let parent = r#"(symbol "NE555" (symbol "NE555_1_1" (rectangle)))"#;
+        let other = r#"(symbol "NE555" (symbol "NE555_1_1" (circle)))"#;
+        let child = r#"(symbol "ICM7555" (extends "NE555"))"#;
+        let inputs = vec![
+            ("a".to_owned(), library(20231120, parent)),
+            (
+                "b".to_owned(),
+                library(20231120, &format!("{child} {other}")),
+            ),
+            (
+                "c".to_owned(),
+                library(20231120, r#"(symbol "X" (extends "Y"))"#),
+            ),
+        ];
+        let merged = merge(&inputs, Policy::KeepBoth).unwrap();
+        assert_eq!(names(&merged.library), ["NE555_2", "ICM7555", "NE555", "X"]);
+        let symbols = group(symbols_in(&merged.library).unwrap());
+        assert_eq!(parent_in(&symbols["ICM7555"]).unwrap(), "NE555_2");
+        assert_eq!(merged.orphans, [("X".to_owned(), "Y".to_owned())]);
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt::Display;
+
+/// A location in source text.
+/// The offset is in bytes from the start of the text.
+/// Lines and columns are counted from 1, columns in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    pub offset: usize,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Span {
+    /// The location of a byte offset in the given text.
+    pub fn at(text: &str, offset: usize) -> Span {
+        Cursor::new(text).advance(offset)
+    }
+
+    /// The line of text containing this location, without its line ending.
+    pub fn line_in<'a>(&self, text: &'a str) -> &'a str {
+        let start = text[..self.offset.min(text.len())]
+            .rfind('\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let line = &text[start..];
+        line.split(['\r', '\n']).next().unwrap_or(line)
+    }
+
+    /// Show the line containing this location with a caret under the column.
+    pub fn snippet(&self, text: &str) -> String {
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let line = self.line_in(text);
+        let pad: String = line
+            .chars()
+            .take(self.column.saturating_sub(1) as usize)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!("{gutter} |\n{number} | {line}\n{gutter} | {pad}^")
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{}:{}", self.line, self.column))
+    }
+}
+
+/// Converts increasing byte offsets to spans in a single pass over the text.
+pub struct Cursor<'a> {
+    text: &'a str,
+    span: Span,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            span: Span {
+                offset: 0,
+                line: 1,
+                column: 1,
+            },
+        }
+    }
+
+    /// Move forward to the given offset and return its span.
+    /// Offsets behind the cursor are located by starting again.
+    pub fn advance(&mut self, offset: usize) -> Span {
+        if offset < self.span.offset {
+            *self = Cursor::new(self.text);
+        }
+        let offset = offset.min(self.text.len());
+        for c in self.text[self.span.offset..offset].chars() {
+            if c == '\n' {
+                self.span.line += 1;
+                self.span.column = 1;
+            } else {
+                self.span.column += 1;
+            }
+        }
+        self.span.offset = offset;
+        self.span
+    }
+}